@@ -0,0 +1,58 @@
+//! Measures the operations that need the sizes of many nodes: diffing two large documents and
+//! finding the positions of children.
+//!
+//! Run with `cargo bench --bench diff`.
+use prosemirror::markdown::helper::{blockquote, doc, li, p, ul};
+use prosemirror::markdown::MarkdownNode;
+use prosemirror::model::Node;
+use std::hint::black_box;
+use std::time::Instant;
+
+fn large_doc(changed: usize) -> MarkdownNode {
+    let sections = (0..500)
+        .map(|i| {
+            let text = if i == changed {
+                format!("Changed paragraph {}", i)
+            } else {
+                format!("Paragraph number {}", i)
+            };
+            blockquote(vec![
+                p(text.as_str()),
+                ul(vec![li(p(text.as_str())), li(p(text.as_str()))]),
+            ])
+        })
+        .collect::<Vec<_>>();
+    doc(sections)
+}
+
+fn measure<F: FnMut()>(name: &str, rounds: u32, mut f: F) {
+    f();
+    let start = Instant::now();
+    for _ in 0..rounds {
+        f();
+    }
+    println!("{:<32} {:>12?} per round", name, start.elapsed() / rounds);
+}
+
+fn main() {
+    let a = large_doc(usize::MAX);
+    let (content_a, size) = (a.content_or_empty(), a.content_size());
+
+    for &changed in &[0, 250, 499] {
+        let b = large_doc(changed);
+        let content_b = b.content_or_empty();
+        measure(&format!("find_diff_start (at {})", changed), 100, || {
+            black_box(content_a.find_diff_start(content_b));
+        });
+        measure(&format!("find_diff_end (at {})", changed), 100, || {
+            black_box(content_a.find_diff_end(content_b));
+        });
+    }
+
+    let rp = a.resolve(size / 2).unwrap();
+    measure("pos_at_index (all children)", 100, || {
+        for index in 0..=a.child_count() {
+            black_box(rp.pos_at_index(index, 0));
+        }
+    });
+}
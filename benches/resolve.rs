@@ -0,0 +1,55 @@
+//! Compares resolving positions with and without a `ResolveCache`.
+//!
+//! Run with `cargo bench --bench resolve`.
+use prosemirror::markdown::helper::{blockquote, doc, li, p, ul};
+use prosemirror::markdown::{MarkdownNode, MD};
+use prosemirror::model::{Node, ResolveCache};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn large_doc() -> MarkdownNode {
+    let sections = (0..200)
+        .map(|i| {
+            let text = format!("Paragraph number {}", i);
+            blockquote(vec![
+                p(text.as_str()),
+                ul(vec![li(p(text.as_str())), li(p(text.as_str()))]),
+            ])
+        })
+        .collect::<Vec<_>>();
+    doc(sections)
+}
+
+fn measure<F: FnMut()>(name: &str, rounds: u32, mut f: F) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..rounds {
+        f();
+    }
+    let elapsed = start.elapsed() / rounds;
+    println!("{:<24} {:>12?} per round", name, elapsed);
+    elapsed
+}
+
+fn main() {
+    let doc = large_doc();
+    let size = doc.content_size();
+    // Positions like the ones a batch of steps touches: close to each other, mostly increasing
+    let positions: Vec<usize> = (0..size).flat_map(|pos| vec![pos, pos / 2 * 2]).collect();
+
+    let uncached = measure("Node::resolve", 20, || {
+        for &pos in &positions {
+            black_box(doc.resolve(pos).unwrap());
+        }
+    });
+    let cached = measure("ResolveCache::resolve", 20, || {
+        let mut cache = ResolveCache::<MD>::new(&doc);
+        for &pos in &positions {
+            black_box(cache.resolve(pos).unwrap());
+        }
+    });
+    println!(
+        "speedup: {:.2}x",
+        uncached.as_secs_f64() / cached.as_secs_f64()
+    );
+}
@@ -0,0 +1,174 @@
+//! Measures applying steps to a large document.
+//!
+//! Run with `cargo bench --bench transform`. To compare against fragments that share their
+//! children, save a baseline first and compare the `rc` feature with it:
+//!
+//! ```text
+//! cargo bench --bench transform -- --save-baseline plain
+//! cargo bench --bench transform --features rc -- --baseline plain
+//! ```
+//!
+//! Every benchmark also prints how many allocations one iteration makes.
+use criterion::{criterion_group, criterion_main, Criterion};
+use prosemirror::markdown::helper::{blockquote, doc, li, p, ul};
+use prosemirror::markdown::{MarkdownMark, MarkdownNode, MD};
+use prosemirror::model::{Fragment, Node, Slice};
+use prosemirror::transform::{AddMarkStep, ReplaceStep, Span, StepKind};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the allocations, so that the benchmarks can report how many they made
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn large_doc() -> MarkdownNode {
+    let sections = (0..500)
+        .map(|i| {
+            let text = format!("Paragraph number {}", i);
+            blockquote(vec![
+                p(text.as_str()),
+                ul(vec![li(p(text.as_str())), li(p(text.as_str()))]),
+            ])
+        })
+        .collect::<Vec<_>>();
+    doc(sections)
+}
+
+fn measure<F: FnMut()>(c: &mut Criterion, name: &str, mut f: F) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!("{}: {} allocations per iteration", name, allocations);
+    c.bench_function(name, |b| b.iter(&mut f));
+}
+
+fn transform(c: &mut Criterion) {
+    let doc = large_doc();
+
+    measure(c, "clone document", || {
+        black_box(doc.clone());
+    });
+    // The start of the text in the first paragraph of every fifth section
+    let mut positions = Vec::new();
+    let mut section_start = 0;
+    for (i, section) in doc.content().unwrap().children().iter().enumerate() {
+        if i % 5 == 0 {
+            positions.push(section_start + 2);
+        }
+        section_start += section.node_size();
+    }
+
+    measure(c, "100 replace steps", || {
+        let mut doc = doc.clone();
+        // Going backwards, so the positions stay valid
+        for &pos in positions.iter().rev() {
+            let step = ReplaceStep::<MD> {
+                span: Span { from: pos, to: pos },
+                slice: Slice::new(Fragment::from((MarkdownNode::text("X"),)), 0, 0),
+                structure: false,
+            };
+            doc = step.apply(&doc).unwrap();
+        }
+        black_box(doc);
+    });
+    // The first word in the first paragraph of every section, once with each mark
+    let mut spans = Vec::new();
+    let mut section_start = 0;
+    for section in doc.content().unwrap().children() {
+        let from = section_start + 2;
+        spans.push(Span { from, to: from + 9 });
+        section_start += section.node_size();
+    }
+
+    measure(c, "1000 add mark steps", || {
+        let mut doc = doc.clone();
+        for mark in &[MarkdownMark::strong(), MarkdownMark::em()] {
+            for span in &spans {
+                let step = AddMarkStep::<MD> {
+                    span: *span,
+                    mark: mark.clone(),
+                };
+                doc = step.apply(&doc).unwrap();
+            }
+        }
+        black_box(doc);
+    });
+
+    // Typing at the end of the first paragraph of the middle section
+    let mut end = 0;
+    for section in doc.content().unwrap().children().iter().take(250) {
+        end += section.node_size();
+    }
+    end += 2 + "Paragraph number 250".len();
+
+    measure(c, "1000 typed characters", || {
+        let mut doc = doc.clone();
+        for i in 0..1000 {
+            let step = ReplaceStep::<MD> {
+                span: Span {
+                    from: end + i,
+                    to: end + i,
+                },
+                slice: Slice::new(Fragment::from((MarkdownNode::text("x"),)), 0, 0),
+                structure: false,
+            };
+            doc = step.apply(&doc).unwrap();
+        }
+        black_box(doc);
+    });
+
+    // The mark step builds a slice of the whole document, and replaces the document with it
+    let size = doc.content_size();
+    measure(c, "add mark to whole document", || {
+        let step = AddMarkStep::<MD> {
+            span: Span { from: 0, to: size },
+            mark: MarkdownMark::em(),
+        };
+        black_box(step.apply(&doc).unwrap());
+    });
+
+    let pasted: Vec<MarkdownNode> = (0..10_000).map(|i| p(format!("{}", i).as_str())).collect();
+    let pasted = Slice::new(Fragment::from(pasted), 0, 0);
+    measure(c, "paste 10k nodes", || {
+        black_box(doc.replace(0..0, &pasted).unwrap());
+    });
+    // A slice that is owned by the caller, e.g. fresh from the clipboard, can be moved into the
+    // document instead of being cloned. Both rounds include cloning the slice first.
+    measure(c, "paste 10k nodes (cloned, borrowed)", || {
+        let pasted = pasted.clone();
+        black_box(doc.replace(0..0, &pasted).unwrap());
+    });
+    measure(c, "paste 10k nodes (cloned, owned)", || {
+        let pasted = pasted.clone();
+        black_box(doc.replace_cow(0..0, Cow::Owned(pasted)).unwrap());
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = transform
+}
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+fn default_level() -> u8 {
+    1
+}
+
+/// Attributes for a heading (i.e. `<h1>`, `<h2>`, ...)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HeadingAttrs {
+    /// The level of the heading (i.e. `1` for `<h1>`)
+    #[serde(default = "default_level")]
+    pub level: u8,
+}
+
+impl Default for HeadingAttrs {
+    fn default() -> Self {
+        Self { level: 1 }
+    }
+}
+
+/// Attributes for an image
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ImageAttrs {
+    /// Source URL
+    pub src: String,
+    /// Alternative Text (Accessibility)
+    #[serde(default)]
+    pub alt: Option<String>,
+    /// Title (Tooltip)
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// The attributes for a hyperlink
+#[derive(Debug, Hash, Eq, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LinkAttrs {
+    /// The URL the link points to
+    pub href: String,
+    /// The title of the link
+    #[serde(default)]
+    pub title: Option<String>,
+}
@@ -0,0 +1,77 @@
+use crate::{Basic, BasicNodeType};
+use prosemirror::model::{ContentMatch, Fragment, Node, NodeType};
+use std::ops::{Bound, RangeBounds};
+
+/// The content match type for the basic schema
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BasicContentMatch {
+    /// `inline*`
+    InlineStar,
+    /// `block+`
+    BlockPlus,
+    /// `block*`
+    BlockStar,
+    /// `text*`
+    TextStar,
+    /// empty
+    Empty,
+}
+
+impl ContentMatch<Basic> for BasicContentMatch {
+    fn match_type(self, r#type: BasicNodeType) -> Option<Self> {
+        match self {
+            Self::InlineStar if r#type.is_inline() => Some(Self::InlineStar),
+            Self::BlockPlus | Self::BlockStar if r#type.is_block() => Some(Self::BlockStar),
+            Self::TextStar if r#type == BasicNodeType::Text => Some(Self::TextStar),
+            _ => None,
+        }
+    }
+
+    fn match_fragment_range<R: RangeBounds<usize>>(
+        self,
+        fragment: &Fragment<Basic>,
+        range: R,
+    ) -> Option<Self> {
+        let start = match range.start_bound() {
+            Bound::Unbounded => 0,
+            Bound::Included(x) => *x,
+            Bound::Excluded(x) => x + 1,
+        };
+        let end = match range.end_bound() {
+            Bound::Unbounded => fragment.child_count(),
+            Bound::Included(x) => x + 1,
+            Bound::Excluded(x) => *x,
+        };
+
+        fragment.children()[start..end]
+            .iter()
+            .try_fold(self, |m, child| m.match_type(child.r#type()))
+    }
+
+    fn valid_end(self) -> bool {
+        !matches!(self, Self::BlockPlus)
+    }
+
+    fn inline_content(self) -> bool {
+        matches!(self, Self::InlineStar | Self::TextStar)
+    }
+
+    fn default_type(self) -> Option<BasicNodeType> {
+        match self {
+            Self::BlockPlus | Self::BlockStar => Some(BasicNodeType::Paragraph),
+            // Inline content is text, images (with a required `src`) and hard breaks
+            Self::InlineStar => Some(BasicNodeType::HardBreak),
+            Self::TextStar | Self::Empty => None,
+        }
+    }
+}
+
+impl BasicContentMatch {
+    pub(crate) fn compatible(self, other: Self) -> bool {
+        match self {
+            Self::InlineStar | Self::TextStar => matches!(other, Self::InlineStar | Self::TextStar),
+            Self::BlockPlus | Self::BlockStar => matches!(other, Self::BlockPlus | Self::BlockStar),
+            Self::Empty => false,
+        }
+    }
+}
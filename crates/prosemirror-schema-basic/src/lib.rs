@@ -0,0 +1,330 @@
+#![warn(missing_docs)]
+//! # The basic schema
+//!
+//! This crate is a port of [`prosemirror-schema-basic`](https://github.com/prosemirror/prosemirror-schema-basic),
+//! a schema that corresponds to the document elements of a simple HTML document.
+//!
+//! It only uses the public API of the `prosemirror` crate, and so also serves as an example of
+//! how to define a custom schema.
+mod attrs;
+mod content;
+mod schema;
+
+pub use attrs::{HeadingAttrs, ImageAttrs, LinkAttrs};
+pub use content::BasicContentMatch;
+pub use schema::{Basic, BasicNodeType};
+
+use derivative::Derivative;
+use prosemirror::model::{
+    AttrNode, Block, Fragment, Leaf, Mark, MarkSet, MarkType, Node, Text, TextNode,
+};
+use serde::{Deserialize, Serialize};
+
+/// The node type for the basic schema
+#[derive(Debug, Derivative, Deserialize, Serialize, PartialEq, Eq)]
+#[derivative(Clone(bound = ""))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BasicNode {
+    /// The document root
+    Doc(Block<Basic>),
+    /// A paragraph `<p>`
+    Paragraph(Block<Basic>),
+    /// A blockquote `<blockquote>`
+    Blockquote(Block<Basic>),
+    /// A horizontal line `<hr>`
+    HorizontalRule,
+    /// A heading, e.g. `<h1>`
+    Heading(AttrNode<Basic, HeadingAttrs>),
+    /// A code block `<pre><code>`
+    CodeBlock(Block<Basic>),
+    /// A text node
+    Text(TextNode<Basic>),
+    /// An image `<img>`
+    Image(Leaf<ImageAttrs>),
+    /// A hard break `<br>`
+    HardBreak,
+}
+
+impl From<TextNode<Basic>> for BasicNode {
+    fn from(text_node: TextNode<Basic>) -> Self {
+        Self::Text(text_node)
+    }
+}
+
+impl From<&str> for BasicNode {
+    fn from(text: &str) -> Self {
+        Self::text(text)
+    }
+}
+
+impl Node<Basic> for BasicNode {
+    fn text_node(&self) -> Option<&TextNode<Basic>> {
+        if let Self::Text(node) = self {
+            Some(node)
+        } else {
+            None
+        }
+    }
+
+    fn new_text_node(node: TextNode<Basic>) -> Self {
+        Self::Text(node)
+    }
+
+    fn is_block(&self) -> bool {
+        !matches!(self, Self::Text(_) | Self::Image(_) | Self::HardBreak)
+    }
+
+    fn r#type(&self) -> BasicNodeType {
+        match self {
+            Self::Doc(_) => BasicNodeType::Doc,
+            Self::Paragraph(_) => BasicNodeType::Paragraph,
+            Self::Blockquote(_) => BasicNodeType::Blockquote,
+            Self::HorizontalRule => BasicNodeType::HorizontalRule,
+            Self::Heading(_) => BasicNodeType::Heading,
+            Self::CodeBlock(_) => BasicNodeType::CodeBlock,
+            Self::Text(_) => BasicNodeType::Text,
+            Self::Image(_) => BasicNodeType::Image,
+            Self::HardBreak => BasicNodeType::HardBreak,
+        }
+    }
+
+    fn text<A: Into<String>>(text: A) -> Self {
+        Self::Text(TextNode {
+            text: Text::from(text.into()),
+            marks: MarkSet::default(),
+        })
+    }
+
+    fn content(&self) -> Option<&Fragment<Basic>> {
+        match self {
+            Self::Doc(Block { content }) => Some(content),
+            Self::Paragraph(Block { content }) => Some(content),
+            Self::Blockquote(Block { content }) => Some(content),
+            Self::Heading(AttrNode { content, .. }) => Some(content),
+            Self::CodeBlock(Block { content }) => Some(content),
+            Self::HorizontalRule | Self::Text(_) | Self::Image(_) | Self::HardBreak => None,
+        }
+    }
+
+    fn marks(&self) -> Option<&MarkSet<Basic>> {
+        self.text_node().map(|node| &node.marks)
+    }
+
+    fn mark(&self, marks: MarkSet<Basic>) -> Self {
+        if let Some(text_node) = self.text_node() {
+            Self::Text(TextNode {
+                marks,
+                text: text_node.text.clone(),
+            })
+        } else {
+            self.clone()
+        }
+    }
+
+    fn copy<F>(&self, map: F) -> Self
+    where
+        F: FnOnce(&Fragment<Basic>) -> Fragment<Basic>,
+    {
+        match self {
+            Self::Doc(block) => Self::Doc(block.copy(map)),
+            Self::Paragraph(block) => Self::Paragraph(block.copy(map)),
+            Self::Blockquote(block) => Self::Blockquote(block.copy(map)),
+            Self::Heading(node) => Self::Heading(node.copy(map)),
+            Self::CodeBlock(block) => Self::CodeBlock(block.copy(map)),
+            Self::HorizontalRule | Self::Text(_) | Self::Image(_) | Self::HardBreak => self.clone(),
+        }
+    }
+}
+
+/// The marks that can be on some span
+#[derive(Debug, Hash, Eq, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BasicMark {
+    /// hyper-linked
+    Link {
+        /// The attributes
+        attrs: LinkAttrs,
+    },
+    /// italics
+    Em,
+    /// bold
+    Strong,
+    /// monospace
+    Code,
+}
+
+impl Mark<Basic> for BasicMark {
+    fn r#type(&self) -> BasicMarkType {
+        match self {
+            Self::Link { .. } => BasicMarkType::Link,
+            Self::Em => BasicMarkType::Em,
+            Self::Strong => BasicMarkType::Strong,
+            Self::Code => BasicMarkType::Code,
+        }
+    }
+}
+
+/// The type of a basic mark.
+///
+/// The order of the variants is the order in which marks are sorted in a set.
+#[derive(Debug, Hash, Eq, Copy, Clone, PartialEq, PartialOrd, Ord)]
+pub enum BasicMarkType {
+    /// hyper-linked
+    Link,
+    /// italics
+    Em,
+    /// bold
+    Strong,
+    /// monospace
+    Code,
+}
+
+impl MarkType for BasicMarkType {
+    fn inclusive(self) -> bool {
+        !matches!(self, Self::Link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Basic, BasicMark, BasicNode, BasicNodeType, HeadingAttrs};
+    use prosemirror::model::{
+        AttrNode, Block, Fragment, Mark, Node, NodeType, ReplaceError, Schema, Slice, TextNode,
+    };
+    use prosemirror::transform::{AddMarkStep, ReplaceStep, Span, Step, StepError, StepKind};
+
+    fn doc(content: Vec<BasicNode>) -> BasicNode {
+        BasicNode::Doc(Block {
+            content: Fragment::from(content),
+        })
+    }
+
+    fn p(content: Vec<BasicNode>) -> BasicNode {
+        BasicNode::Paragraph(Block {
+            content: Fragment::from(content),
+        })
+    }
+
+    fn h(level: u8, content: Vec<BasicNode>) -> BasicNode {
+        BasicNode::Heading(AttrNode {
+            attrs: HeadingAttrs { level },
+            content: Fragment::from(content),
+        })
+    }
+
+    fn pre(content: Vec<BasicNode>) -> BasicNode {
+        BasicNode::CodeBlock(Block {
+            content: Fragment::from(content),
+        })
+    }
+
+    fn txt(text: &str) -> BasicNode {
+        BasicNode::text(text)
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let node: BasicNode = serde_json::from_str(
+            r#"{"type":"doc","content":[
+                {"type":"heading","attrs":{"level":2},"content":[{"type":"text","text":"Title"}]},
+                {"type":"paragraph","content":[
+                    {"type":"text","text":"Hello","marks":[{"type":"strong"},{"type":"em"}]},
+                    {"type":"hard_break"},
+                    {"type":"image","attrs":{"src":"a.png"}}
+                ]},
+                {"type":"horizontal_rule"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(node.child_count(), 3);
+        assert_eq!(node.child(0).unwrap(), &h(2, vec![txt("Title")]));
+        let marks: Vec<_> = node
+            .child(1)
+            .unwrap()
+            .child(0)
+            .unwrap()
+            .marks()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(marks, vec![&BasicMark::Em, &BasicMark::Strong]);
+        assert_eq!(node.node_size(), 19);
+    }
+
+    #[test]
+    fn test_replace_step() {
+        let d1 = doc(vec![p(vec![txt("one")]), p(vec![txt("two")])]);
+        let step = ReplaceStep::<Basic> {
+            span: Span { from: 3, to: 7 },
+            slice: Slice::default(),
+            structure: false,
+        };
+        assert_eq!(step.apply(&d1).unwrap(), doc(vec![p(vec![txt("onwo")])]));
+
+        let insert = Slice::new(Fragment::from(vec![txt("X")]), 0, 0);
+        let step = ReplaceStep::<Basic>::between(&d1, 6, 6, insert).unwrap();
+        assert_eq!(
+            Step::Replace(step).apply(&d1).unwrap(),
+            doc(vec![p(vec![txt("one")]), p(vec![txt("Xtwo")])])
+        );
+
+        let slice = d1.slice(3..7, false).unwrap();
+        let d2 = doc(vec![h(1, vec![txt("foobar")])]);
+        let step = ReplaceStep::<Basic>::between(&d2, 4, 4, slice).unwrap();
+        assert_eq!(
+            step.apply(&d2).unwrap(),
+            doc(vec![h(1, vec![txt("fooe")]), p(vec![txt("tbar")])])
+        );
+    }
+
+    #[test]
+    fn test_replace_step_rejects_bad_fit() {
+        let d1 = doc(vec![p(vec![txt("one")])]);
+        let slice = Slice::new(Fragment::from(vec![txt("X")]), 0, 0);
+        let result = ReplaceStep::<Basic>::between(&d1, 0, 0, slice);
+        assert!(matches!(
+            result,
+            Err(StepError::Replace(ReplaceError::InvalidContent(
+                BasicNodeType::Doc
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_code_block_disallows_marks() {
+        let d1 = doc(vec![pre(vec![txt("code")]), p(vec![txt("text")])]);
+        let step = AddMarkStep::<Basic> {
+            span: Span { from: 0, to: 12 },
+            mark: BasicMark::Strong,
+        };
+        let strong = BasicNode::Text(TextNode {
+            text: "text".to_owned().into(),
+            marks: BasicMark::Strong.into_set(),
+        });
+        assert_eq!(
+            step.apply(&d1).unwrap(),
+            doc(vec![pre(vec![txt("code")]), p(vec![strong])])
+        );
+    }
+
+    #[test]
+    fn test_allowed_child_types() {
+        use BasicNodeType::*;
+        let blocks = [Paragraph, Blockquote, HorizontalRule, Heading, CodeBlock];
+        assert_eq!(Doc.allowed_child_types(), blocks);
+        assert_eq!(Paragraph.allowed_child_types(), [Text, Image, HardBreak]);
+        assert_eq!(CodeBlock.allowed_child_types(), [Text]);
+    }
+
+    #[test]
+    fn test_node_type_defaults() {
+        use BasicNodeType::*;
+        let textblocks = [Paragraph, Heading, CodeBlock];
+        let leaves = [HorizontalRule, Text, Image, HardBreak];
+        for &r#type in Basic::node_types() {
+            assert_eq!(r#type.is_textblock(), textblocks.contains(&r#type));
+            assert_eq!(r#type.is_leaf(), leaves.contains(&r#type));
+        }
+    }
+}
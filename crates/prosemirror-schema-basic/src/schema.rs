@@ -0,0 +1,152 @@
+use crate::{BasicContentMatch, BasicMark, BasicMarkType, BasicNode, HeadingAttrs};
+use prosemirror::model::{AttrNode, Block, ContentMatch, Fragment, Node, NodeType, Schema};
+
+/// The basic schema type
+pub struct Basic;
+
+impl Schema for Basic {
+    type Node = BasicNode;
+    type Mark = BasicMark;
+    type MarkType = BasicMarkType;
+    type NodeType = BasicNodeType;
+    type ContentMatch = BasicContentMatch;
+
+    fn node_types() -> &'static [BasicNodeType] {
+        &[
+            BasicNodeType::Doc,
+            BasicNodeType::Paragraph,
+            BasicNodeType::Blockquote,
+            BasicNodeType::HorizontalRule,
+            BasicNodeType::Heading,
+            BasicNodeType::CodeBlock,
+            BasicNodeType::Text,
+            BasicNodeType::Image,
+            BasicNodeType::HardBreak,
+        ]
+    }
+
+    fn empty_doc() -> BasicNode {
+        BasicNode::Doc(Block {
+            content: Fragment::from(vec![BasicNode::Paragraph(Block {
+                content: Fragment::new(),
+            })]),
+        })
+    }
+}
+
+/// The node-spec type for the basic schema
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BasicNodeType {
+    /// The document root
+    Doc,
+    /// A paragraph
+    Paragraph,
+    /// A blockquote
+    Blockquote,
+    /// A horizontal line `<hr>`
+    HorizontalRule,
+    /// A heading, e.g. `<h1>`
+    Heading,
+    /// A code block
+    CodeBlock,
+    /// A text node
+    Text,
+    /// An image `<img>`
+    Image,
+    /// A hard break `<br>`
+    HardBreak,
+}
+
+impl BasicNodeType {
+    fn _allow_marks(self) -> bool {
+        match self {
+            Self::Doc | Self::Blockquote | Self::HorizontalRule => false, // block && !textblock
+
+            Self::CodeBlock => false, // marks = ""
+
+            Self::Heading | Self::Paragraph => true, // textblock
+
+            Self::Text | Self::Image | Self::HardBreak => true, // inline
+        }
+    }
+}
+
+impl NodeType<Basic> for BasicNodeType {
+    fn allows_mark_type(self, _mark_type: BasicMarkType) -> bool {
+        self._allow_marks()
+    }
+
+    fn is_inline(self) -> bool {
+        matches!(self, Self::Text | Self::Image | Self::HardBreak)
+    }
+
+    fn is_block(self) -> bool {
+        matches!(
+            self,
+            Self::Paragraph
+                | Self::Blockquote
+                | Self::HorizontalRule
+                | Self::Heading
+                | Self::CodeBlock
+        )
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Doc => "doc",
+            Self::Paragraph => "paragraph",
+            Self::Blockquote => "blockquote",
+            Self::HorizontalRule => "horizontal_rule",
+            Self::Heading => "heading",
+            Self::CodeBlock => "code_block",
+            Self::Text => "text",
+            Self::Image => "image",
+            Self::HardBreak => "hard_break",
+        }
+    }
+
+    fn content_match(self) -> BasicContentMatch {
+        match self {
+            Self::Doc => BasicContentMatch::BlockPlus,
+            Self::Paragraph => BasicContentMatch::InlineStar,
+            Self::Blockquote => BasicContentMatch::BlockPlus,
+            Self::HorizontalRule => BasicContentMatch::Empty,
+            Self::Heading => BasicContentMatch::InlineStar,
+            Self::CodeBlock => BasicContentMatch::TextStar,
+            Self::Text => BasicContentMatch::Empty,
+            Self::Image => BasicContentMatch::Empty,
+            Self::HardBreak => BasicContentMatch::Empty,
+        }
+    }
+
+    fn create(self, content: Fragment<Basic>) -> Option<BasicNode> {
+        match self {
+            Self::Doc => Some(BasicNode::Doc(Block { content })),
+            Self::Paragraph => Some(BasicNode::Paragraph(Block { content })),
+            Self::Blockquote => Some(BasicNode::Blockquote(Block { content })),
+            Self::HorizontalRule => Some(BasicNode::HorizontalRule),
+            Self::Heading => Some(BasicNode::Heading(AttrNode {
+                attrs: HeadingAttrs::default(),
+                content,
+            })),
+            Self::CodeBlock => Some(BasicNode::CodeBlock(Block { content })),
+            Self::HardBreak => Some(BasicNode::HardBreak),
+            Self::Text | Self::Image => None,
+        }
+    }
+
+    fn compatible_content(self, other: Self) -> bool {
+        self == other || self.content_match().compatible(other.content_match())
+    }
+
+    fn valid_content(self, fragment: &Fragment<Basic>) -> bool {
+        match self.content_match().match_fragment(fragment) {
+            Some(m) if m.valid_end() => fragment
+                .children()
+                .iter()
+                .filter_map(Node::marks)
+                .all(|m| self.allow_marks(m)),
+            _ => false,
+        }
+    }
+}
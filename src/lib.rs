@@ -11,5 +11,6 @@ extern crate derive_new;
 pub(crate) mod de;
 pub mod markdown;
 pub mod model;
+pub mod selection;
 pub mod transform;
 pub mod util;
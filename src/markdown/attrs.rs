@@ -1,11 +1,158 @@
 use crate::de;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+
+/// A free-form map of attributes, for the document root and the strong, em and code marks
+///
+/// The markdown schema doesn't define any attributes on these, but some applications attach
+/// their own (e.g. a `version` on the document, or a `color` on strong text). These are kept
+/// as they are, so they survive a round-trip. Marks leave them out of the JSON when empty.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(
+    from = "Option<BTreeMap<String, Value>>",
+    into = "BTreeMap<String, Value>"
+)]
+pub struct AttrMap {
+    /// The attributes by name
+    pub attrs: BTreeMap<String, Value>,
+}
+
+impl AttrMap {
+    /// True if there are no attributes
+    pub fn is_empty(&self) -> bool {
+        self.attrs.is_empty()
+    }
+}
+
+impl Hash for AttrMap {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.attrs.len().hash(state);
+        for (key, value) in &self.attrs {
+            key.hash(state);
+            hash_value(value, state);
+        }
+    }
+}
+
+/// Hashes a JSON value, such that equal values have the same hash
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    match value {
+        Value::Null => 0u8.hash(state),
+        Value::Bool(b) => {
+            1u8.hash(state);
+            b.hash(state);
+        }
+        Value::Number(n) => {
+            2u8.hash(state);
+            if let Some(n) = n.as_u64() {
+                n.hash(state);
+            } else if let Some(n) = n.as_i64() {
+                n.hash(state);
+            } else if let Some(n) = n.as_f64() {
+                // `0.0 == -0.0`, but their bits differ
+                let n = if n == 0.0 { 0.0 } else { n };
+                n.to_bits().hash(state);
+            }
+        }
+        Value::String(s) => {
+            3u8.hash(state);
+            s.hash(state);
+        }
+        Value::Array(values) => {
+            4u8.hash(state);
+            values.len().hash(state);
+            for value in values {
+                hash_value(value, state);
+            }
+        }
+        Value::Object(map) => {
+            5u8.hash(state);
+            map.len().hash(state);
+            // With `serde_json/preserve_order`, equal maps may iterate in a different order
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                key.hash(state);
+                hash_value(value, state);
+            }
+        }
+    }
+}
+
+impl From<Option<BTreeMap<String, Value>>> for AttrMap {
+    fn from(attrs: Option<BTreeMap<String, Value>>) -> Self {
+        Self {
+            attrs: attrs.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<AttrMap> for BTreeMap<String, Value> {
+    fn from(attrs: AttrMap) -> Self {
+        attrs.attrs
+    }
+}
 
 /// Attributes for a heading (i.e. `<h1>`, `<h2>`, ...)
+///
+/// Deserializing fails if the level is not between 1 and 6.
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(try_from = "HeadingAttrsDef")]
 pub struct HeadingAttrs {
     /// The level of the heading (i.e. `1` for `<h1>`)
     pub level: u8,
+    /// Unknown attributes, see the [module docs](crate::markdown#unknown-attributes)
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    pub extra: Map<String, Value>,
+}
+
+impl HeadingAttrs {
+    /// The attributes for a heading of the given level, or `None` if the level is not between
+    /// 1 and 6.
+    pub fn new(level: u8) -> Option<Self> {
+        if (1..=6).contains(&level) {
+            Some(Self {
+                level,
+                extra: Map::new(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct HeadingAttrsDef {
+    level: u8,
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    extra: Map<String, Value>,
+}
+
+impl TryFrom<HeadingAttrsDef> for HeadingAttrs {
+    type Error = &'static str;
+
+    fn try_from(attrs: HeadingAttrsDef) -> Result<Self, Self::Error> {
+        let mut heading = Self::new(attrs.level).ok_or("heading level must be between 1 and 6")?;
+        heading.extra = attrs.extra;
+        Ok(heading)
+    }
+}
+
+/// Attributes for a directive container (i.e. `:::success`)
+#[cfg(feature = "directives")]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ContainerAttrs {
+    /// The name after the opening fence, e.g. `success`
+    pub kind: String,
+    /// Unknown attributes, see the [module docs](crate::markdown#unknown-attributes)
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    pub extra: Map<String, Value>,
 }
 
 /// Attributes for a code block
@@ -13,6 +160,10 @@ pub struct HeadingAttrs {
 pub struct CodeBlockAttrs {
     /// ???
     pub params: String,
+    /// Unknown attributes, see the [module docs](crate::markdown#unknown-attributes)
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    pub extra: Map<String, Value>,
 }
 
 /// Attributes for a bullet list
@@ -20,15 +171,75 @@ pub struct CodeBlockAttrs {
 pub struct BulletListAttrs {
     /// ???
     pub tight: bool,
+    /// Unknown attributes, see the [module docs](crate::markdown#unknown-attributes)
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    pub extra: Map<String, Value>,
+}
+
+/// Attributes for a list item
+///
+/// The attributes may be missing in JSON, which is the same as not being a task list item. They
+/// are left out again when serializing a plain list item.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(from = "Option<ListItemAttrsDef>")]
+pub struct ListItemAttrs {
+    /// Whether a task list item is checked, or `None` if this isn't a task list item
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked: Option<bool>,
+    /// Unknown attributes, see the [module docs](crate::markdown#unknown-attributes)
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct ListItemAttrsDef {
+    #[serde(default)]
+    checked: Option<bool>,
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    extra: Map<String, Value>,
+}
+
+impl From<Option<ListItemAttrsDef>> for ListItemAttrs {
+    fn from(attrs: Option<ListItemAttrsDef>) -> Self {
+        match attrs {
+            Some(attrs) => Self {
+                checked: attrs.checked,
+                extra: attrs.extra,
+            },
+            None => Self::default(),
+        }
+    }
 }
 
 /// Attributes for an ordered list
+///
+/// When deserializing, a missing, `null` or `0` order is read as 1, like in prosemirror-markdown.
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct OrderedListAttrs {
     /// Initial value
+    #[serde(default = "default_order", deserialize_with = "deserialize_order")]
     pub order: usize,
     /// ???
+    #[serde(default)]
     pub tight: bool,
+    /// Unknown attributes, see the [module docs](crate::markdown#unknown-attributes)
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    pub extra: Map<String, Value>,
+}
+
+fn default_order() -> usize {
+    1
+}
+
+fn deserialize_order<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let order: Option<usize> = Deserialize::deserialize(deserializer)?;
+    Ok(order
+        .filter(|&order| order > 0)
+        .unwrap_or_else(default_order))
 }
 
 /// Attributes for an image
@@ -42,14 +253,89 @@ pub struct ImageAttrs {
     /// Title (Tooltip)
     #[serde(default, deserialize_with = "de::deserialize_or_default")]
     pub title: String,
+    /// Unknown attributes, see the [module docs](crate::markdown#unknown-attributes)
+    #[cfg_attr(feature = "preserve-unknown-attrs", serde(flatten))]
+    #[cfg_attr(not(feature = "preserve-unknown-attrs"), serde(skip))]
+    pub extra: Map<String, Value>,
+}
+
+/// How a link is written in markdown
+#[derive(Debug, Hash, Eq, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkdownLinkType {
+    /// `[text](href)`, or a reference to a link definition
+    Inline,
+    /// `<https://example.com>`
+    Autolink,
+    /// `<me@example.com>`, whose `href` starts with `mailto:`
+    Email,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for MarkdownLinkType {
+    fn default() -> Self {
+        Self::Inline
+    }
+}
+
+impl MarkdownLinkType {
+    fn is_inline(&self) -> bool {
+        *self == Self::Inline
+    }
 }
 
 /// The attributes for a hyperlink
+///
+/// Deserializing fails if `href` is missing or `null`, but an empty `href` is allowed.
 #[derive(Debug, Hash, Eq, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "LinkAttrsDef")]
 pub struct LinkAttrs {
     /// The URL the link points to
     pub href: String,
     /// The title of the link
-    #[serde(default, deserialize_with = "de::deserialize_or_default")]
     pub title: String,
+    /// How the link was written in markdown, only serialized if it isn't an inline link
+    #[serde(
+        rename = "linkType",
+        skip_serializing_if = "MarkdownLinkType::is_inline"
+    )]
+    pub link_type: MarkdownLinkType,
+}
+
+impl LinkAttrs {
+    /// The attributes for an inline link to `href` with the given title
+    pub fn new(href: String, title: String) -> Self {
+        Self {
+            href,
+            title,
+            link_type: MarkdownLinkType::Inline,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LinkAttrsDef {
+    #[serde(default)]
+    href: Option<String>,
+    #[serde(default, deserialize_with = "de::deserialize_or_default")]
+    title: String,
+    #[serde(
+        default,
+        rename = "linkType",
+        deserialize_with = "de::deserialize_or_default"
+    )]
+    link_type: MarkdownLinkType,
+}
+
+impl TryFrom<LinkAttrsDef> for LinkAttrs {
+    type Error = &'static str;
+
+    fn try_from(attrs: LinkAttrsDef) -> Result<Self, Self::Error> {
+        let href = attrs.href.ok_or("link is missing the `href` attribute")?;
+        Ok(Self {
+            href,
+            title: attrs.title,
+            link_type: attrs.link_type,
+        })
+    }
 }
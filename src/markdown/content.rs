@@ -48,6 +48,13 @@ impl ContentMatch<MD> for MarkdownContentMatch {
         }
     }
 
+    fn inline_content(self) -> bool {
+        matches!(
+            self,
+            Self::InlineStar | Self::OrTextImageStar | Self::TextStar
+        )
+    }
+
     fn match_fragment_range<R: RangeBounds<usize>>(
         self,
         fragment: &Fragment<MD>,
@@ -70,6 +77,18 @@ impl ContentMatch<MD> for MarkdownContentMatch {
         Some(test)
     }
 
+    fn default_type(self) -> Option<MarkdownNodeType> {
+        match self {
+            Self::BlockPlus | Self::BlockStar | Self::ParagraphBlockStar => {
+                Some(MarkdownNodeType::Paragraph)
+            }
+            Self::ListItemPlus | Self::ListItemStar => Some(MarkdownNodeType::ListItem),
+            // Inline content is text, images (with a required `src`) and hard breaks
+            Self::InlineStar => Some(MarkdownNodeType::HardBreak),
+            Self::OrTextImageStar | Self::TextStar | Self::Empty => None,
+        }
+    }
+
     fn valid_end(self) -> bool {
         matches!(
             self,
@@ -1,13 +1,19 @@
+#[cfg(feature = "directives")]
+use super::helper;
 use super::{
-    BulletListAttrs, CodeBlockAttrs, HeadingAttrs, ImageAttrs, LinkAttrs, MarkdownMark,
-    MarkdownNode, OrderedListAttrs, MD,
+    AttrMap, BulletListAttrs, CodeBlockAttrs, HeadingAttrs, ImageAttrs, LinkAttrs, ListItemAttrs,
+    MarkdownLinkType, MarkdownMark, MarkdownNode, OrderedListAttrs, MD,
+};
+use crate::model::{
+    AttrNode, Block, Fragment, Leaf, MarkSet, Node, Text, TextNode, DEFAULT_MAX_DEPTH,
 };
-use crate::model::{AttrNode, Block, Fragment, Leaf, MarkSet, Text, TextNode};
 use displaydoc::Display;
-use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag};
 use std::{
     convert::{TryFrom, TryInto},
+    io::{self, Read},
     num::TryFromIntError,
+    ops::Range,
 };
 use thiserror::Error;
 
@@ -20,10 +26,23 @@ pub enum FromMarkdownError {
     NotSupported(&'static str),
     /// The stack was empty
     StackEmpty,
-    /// Event mismatch
-    MisplacedEndTag(&'static str, Attrs),
+    /// Found the end of {found}, but the innermost open node is {expected:?} ({depth} open nodes)
+    MisplacedEndTag {
+        /// The end tag that was found
+        found: &'static str,
+        /// The innermost open node, whose end was expected
+        expected: Box<Attrs>,
+        /// The number of open nodes, including the document
+        depth: usize,
+    },
+    /// Nodes are nested deeper than the limit of {0}
+    DepthExceeded(usize),
+    /// Reached the end of the input with unclosed nodes: {0:?}
+    Unclosed(Vec<Attrs>),
     /// No children allowed in {0:?}
     NoChildrenAllowed(&'static str),
+    /// Failed to read the input: {0:?}
+    Read(io::ErrorKind),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -35,24 +54,323 @@ pub enum Attrs {
     CodeBlock(CodeBlockAttrs),
     OrderedList(OrderedListAttrs),
     BulletList(BulletListAttrs),
-    ListItem,
+    ListItem(ListItemAttrs),
     Image(ImageAttrs),
+    Custom(MarkdownNode),
+}
+
+/// What to do with markdown constructs that the schema has no nodes or marks for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnsupportedPolicy {
+    /// Fail with [`FromMarkdownError::NotSupported`]. Tables, footnotes and strikethrough are
+    /// not parsed at all with this policy, so their source ends up in the text, like before
+    /// these policies existed.
+    Error,
+    /// Leave them out of the document
+    Skip,
+    /// Insert their markdown source as text
+    AsText,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for UnsupportedPolicy {
+    fn default() -> Self {
+        Self::Error
+    }
+}
+
+/// How to represent a line break within a paragraph, i.e. a soft break
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftBreakMode {
+    /// A space in the text, like a browser displays it
+    Space,
+    /// A [`MarkdownNode::HardBreak`]
+    HardBreak,
+    /// A newline in the text
+    Newline,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for SoftBreakMode {
+    fn default() -> Self {
+        Self::Space
+    }
+}
+
+/// Options for [`from_markdown_with_options`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FromMarkdownOptions {
+    /// What to do with tables, footnotes, strikethrough and HTML (default: error)
+    pub on_unsupported: UnsupportedPolicy,
+    /// The maximum number of nested nodes, e.g. lists and list items, counting the document
+    /// itself (default: [`DEFAULT_MAX_DEPTH`])
+    ///
+    /// Deeper documents fail with [`FromMarkdownError::DepthExceeded`], because processing
+    /// them recursively could overflow the stack.
+    pub max_depth: usize,
+    /// How to represent line breaks within a paragraph (default: as a space)
+    pub soft_break: SoftBreakMode,
+}
+
+impl Default for FromMarkdownOptions {
+    fn default() -> Self {
+        Self {
+            on_unsupported: UnsupportedPolicy::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            soft_break: SoftBreakMode::default(),
+        }
+    }
 }
 
 /// Creates a MarkdownNode::Doc from a text
 pub fn from_markdown(text: &str) -> Result<MarkdownNode, FromMarkdownError> {
-    let parser = Parser::new(text);
-    let mut d = MarkdownDeserializer::default();
-    d.deserialize(parser)
+    from_markdown_with_options(text, &FromMarkdownOptions::default())
 }
 
-#[derive(Default)]
+/// Creates a MarkdownNode::Doc from a text, using the given options
+pub fn from_markdown_with_options(
+    text: &str,
+    options: &FromMarkdownOptions,
+) -> Result<MarkdownNode, FromMarkdownError> {
+    let deserializer = MarkdownDeserializer::new()
+        .with_max_depth(options.max_depth)
+        .with_soft_break(options.soft_break);
+    match options.on_unsupported {
+        // Without the extensions, tables, strikethrough and footnotes are read as plain text
+        UnsupportedPolicy::Error => {
+            let parser = Parser::new_ext(text, Options::ENABLE_TASKLISTS);
+            deserialize_events(deserializer, parser)
+        }
+        policy => {
+            let parser = Parser::new_ext(
+                text,
+                Options::ENABLE_TASKLISTS
+                    | Options::ENABLE_TABLES
+                    | Options::ENABLE_STRIKETHROUGH
+                    | Options::ENABLE_FOOTNOTES,
+            );
+            deserialize_events(
+                deserializer,
+                replace_unsupported(text, policy, parser.into_offset_iter()),
+            )
+        }
+    }
+}
+
+fn deserialize_events<'a, I>(
+    deserializer: MarkdownDeserializer,
+    events: I,
+) -> Result<MarkdownNode, FromMarkdownError>
+where
+    I: IntoIterator<Item = Event<'a>>,
+{
+    #[cfg(feature = "directives")]
+    return deserializer.deserialize_directives(events);
+    #[cfg(not(feature = "directives"))]
+    return deserializer.deserialize(events);
+}
+
+fn is_unsupported(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Table(_) | Tag::FootnoteDefinition(_) | Tag::Strikethrough
+    )
+}
+
+fn is_textblock(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Paragraph | Tag::Heading(_) | Tag::CodeBlock(_) | Tag::Image(..)
+    )
+}
+
+/// Drops the events of unsupported constructs, or replaces them with their source text. At the
+/// block level, consecutive pieces of source text are put into a single paragraph.
+fn replace_unsupported<'a, I>(text: &'a str, policy: UnsupportedPolicy, events: I) -> Vec<Event<'a>>
+where
+    I: IntoIterator<Item = (Event<'a>, Range<usize>)>,
+{
+    let mut result = Vec::new();
+    let mut textblock_depth = 0;
+    // Source text for a paragraph that may still be extended
+    let mut pending: Option<Range<usize>> = None;
+    let mut events = events.into_iter();
+
+    while let Some((event, range)) = events.next() {
+        let unsupported = match &event {
+            Event::Start(tag) if is_unsupported(tag) => {
+                let mut depth = 1;
+                for (event, _) in &mut events {
+                    match event {
+                        Event::Start(_) => depth += 1,
+                        Event::End(_) if depth == 1 => break,
+                        Event::End(_) => depth -= 1,
+                        _ => {}
+                    }
+                }
+                true
+            }
+            Event::Html(_) | Event::FootnoteReference(_) => true,
+            _ => false,
+        };
+
+        if unsupported && policy == UnsupportedPolicy::AsText {
+            if textblock_depth > 0 {
+                result.push(Event::Text(CowStr::Borrowed(&text[range])));
+            } else {
+                pending = match pending {
+                    Some(p) if p.end == range.start => Some(p.start..range.end),
+                    p => {
+                        push_source_paragraph(&mut result, text, p);
+                        Some(range)
+                    }
+                };
+            }
+        } else if !unsupported {
+            push_source_paragraph(&mut result, text, pending.take());
+            match &event {
+                Event::Start(tag) if is_textblock(tag) => textblock_depth += 1,
+                Event::End(tag) if is_textblock(tag) => textblock_depth -= 1,
+                _ => {}
+            }
+            result.push(event);
+        }
+    }
+    push_source_paragraph(&mut result, text, pending);
+    result
+}
+
+fn push_source_paragraph<'a>(
+    result: &mut Vec<Event<'a>>,
+    text: &'a str,
+    range: Option<Range<usize>>,
+) {
+    if let Some(range) = range {
+        let source = text[range].trim_end_matches('\n');
+        result.push(Event::Start(Tag::Paragraph));
+        result.push(Event::Text(CowStr::Borrowed(source)));
+        result.push(Event::End(Tag::Paragraph));
+    }
+}
+
+/// The attributes of a link mark. Reference links arrive resolved from the parser, and email
+/// autolinks get a `mailto:` prefix like in `prosemirror-markdown`. Autolinks keep their type,
+/// so that they are written as autolinks again.
+fn link_attrs(link_type: LinkType, href: &str, title: &str) -> LinkAttrs {
+    let markdown_type = match link_type {
+        LinkType::Autolink => MarkdownLinkType::Autolink,
+        LinkType::Email => MarkdownLinkType::Email,
+        _ => MarkdownLinkType::Inline,
+    };
+    let href = match link_type {
+        LinkType::Email if !href.starts_with("mailto:") => format!("mailto:{}", href),
+        LinkType::Inline
+        | LinkType::Reference
+        | LinkType::ReferenceUnknown
+        | LinkType::Collapsed
+        | LinkType::CollapsedUnknown
+        | LinkType::Shortcut
+        | LinkType::ShortcutUnknown
+        | LinkType::Autolink
+        | LinkType::Email => href.to_owned(),
+    };
+    LinkAttrs {
+        href,
+        title: title.to_owned(),
+        link_type: markdown_type,
+    }
+}
+
+/// A line that is a directive fence, i.e. `:::name` or `:::`
+#[cfg(feature = "directives")]
+enum Fence {
+    Open(String),
+    Close,
+}
+
+#[cfg(feature = "directives")]
+impl Fence {
+    fn parse(line: &[Event]) -> Option<Self> {
+        let mut text = String::new();
+        for event in line {
+            match event {
+                Event::Text(t) => text.push_str(t),
+                _ => return None,
+            }
+        }
+        let name = text.trim().strip_prefix(":::")?.trim();
+        if name.is_empty() {
+            Some(Fence::Close)
+        } else if name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            Some(Fence::Open(name.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Creates a MarkdownNode::Doc from a reader
+///
+/// For now, this reads the entire input into memory before parsing it.
+pub fn from_markdown_reader<R: Read>(mut reader: R) -> Result<MarkdownNode, FromMarkdownError> {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .map_err(|e| FromMarkdownError::Read(e.kind()))?;
+    from_markdown(&text)
+}
+
+/// Builds a MarkdownNode::Doc from a stream of `pulldown_cmark` events
+///
+/// Use this instead of [`from_markdown`] to pass in events from a custom source, e.g. a parser
+/// with non-default options or a filtered event stream. To extend the parsing, feed the events
+/// one at a time with [`MarkdownDeserializer::push_event`] and insert custom nodes in between.
 pub struct MarkdownDeserializer {
     stack: Vec<(Vec<MarkdownNode>, Attrs)>,
     mark_set: MarkSet<MD>,
+    max_depth: usize,
+    soft_break: SoftBreakMode,
+}
+
+impl Default for MarkdownDeserializer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MarkdownDeserializer {
+    /// Create a deserializer with an empty document and no limit on the nesting depth
+    pub fn new() -> Self {
+        MarkdownDeserializer {
+            stack: vec![(Vec::new(), Attrs::Doc)],
+            mark_set: MarkSet::default(),
+            max_depth: usize::MAX,
+            soft_break: SoftBreakMode::default(),
+        }
+    }
+
+    /// Fail with [`FromMarkdownError::DepthExceeded`] when events would nest nodes more than
+    /// `max_depth` levels deep, counting the document itself. Nodes opened with
+    /// [`MarkdownDeserializer::open_node`] are not limited.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Represent soft breaks as given by `mode` instead of as a space.
+    pub fn with_soft_break(mut self, mode: SoftBreakMode) -> Self {
+        self.soft_break = mode;
+        self
+    }
+
+    /// The marks that apply to text at the current point
+    pub fn marks(&self) -> &MarkSet<MD> {
+        &self.mark_set
+    }
+
     /*#[must_use]
     fn push_text(&mut self) -> Result<(), FromMarkdownError> {
         let last = self.stack.last_mut().ok_or(FromMarkdownError::StackEmpty)?;
@@ -65,8 +383,13 @@ impl MarkdownDeserializer {
         Ok(())
     }*/
 
-    fn push_stack(&mut self, attrs: Attrs) {
+    fn push_stack(&mut self, attrs: Attrs) -> Result<(), FromMarkdownError> {
+        // The document counts, like the outermost fragment does when reading JSON
+        if self.stack.len() >= self.max_depth {
+            return Err(FromMarkdownError::DepthExceeded(self.max_depth));
+        }
         self.stack.push((Vec::new(), attrs));
+        Ok(())
     }
 
     fn pop_stack(&mut self) -> Result<(Vec<MarkdownNode>, Attrs), FromMarkdownError> {
@@ -74,269 +397,742 @@ impl MarkdownDeserializer {
         Ok(popped)
     }
 
-    fn add_content(&mut self, node: MarkdownNode) -> Result<(), FromMarkdownError> {
+    /// Put back a node that was popped for a mismatched end tag, so the stack stays intact, and
+    /// describe the mismatch.
+    fn misplaced(
+        &mut self,
+        found: &'static str,
+        content: Vec<MarkdownNode>,
+        attrs: Attrs,
+    ) -> FromMarkdownError {
+        self.stack.push((content, attrs.clone()));
+        FromMarkdownError::MisplacedEndTag {
+            found,
+            expected: Box::new(attrs),
+            depth: self.stack.len(),
+        }
+    }
+
+    /// Add a node to the content of the innermost open node.
+    pub fn add_content(&mut self, node: MarkdownNode) -> Result<(), FromMarkdownError> {
         let last = self.stack.last_mut().ok_or(FromMarkdownError::StackEmpty)?;
         last.0.push(node);
         Ok(())
     }
 
-    fn deserialize(&mut self, parser: Parser) -> Result<MarkdownNode, FromMarkdownError> {
-        self.push_stack(Attrs::Doc);
-        for event in parser {
-            match event {
-                Event::Start(tag) => match tag {
-                    Tag::Paragraph => {
-                        self.stack.push((Vec::new(), Attrs::Paragraph));
-                    }
-                    Tag::Heading(l) => {
-                        let level = u8::try_from(l)?;
-                        self.stack
-                            .push((Vec::new(), Attrs::Heading(HeadingAttrs { level })));
-                    }
-                    Tag::BlockQuote => {
-                        self.stack.push((Vec::new(), Attrs::Blockquote));
-                    }
-                    Tag::CodeBlock(kind) => {
-                        let params = if let CodeBlockKind::Fenced(params) = kind {
-                            params.to_string()
-                        } else {
-                            String::new()
-                        };
-                        self.stack
-                            .push((Vec::new(), Attrs::CodeBlock(CodeBlockAttrs { params })));
-                    }
-                    Tag::List(ord) => {
-                        if let Some(order) = ord {
-                            self.stack.push((
-                                Vec::new(),
-                                Attrs::OrderedList(OrderedListAttrs {
-                                    order: order.try_into()?, // TODO: other error
-                                    tight: false,
-                                }),
-                            ))
-                        } else {
-                            self.stack.push((
-                                Vec::new(),
-                                Attrs::BulletList(BulletListAttrs { tight: false }),
-                            ));
-                        }
-                    }
-                    Tag::Item => {
-                        self.stack.push((Vec::new(), Attrs::ListItem));
-                    }
-                    Tag::FootnoteDefinition(_) => {
-                        return Err(FromMarkdownError::NotSupported("FootnoteDefinition"));
-                    }
-                    Tag::Table(_) => {
-                        return Err(FromMarkdownError::NotSupported("Table"));
-                    }
-                    Tag::TableHead => {
-                        return Err(FromMarkdownError::NotSupported("TableHead"));
-                    }
-                    Tag::TableRow => {
-                        return Err(FromMarkdownError::NotSupported("TableRow"));
-                    }
-                    Tag::TableCell => {
-                        return Err(FromMarkdownError::NotSupported("TableCell"));
-                    }
-                    Tag::Emphasis => {
-                        self.mark_set.add(&MarkdownMark::Em);
+    /// Add text with the current marks, appending it to a preceding text node with the same
+    /// marks.
+    fn push_text(&mut self, text: &str) -> Result<(), FromMarkdownError> {
+        let last = self.stack.last_mut().ok_or(FromMarkdownError::StackEmpty)?;
+        if let Some(MarkdownNode::Text(prev)) = last.0.last_mut() {
+            if prev.marks == self.mark_set {
+                let mut joined = String::with_capacity(prev.text.as_str().len() + text.len());
+                joined.push_str(prev.text.as_str());
+                joined.push_str(text);
+                prev.text = Text::from(joined);
+                return Ok(());
+            }
+        }
+        last.0.push(MarkdownNode::Text(TextNode {
+            text: Text::from(text.to_string()),
+            marks: self.mark_set.clone(),
+        }));
+        Ok(())
+    }
+
+    /// Consume the events and return the document they describe.
+    pub fn deserialize<'a, I>(mut self, events: I) -> Result<MarkdownNode, FromMarkdownError>
+    where
+        I: IntoIterator<Item = Event<'a>>,
+    {
+        for event in events {
+            self.push_event(event)?;
+        }
+        self.finish()
+    }
+
+    /// Like [`MarkdownDeserializer::deserialize`], but turns lines of paragraphs that are
+    /// `:::name` and `:::` fences into containers. Containers that are still open at the end
+    /// of the input are closed there.
+    #[cfg(feature = "directives")]
+    fn deserialize_directives<'a, I>(mut self, events: I) -> Result<MarkdownNode, FromMarkdownError>
+    where
+        I: IntoIterator<Item = Event<'a>>,
+    {
+        // The stack depth inside of each open container
+        let mut containers = Vec::new();
+        let mut events = events.into_iter();
+        while let Some(event) = events.next() {
+            if let Event::Start(Tag::Paragraph) = event {
+                let mut lines = vec![Vec::new()];
+                for event in &mut events {
+                    match event {
+                        Event::End(Tag::Paragraph) => break,
+                        Event::SoftBreak => lines.push(Vec::new()),
+                        event => lines.last_mut().unwrap().push(event),
                     }
-                    Tag::Strong => {
-                        self.mark_set.add(&MarkdownMark::Strong);
+                }
+                self.push_paragraph_lines(lines, &mut containers)?;
+            } else {
+                self.push_event(event)?;
+            }
+        }
+        while containers.pop().is_some() {
+            self.close_node()?;
+        }
+        self.finish()
+    }
+
+    #[cfg(feature = "directives")]
+    fn push_paragraph_lines(
+        &mut self,
+        lines: Vec<Vec<Event>>,
+        containers: &mut Vec<usize>,
+    ) -> Result<(), FromMarkdownError> {
+        let mut paragraph: Vec<Event> = Vec::new();
+        for line in lines {
+            let fence = Fence::parse(&line);
+            if let Some(Fence::Open(kind)) = fence {
+                self.push_paragraph(paragraph.drain(..))?;
+                self.push_stack(Attrs::Custom(helper::container(&kind, vec![])))?;
+                containers.push(self.stack.len());
+            } else if matches!(fence, Some(Fence::Close))
+                && containers.last() == Some(&self.stack.len())
+            {
+                self.push_paragraph(paragraph.drain(..))?;
+                containers.pop();
+                self.close_node()?;
+            } else {
+                if !paragraph.is_empty() {
+                    paragraph.push(Event::SoftBreak);
+                }
+                paragraph.extend(line);
+            }
+        }
+        self.push_paragraph(paragraph.drain(..))
+    }
+
+    #[cfg(feature = "directives")]
+    fn push_paragraph<'a, I>(&mut self, events: I) -> Result<(), FromMarkdownError>
+    where
+        I: ExactSizeIterator<Item = Event<'a>>,
+    {
+        if events.len() > 0 {
+            self.push_event(Event::Start(Tag::Paragraph))?;
+            for event in events {
+                self.push_event(event)?;
+            }
+            self.push_event(Event::End(Tag::Paragraph))?;
+        }
+        Ok(())
+    }
+
+    /// Process a single event.
+    pub fn push_event(&mut self, event: Event) -> Result<(), FromMarkdownError> {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph => {
+                    self.push_stack(Attrs::Paragraph)?;
+                }
+                Tag::Heading(l) => {
+                    let level = u8::try_from(l)?;
+                    self.push_stack(Attrs::Heading(HeadingAttrs {
+                        level,
+                        extra: Default::default(),
+                    }))?;
+                }
+                Tag::BlockQuote => {
+                    self.push_stack(Attrs::Blockquote)?;
+                }
+                Tag::CodeBlock(kind) => {
+                    let params = if let CodeBlockKind::Fenced(params) = kind {
+                        params.to_string()
+                    } else {
+                        String::new()
+                    };
+                    self.push_stack(Attrs::CodeBlock(CodeBlockAttrs {
+                        params,
+                        extra: Default::default(),
+                    }))?;
+                }
+                Tag::List(ord) => {
+                    if let Some(order) = ord {
+                        self.push_stack(Attrs::OrderedList(OrderedListAttrs {
+                            order: order.try_into()?, // TODO: other error
+                            tight: false,
+                            extra: Default::default(),
+                        }))?;
+                    } else {
+                        self.push_stack(Attrs::BulletList(BulletListAttrs {
+                            tight: false,
+                            extra: Default::default(),
+                        }))?;
                     }
-                    Tag::Strikethrough => {
-                        return Err(FromMarkdownError::NotSupported("Strikethrough"));
+                }
+                Tag::Item => {
+                    self.push_stack(Attrs::ListItem(ListItemAttrs::default()))?;
+                }
+                Tag::FootnoteDefinition(_) => {
+                    return Err(FromMarkdownError::NotSupported("FootnoteDefinition"));
+                }
+                Tag::Table(_) => {
+                    return Err(FromMarkdownError::NotSupported("Table"));
+                }
+                Tag::TableHead => {
+                    return Err(FromMarkdownError::NotSupported("TableHead"));
+                }
+                Tag::TableRow => {
+                    return Err(FromMarkdownError::NotSupported("TableRow"));
+                }
+                Tag::TableCell => {
+                    return Err(FromMarkdownError::NotSupported("TableCell"));
+                }
+                Tag::Emphasis => {
+                    self.mark_set.add(&MarkdownMark::em());
+                }
+                Tag::Strong => {
+                    self.mark_set.add(&MarkdownMark::strong());
+                }
+                Tag::Strikethrough => {
+                    return Err(FromMarkdownError::NotSupported("Strikethrough"));
+                }
+                Tag::Link(link_type, href, title) => {
+                    self.mark_set.add(&MarkdownMark::Link {
+                        attrs: link_attrs(link_type, &href, &title),
+                    });
+                }
+                Tag::Image(_, src, title) => {
+                    self.push_stack(Attrs::Image(ImageAttrs {
+                        src: src.to_string(),
+                        alt: String::new(),
+                        title: title.to_string(),
+                        extra: Default::default(),
+                    }))?;
+                }
+            },
+            Event::End(tag) => match tag {
+                Tag::Paragraph => {
+                    let (content, attrs) = self.pop_stack()?;
+                    if matches!(attrs, Attrs::Paragraph) {
+                        let p = MarkdownNode::Paragraph(Block {
+                            content: Fragment::from(content),
+                        });
+                        self.add_content(p)?;
+                    } else {
+                        return Err(self.misplaced("Paragraph", content, attrs));
                     }
-                    Tag::Link(_, href, title) => {
-                        self.mark_set.add(&MarkdownMark::Link {
-                            attrs: LinkAttrs {
-                                href: href.to_string(),
-                                title: title.to_string(),
-                            },
+                }
+                Tag::Heading(_) => {
+                    let (content, attrs) = self.pop_stack()?;
+                    if let Attrs::Heading(attrs) = attrs {
+                        let h = MarkdownNode::Heading(AttrNode {
+                            attrs,
+                            content: Fragment::from(content),
                         });
+                        self.add_content(h)?;
+                    } else {
+                        return Err(self.misplaced("Heading", content, attrs));
                     }
-                    Tag::Image(_, src, title) => {
-                        self.push_stack(Attrs::Image(ImageAttrs {
-                            src: src.to_string(),
-                            alt: title.to_string(),
-                            title: title.to_string(),
-                        }));
+                }
+                Tag::BlockQuote => {
+                    let (content, attrs) = self.pop_stack()?;
+                    if let Attrs::Blockquote = attrs {
+                        let b = MarkdownNode::Blockquote(Block {
+                            content: Fragment::from(content),
+                        });
+                        self.add_content(b)?;
+                    } else {
+                        return Err(self.misplaced("BlockQuote", content, attrs));
                     }
-                },
-                Event::End(tag) => match tag {
-                    Tag::Paragraph => {
-                        let (content, attrs) = self.pop_stack()?;
-                        if matches!(attrs, Attrs::Paragraph) {
-                            let p = MarkdownNode::Paragraph(Block {
-                                content: Fragment::from(content),
-                            });
-                            self.add_content(p)?;
-                        } else {
-                            return Err(FromMarkdownError::MisplacedEndTag("Paragraph", attrs));
+                }
+                Tag::CodeBlock(_) => {
+                    let (mut content, attrs) = self.pop_stack()?;
+                    if let Attrs::CodeBlock(attrs) = attrs {
+                        if let Some(MarkdownNode::Text(t)) = content.last_mut() {
+                            t.text.remove_last_newline();
                         }
+                        let cb = MarkdownNode::CodeBlock(AttrNode {
+                            attrs,
+                            content: Fragment::from(content),
+                        });
+                        self.add_content(cb)?;
+                    } else {
+                        return Err(self.misplaced("CodeBlock", content, attrs));
                     }
-                    Tag::Heading(_) => {
-                        let (content, attrs) = self.pop_stack()?;
-                        if let Attrs::Heading(attrs) = attrs {
-                            let h = MarkdownNode::Heading(AttrNode {
+                }
+                Tag::List(_) => {
+                    let (content, attrs) = self.pop_stack()?;
+                    match attrs {
+                        Attrs::BulletList(attrs) => {
+                            let l = MarkdownNode::BulletList(AttrNode {
                                 attrs,
                                 content: Fragment::from(content),
                             });
-                            self.add_content(h)?;
-                        } else {
-                            return Err(FromMarkdownError::MisplacedEndTag("Heading", attrs));
+                            self.add_content(l)?;
                         }
-                    }
-                    Tag::BlockQuote => {
-                        let (content, attrs) = self.pop_stack()?;
-                        if let Attrs::Blockquote = attrs {
-                            let b = MarkdownNode::Blockquote(Block {
-                                content: Fragment::from(content),
-                            });
-                            self.add_content(b)?;
-                        } else {
-                            return Err(FromMarkdownError::MisplacedEndTag("BlockQuote", attrs));
-                        }
-                    }
-                    Tag::CodeBlock(_) => {
-                        let (mut content, attrs) = self.pop_stack()?;
-                        if let Attrs::CodeBlock(attrs) = attrs {
-                            if let Some(MarkdownNode::Text(t)) = content.last_mut() {
-                                t.text.remove_last_newline();
-                            }
-                            let cb = MarkdownNode::CodeBlock(AttrNode {
+                        Attrs::OrderedList(attrs) => {
+                            let l = MarkdownNode::OrderedList(AttrNode {
                                 attrs,
                                 content: Fragment::from(content),
                             });
-                            self.add_content(cb)?;
-                        } else {
-                            return Err(FromMarkdownError::MisplacedEndTag("CodeBlock", attrs));
+                            self.add_content(l)?;
                         }
-                    }
-                    Tag::List(_) => {
-                        let (content, attrs) = self.pop_stack()?;
-                        match attrs {
-                            Attrs::BulletList(attrs) => {
-                                let l = MarkdownNode::BulletList(AttrNode {
-                                    attrs,
-                                    content: Fragment::from(content),
-                                });
-                                self.add_content(l)?;
-                            }
-                            Attrs::OrderedList(attrs) => {
-                                let l = MarkdownNode::OrderedList(AttrNode {
-                                    attrs,
-                                    content: Fragment::from(content),
-                                });
-                                self.add_content(l)?;
-                            }
-                            _ => {
-                                return Err(FromMarkdownError::MisplacedEndTag("List", attrs));
-                            }
+                        _ => {
+                            return Err(self.misplaced("List", content, attrs));
                         }
                     }
-                    Tag::Item => {
-                        let (content, attrs) = self.pop_stack()?;
-                        if let Attrs::ListItem = attrs {
-                            let cb = MarkdownNode::ListItem(Block {
-                                content: Fragment::from(content),
-                            });
-                            self.add_content(cb)?;
-                        }
-                    }
-                    Tag::FootnoteDefinition(_) => {
-                        return Err(FromMarkdownError::NotSupported("FootnoteDefinition"));
-                    }
-                    Tag::Table(_) => {
-                        return Err(FromMarkdownError::NotSupported("Table"));
-                    }
-                    Tag::TableHead => {
-                        return Err(FromMarkdownError::NotSupported("TableHead"));
-                    }
-                    Tag::TableRow => {
-                        return Err(FromMarkdownError::NotSupported("TableRow"));
-                    }
-                    Tag::TableCell => {
-                        return Err(FromMarkdownError::NotSupported("TableCell"));
-                    }
-                    Tag::Emphasis => {
-                        self.mark_set.remove(&MarkdownMark::Em);
-                    }
-                    Tag::Strong => {
-                        self.mark_set.remove(&MarkdownMark::Strong);
-                    }
-                    Tag::Strikethrough => {
-                        return Err(FromMarkdownError::NotSupported("Strikethrough"));
-                    }
-                    Tag::Link(_, href, title) => self.mark_set.remove(&MarkdownMark::Link {
-                        attrs: LinkAttrs {
-                            href: href.to_string(),
-                            title: title.to_string(),
-                        },
-                    }),
-                    Tag::Image(_, _, _) => {
-                        let (content, attrs) = self.pop_stack()?;
-                        if let Attrs::Image(attrs) = attrs {
-                            if content.len() > 0 {
-                                return Err(FromMarkdownError::NoChildrenAllowed("Image"));
-                            }
-                            let cb = MarkdownNode::Image(Leaf { attrs });
-                            self.add_content(cb)?;
-                        } else {
-                            return Err(FromMarkdownError::MisplacedEndTag("Image", attrs));
-                        }
+                }
+                Tag::Item => {
+                    let (content, attrs) = self.pop_stack()?;
+                    if let Attrs::ListItem(attrs) = attrs {
+                        let cb = MarkdownNode::ListItem(AttrNode {
+                            attrs,
+                            content: Fragment::from(content),
+                        });
+                        self.add_content(cb)?;
+                    } else {
+                        return Err(self.misplaced("Item", content, attrs));
                     }
-                },
-                Event::Text(text) => {
-                    self.add_content(MarkdownNode::Text(TextNode {
-                        text: Text::from(text.to_string()),
-                        marks: self.mark_set.clone(),
-                    }))?;
                 }
-                Event::Code(text) => {
-                    let mut marks = self.mark_set.clone();
-                    marks.add(&MarkdownMark::Code);
-                    self.add_content(MarkdownNode::Text(TextNode {
-                        text: Text::from(text.to_string()),
-                        marks,
-                    }))?;
+                Tag::FootnoteDefinition(_) => {
+                    return Err(FromMarkdownError::NotSupported("FootnoteDefinition"));
+                }
+                Tag::Table(_) => {
+                    return Err(FromMarkdownError::NotSupported("Table"));
+                }
+                Tag::TableHead => {
+                    return Err(FromMarkdownError::NotSupported("TableHead"));
                 }
-                Event::Html(_) => {
-                    return Err(FromMarkdownError::NotSupported("Html"));
+                Tag::TableRow => {
+                    return Err(FromMarkdownError::NotSupported("TableRow"));
                 }
-                Event::FootnoteReference(_) => {
-                    return Err(FromMarkdownError::NotSupported("FootnoteReference"));
+                Tag::TableCell => {
+                    return Err(FromMarkdownError::NotSupported("TableCell"));
                 }
-                Event::SoftBreak => {
-                    return Err(FromMarkdownError::NotSupported("SoftBreak"));
+                Tag::Emphasis => {
+                    self.mark_set.remove(&MarkdownMark::em());
                 }
-                Event::HardBreak => {
-                    self.add_content(MarkdownNode::HardBreak)?;
+                Tag::Strong => {
+                    self.mark_set.remove(&MarkdownMark::strong());
                 }
-                Event::Rule => {
-                    self.add_content(MarkdownNode::HorizontalRule)?;
+                Tag::Strikethrough => {
+                    return Err(FromMarkdownError::NotSupported("Strikethrough"));
                 }
-                Event::TaskListMarker(_) => {
-                    return Err(FromMarkdownError::NotSupported("TaskListMarker"));
+                Tag::Link(link_type, href, title) => self.mark_set.remove(&MarkdownMark::Link {
+                    attrs: link_attrs(link_type, &href, &title),
+                }),
+                Tag::Image(_, _, _) => {
+                    let (content, attrs) = self.pop_stack()?;
+                    if let Attrs::Image(mut attrs) = attrs {
+                        // The alt text is the content of the image tag
+                        for child in &content {
+                            match child {
+                                MarkdownNode::Text(text_node) => {
+                                    attrs.alt.push_str(text_node.text.as_str())
+                                }
+                                _ => return Err(FromMarkdownError::NoChildrenAllowed("Image")),
+                            }
+                        }
+                        let cb = MarkdownNode::Image(Leaf { attrs });
+                        self.add_content(cb)?;
+                    } else {
+                        return Err(self.misplaced("Image", content, attrs));
+                    }
                 }
+            },
+            // Indented code, e.g. in a blockquote, arrives one line at a time
+            Event::Text(text) => {
+                self.push_text(&text)?;
+            }
+            Event::Code(text) => {
+                let mut marks = self.mark_set.clone();
+                marks.add(&MarkdownMark::code());
+                self.add_content(MarkdownNode::Text(TextNode {
+                    text: Text::from(text.to_string()),
+                    marks,
+                }))?;
+            }
+            Event::Html(_) => {
+                return Err(FromMarkdownError::NotSupported("Html"));
             }
+            Event::FootnoteReference(_) => {
+                return Err(FromMarkdownError::NotSupported("FootnoteReference"));
+            }
+            Event::SoftBreak => match self.soft_break {
+                SoftBreakMode::Space => self.push_text(" ")?,
+                SoftBreakMode::HardBreak => self.add_content(MarkdownNode::HardBreak)?,
+                SoftBreakMode::Newline => self.push_text("\n")?,
+            },
+            Event::HardBreak => {
+                self.add_content(MarkdownNode::HardBreak)?;
+            }
+            Event::Rule => {
+                self.add_content(MarkdownNode::HorizontalRule)?;
+            }
+            Event::TaskListMarker(checked) => match self.stack.last_mut() {
+                Some((_, Attrs::ListItem(attrs))) => attrs.checked = Some(checked),
+                _ => return Err(FromMarkdownError::NotSupported("TaskListMarker")),
+            },
         }
+        Ok(())
+    }
+
+    /// Start a custom node. Until the matching call to [`MarkdownDeserializer::close_node`],
+    /// content is added to this node instead of its parent. The content of `node` itself is
+    /// replaced when it is closed.
+    pub fn open_node(&mut self, node: MarkdownNode) {
+        self.stack.push((Vec::new(), Attrs::Custom(node)));
+    }
+
+    /// Finish the custom node that was started last, and add it to its parent.
+    pub fn close_node(&mut self) -> Result<(), FromMarkdownError> {
         let (content, attrs) = self.pop_stack()?;
-        if let Attrs::Doc = attrs {
-            Ok(MarkdownNode::Doc(Block {
-                content: Fragment::from(content),
-            }))
+        if let Attrs::Custom(node) = attrs {
+            self.add_content(node.copy(|_| Fragment::from(content)))
         } else {
-            Err(FromMarkdownError::MisplacedEndTag("Doc", attrs))
+            Err(self.misplaced("Custom", content, attrs))
+        }
+    }
+
+    /// Finish the document. This fails if any node other than the document is still open.
+    pub fn finish(mut self) -> Result<MarkdownNode, FromMarkdownError> {
+        if self.stack.len() > 1 {
+            let open = self.stack.drain(1..).map(|(_, attrs)| attrs).collect();
+            return Err(FromMarkdownError::Unclosed(open));
+        }
+        match self.pop_stack()? {
+            (content, Attrs::Doc) => Ok(MarkdownNode::Doc(AttrNode {
+                attrs: AttrMap::default(),
+                content: Fragment::from(content),
+            })),
+            (_, attrs) => Err(FromMarkdownError::Unclosed(vec![attrs])),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{
+        from_markdown, from_markdown_reader, from_markdown_with_options, Attrs, FromMarkdownError,
+        FromMarkdownOptions, MarkdownDeserializer, SoftBreakMode, UnsupportedPolicy,
+    };
+    use crate::markdown::{
+        helper::{autolink, blockquote, br, code_block, doc, em, h1, link, node, p, strong},
+        MarkdownNode,
+    };
+    use crate::model::DEFAULT_MAX_DEPTH;
     use pulldown_cmark::{CowStr, Event, Parser, Tag};
 
+    #[test]
+    fn test_deserialize_events() {
+        let events = vec![
+            Event::Start(Tag::Heading(1)),
+            Event::Text(CowStr::Borrowed("Title")),
+            Event::End(Tag::Heading(1)),
+            Event::Start(Tag::Paragraph),
+            Event::Text(CowStr::Borrowed("Hello ")),
+            Event::Start(Tag::Strong),
+            Event::Text(CowStr::Borrowed("World")),
+            Event::End(Tag::Strong),
+            Event::End(Tag::Paragraph),
+        ];
+        let result = MarkdownDeserializer::default().deserialize(events);
+        assert_eq!(
+            result,
+            Ok(doc(vec![
+                h1("Title"),
+                p(vec![node("Hello "), strong("World")])
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_custom_node() {
+        let mut d = MarkdownDeserializer::new();
+        d.open_node(blockquote(vec![]));
+        for event in Parser::new("Hello **World**") {
+            d.push_event(event).unwrap();
+        }
+        d.close_node().unwrap();
+        d.add_content(MarkdownNode::HorizontalRule).unwrap();
+        assert_eq!(
+            d.finish(),
+            Ok(doc(vec![
+                blockquote(p(vec![node("Hello "), strong("World")])),
+                MarkdownNode::HorizontalRule
+            ]))
+        );
+
+        let mut d = MarkdownDeserializer::new();
+        assert_eq!(
+            d.close_node(),
+            Err(FromMarkdownError::MisplacedEndTag {
+                found: "Custom",
+                expected: Box::new(Attrs::Doc),
+                depth: 1,
+            })
+        );
+        // The document is still open after the error
+        assert_eq!(d.finish(), Ok(doc(vec![])));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // Every level is a list and a list item
+        let nested = |levels: usize| {
+            (0..levels)
+                .map(|level| format!("{}- item", "  ".repeat(level)))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        // The items of tight lists contain text directly, so 23 levels are 47 nodes with the
+        // document
+        assert!(from_markdown(&nested(23)).is_ok());
+        assert_eq!(
+            from_markdown(&nested(24)),
+            Err(FromMarkdownError::DepthExceeded(DEFAULT_MAX_DEPTH))
+        );
+
+        // The deepest document that is accepted by default can be read back from JSON
+        let json = serde_json::to_string(&from_markdown(&nested(23)).unwrap()).unwrap();
+        assert!(serde_json::from_str::<MarkdownNode>(&json).is_ok());
+
+        let err = from_markdown(&nested(1000)).unwrap_err();
+        assert_eq!(err, FromMarkdownError::DepthExceeded(DEFAULT_MAX_DEPTH));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Nodes are nested deeper than the limit of {}",
+                DEFAULT_MAX_DEPTH
+            )
+        );
+
+        let options = FromMarkdownOptions {
+            max_depth: 2001,
+            ..FromMarkdownOptions::default()
+        };
+        assert!(from_markdown_with_options(&nested(1000), &options).is_ok());
+    }
+
+    #[test]
+    fn test_unbalanced_events() {
+        let unbalanced = vec![
+            Event::Start(Tag::BlockQuote),
+            Event::Start(Tag::Paragraph),
+            Event::Text(CowStr::Borrowed("Hello")),
+            Event::End(Tag::BlockQuote),
+        ];
+        let err = MarkdownDeserializer::new()
+            .deserialize(unbalanced.clone())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            FromMarkdownError::MisplacedEndTag {
+                found: "BlockQuote",
+                expected: Box::new(Attrs::Paragraph),
+                depth: 3,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Found the end of BlockQuote, but the innermost open node is Paragraph (3 open nodes)"
+        );
+
+        let unclosed = MarkdownDeserializer::new().deserialize(unbalanced[..3].to_vec());
+        assert_eq!(
+            unclosed,
+            Err(FromMarkdownError::Unclosed(vec![
+                Attrs::Blockquote,
+                Attrs::Paragraph
+            ]))
+        );
+    }
+
+    #[cfg(feature = "directives")]
+    #[test]
+    fn test_directives() {
+        use crate::markdown::{from_markdown, helper::container, to_markdown};
+
+        let expected = doc(vec![
+            h1("Alert Area"),
+            container("success", p("Yes :tada:")),
+        ]);
+        let compact = "# Alert Area\n\n:::success\nYes :tada:\n:::\n";
+        assert_eq!(from_markdown(compact), Ok(expected.clone()));
+
+        let text = to_markdown(&expected).unwrap();
+        assert_eq!(text, "# Alert Area\n\n:::success\n\nYes :tada:\n\n:::");
+        assert_eq!(from_markdown(&text), Ok(expected));
+
+        let nested = doc(container(
+            "info",
+            vec![p("one"), container("warning", p("two"))],
+        ));
+        assert_eq!(
+            from_markdown(":::info\n\none\n:::warning\ntwo\n:::\n\n:::\n"),
+            Ok(nested)
+        );
+
+        let unclosed = doc(vec![p("a"), container("note", p("b"))]);
+        assert_eq!(from_markdown("a\n\n:::note\nb"), Ok(unclosed));
+    }
+
+    #[test]
+    fn test_from_markdown_reader() {
+        let input: &[u8] = b"# Title\n\nHello **World**\n";
+        assert_eq!(
+            from_markdown_reader(input),
+            Ok(doc(vec![
+                h1("Title"),
+                p(vec![node("Hello "), strong("World")])
+            ]))
+        );
+
+        let invalid: &[u8] = &[0xff, 0xfe];
+        assert_eq!(
+            from_markdown_reader(invalid),
+            Err(FromMarkdownError::Read(std::io::ErrorKind::InvalidData))
+        );
+    }
+
+    #[test]
+    fn test_links() {
+        let linked = |md: &str| from_markdown(md).unwrap();
+        let expected = doc(p(vec![
+            node("see "),
+            link("https://example.com", "Example", "docs"),
+        ]));
+        for md in &[
+            "see [docs](https://example.com \"Example\")",
+            "see [docs][ex]\n\n[ex]: https://example.com \"Example\"",
+        ] {
+            assert_eq!(linked(md), expected, "{}", md);
+        }
+
+        let expected = doc(p(link("https://example.com", "Example", "docs")));
+        for md in &[
+            "[docs][]\n\n[docs]: https://example.com \"Example\"",
+            "[docs]\n\n[docs]: https://example.com \"Example\"",
+        ] {
+            assert_eq!(linked(md), expected, "{}", md);
+        }
+
+        assert_eq!(
+            linked("<https://example.com>"),
+            doc(p(autolink("https://example.com", "https://example.com")))
+        );
+        assert_eq!(
+            linked("<me@example.com>"),
+            doc(p(autolink("mailto:me@example.com", "me@example.com")))
+        );
+    }
+
+    #[test]
+    fn test_code_block_in_blockquote() {
+        assert_eq!(
+            from_markdown("> ```\n> a\n> b\n> ```"),
+            Ok(doc(blockquote(code_block("", "a\nb"))))
+        );
+    }
+
+    #[test]
+    fn test_soft_break() {
+        let text = "first *line*\nsecond line\n";
+        let parse = |soft_break| {
+            from_markdown_with_options(
+                text,
+                &FromMarkdownOptions {
+                    soft_break,
+                    ..FromMarkdownOptions::default()
+                },
+            )
+        };
+
+        assert_eq!(
+            parse(SoftBreakMode::Space),
+            Ok(doc(p(vec![
+                node("first "),
+                em("line"),
+                node(" second line")
+            ])))
+        );
+        assert_eq!(
+            parse(SoftBreakMode::HardBreak),
+            Ok(doc(p(vec![
+                node("first "),
+                em("line"),
+                br(),
+                node("second line")
+            ])))
+        );
+        assert_eq!(
+            parse(SoftBreakMode::Newline),
+            Ok(doc(p(vec![
+                node("first "),
+                em("line"),
+                node("\nsecond line")
+            ])))
+        );
+        assert_eq!(from_markdown("one\ntwo"), Ok(doc(p("one two"))));
+        assert_eq!(
+            from_markdown_with_options(
+                "one\ntwo",
+                &FromMarkdownOptions {
+                    soft_break: SoftBreakMode::Newline,
+                    ..FromMarkdownOptions::default()
+                }
+            ),
+            Ok(doc(p("one\ntwo")))
+        );
+    }
+
+    #[test]
+    fn test_unsupported_policy() {
+        let text = "# Title\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\nSome ~~old~~ text\n";
+        let parse = |on_unsupported| {
+            from_markdown_with_options(
+                text,
+                &FromMarkdownOptions {
+                    on_unsupported,
+                    ..FromMarkdownOptions::default()
+                },
+            )
+        };
+
+        // Without the extensions, the table and strikethrough are read as text
+        assert_eq!(
+            parse(UnsupportedPolicy::Error),
+            Ok(doc(vec![
+                h1("Title"),
+                p("| a | b | |---|---| | 1 | 2 |"),
+                p("Some ~~old~~ text")
+            ]))
+        );
+        assert_eq!(
+            parse(UnsupportedPolicy::Skip),
+            Ok(doc(vec![h1("Title"), p("Some  text")]))
+        );
+        assert_eq!(
+            parse(UnsupportedPolicy::AsText),
+            Ok(doc(vec![
+                h1("Title"),
+                p("| a | b |\n|---|---|\n| 1 | 2 |"),
+                p("Some ~~old~~ text")
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_extensions_off_by_default() {
+        assert_eq!(from_markdown("a ~~b~~ c"), Ok(doc(p("a ~~b~~ c"))));
+        assert_eq!(
+            from_markdown("| a |\n|---|\n| 1 |"),
+            Ok(doc(p("| a | |---| | 1 |")))
+        );
+        // Without the extension, a footnote definition is read as a link reference definition
+        assert_eq!(from_markdown("text\n\n[^1]: note"), Ok(doc(p("text"))));
+    }
+
     #[test]
     fn test_alerts() {
         let test_string = "\
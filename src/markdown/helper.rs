@@ -3,8 +3,11 @@
 //! This module contains some functions to create nodes programmatically.
 //!
 //! See also: <https://github.com/prosemirror/prosemirror-test-builder>
-use super::{BulletListAttrs, CodeBlockAttrs, HeadingAttrs, MarkdownMark, MarkdownNode, MD};
-use crate::model::{self, AttrNode, Block, Mark, Text, TextNode};
+use super::{
+    AttrMap, BulletListAttrs, CodeBlockAttrs, HeadingAttrs, ImageAttrs, LinkAttrs, ListItemAttrs,
+    MarkdownLinkType, MarkdownMark, MarkdownNode, OrderedListAttrs, MD,
+};
+use crate::model::{self, AttrNode, Block, Leaf, Mark, MarkSet, Text, TextNode};
 
 type Fragment = model::Fragment<MD>;
 
@@ -22,7 +25,8 @@ impl From<MarkdownNode> for Fragment {
 
 /// Create a document node.
 pub fn doc<A: Into<Fragment>>(content: A) -> MarkdownNode {
-    MarkdownNode::Doc(Block {
+    MarkdownNode::Doc(AttrNode {
+        attrs: AttrMap::default(),
         content: content.into(),
     })
 }
@@ -30,7 +34,10 @@ pub fn doc<A: Into<Fragment>>(content: A) -> MarkdownNode {
 /// Create a heading node.
 pub fn h<A: Into<Fragment>>(level: u8, content: A) -> MarkdownNode {
     MarkdownNode::Heading(AttrNode {
-        attrs: HeadingAttrs { level },
+        attrs: HeadingAttrs {
+            level,
+            extra: Default::default(),
+        },
         content: content.into(),
     })
 }
@@ -49,7 +56,7 @@ pub fn h2<A: Into<Fragment>>(content: A) -> MarkdownNode {
 pub fn em(content: &str) -> MarkdownNode {
     MarkdownNode::Text(TextNode {
         text: Text::from(content.to_string()),
-        marks: MarkdownMark::Em.into_set(),
+        marks: MarkdownMark::em().into_set(),
     })
 }
 
@@ -57,10 +64,87 @@ pub fn em(content: &str) -> MarkdownNode {
 pub fn strong(content: &str) -> MarkdownNode {
     MarkdownNode::Text(TextNode {
         text: Text::from(content.to_string()),
-        marks: MarkdownMark::Strong.into_set(),
+        marks: MarkdownMark::strong().into_set(),
+    })
+}
+
+/// Create an inline code text node.
+pub fn code(content: &str) -> MarkdownNode {
+    MarkdownNode::Text(TextNode {
+        text: Text::from(content.to_string()),
+        marks: MarkdownMark::code().into_set(),
+    })
+}
+
+/// Create a text node with all of the given marks.
+///
+/// If there are several marks of the same type, the last one is used.
+pub fn marked(marks: &[MarkdownMark], content: &str) -> MarkdownNode {
+    let mut set = MarkSet::default();
+    for mark in marks {
+        set.add(mark);
+    }
+    MarkdownNode::Text(TextNode {
+        text: Text::from(content.to_string()),
+        marks: set,
+    })
+}
+
+/// Create a linked text node.
+pub fn link(href: &str, title: &str, content: &str) -> MarkdownNode {
+    MarkdownNode::Text(TextNode {
+        text: Text::from(content.to_string()),
+        marks: MarkdownMark::Link {
+            attrs: LinkAttrs::new(href.to_owned(), title.to_owned()),
+        }
+        .into_set(),
+    })
+}
+
+/// Create a text node with an autolink, i.e. `<https://example.com>` in markdown.
+///
+/// If the `href` starts with `mailto:`, this is an email autolink.
+pub fn autolink(href: &str, content: &str) -> MarkdownNode {
+    let link_type = if href.starts_with("mailto:") {
+        MarkdownLinkType::Email
+    } else {
+        MarkdownLinkType::Autolink
+    };
+    MarkdownNode::Text(TextNode {
+        text: Text::from(content.to_string()),
+        marks: MarkdownMark::Link {
+            attrs: LinkAttrs {
+                href: href.to_owned(),
+                title: String::new(),
+                link_type,
+            },
+        }
+        .into_set(),
+    })
+}
+
+/// Create a hard break node.
+pub fn br() -> MarkdownNode {
+    MarkdownNode::HardBreak
+}
+
+/// Create an image node.
+pub fn img(src: &str, alt: &str, title: &str) -> MarkdownNode {
+    MarkdownNode::Image(Leaf {
+        attrs: ImageAttrs {
+            src: src.to_owned(),
+            alt: alt.to_owned(),
+            title: title.to_owned(),
+            extra: Default::default(),
+        },
     })
 }
 
+/// Create a horizontal rule node.
+pub fn hr() -> MarkdownNode {
+    MarkdownNode::HorizontalRule
+}
+
 /// Create a paragraph node.
 pub fn p<A: Into<Fragment>>(content: A) -> MarkdownNode {
     MarkdownNode::Paragraph(Block {
@@ -70,7 +154,19 @@ pub fn p<A: Into<Fragment>>(content: A) -> MarkdownNode {
 
 /// Create a list item node.
 pub fn li<A: Into<Fragment>>(content: A) -> MarkdownNode {
-    MarkdownNode::ListItem(Block {
+    MarkdownNode::ListItem(AttrNode {
+        attrs: ListItemAttrs::default(),
+        content: content.into(),
+    })
+}
+
+/// Create a task list item node.
+pub fn task<A: Into<Fragment>>(checked: bool, content: A) -> MarkdownNode {
+    MarkdownNode::ListItem(AttrNode {
+        attrs: ListItemAttrs {
+            checked: Some(checked),
+            extra: Default::default(),
+        },
         content: content.into(),
     })
 }
@@ -78,7 +174,22 @@ pub fn li<A: Into<Fragment>>(content: A) -> MarkdownNode {
 /// Create a Bullet list node.
 pub fn ul<A: Into<Fragment>>(content: A) -> MarkdownNode {
     MarkdownNode::BulletList(AttrNode {
-        attrs: BulletListAttrs { tight: false },
+        attrs: BulletListAttrs {
+            tight: false,
+            extra: Default::default(),
+        },
+        content: content.into(),
+    })
+}
+
+/// Create an ordered list node, starting at `order`.
+pub fn ol<A: Into<Fragment>>(order: usize, content: A) -> MarkdownNode {
+    MarkdownNode::OrderedList(AttrNode {
+        attrs: OrderedListAttrs {
+            order,
+            tight: false,
+            extra: Default::default(),
+        },
         content: content.into(),
     })
 }
@@ -88,6 +199,7 @@ pub fn code_block<A: Into<Fragment>>(params: &str, content: A) -> MarkdownNode {
     MarkdownNode::CodeBlock(AttrNode {
         attrs: CodeBlockAttrs {
             params: params.to_owned(),
+            extra: Default::default(),
         },
         content: content.into(),
     })
@@ -100,6 +212,18 @@ pub fn blockquote<A: Into<Fragment>>(content: A) -> MarkdownNode {
     })
 }
 
+/// Create a directive container node.
+#[cfg(feature = "directives")]
+pub fn container<A: Into<Fragment>>(kind: &str, content: A) -> MarkdownNode {
+    MarkdownNode::Container(AttrNode {
+        attrs: super::ContainerAttrs {
+            kind: kind.to_owned(),
+            extra: Default::default(),
+        },
+        content: content.into(),
+    })
+}
+
 /// Create a node.
 pub fn node<A: Into<MarkdownNode>>(src: A) -> MarkdownNode {
     src.into()
@@ -2,6 +2,22 @@
 //!
 //! This module is derived from the `prosemirror-markdown` schema and the
 //! the general JSON serialization of nodes.
+//!
+//! ## Unknown attributes
+//!
+//! Documents from a frontend whose schema has more attributes than this one would lose them in
+//! a round trip. With the `preserve-unknown-attrs` feature, the attributes of headings, code
+//! blocks, lists, list items, images and directive containers that this schema doesn't know
+//! about are kept in their `extra` field and written back when serializing. Without the
+//! feature, they are dropped and `extra` stays empty.
+//!
+//! This only covers nodes and marks that have attributes in this schema. Unknown attributes on
+//! paragraphs, blockquotes and links are always dropped. The document and the strong, em and
+//! code marks keep all of their attributes, see [`AttrMap`].
+//!
+//! The `extra` fields exist independent of the feature, so that enabling it doesn't change the
+//! API. Struct literals need to set them, e.g. to `Map::new()`, or use a constructor like
+//! [`HeadingAttrs::new`] or [`LinkAttrs::new`].
 mod attrs;
 mod content;
 pub mod helper;
@@ -9,33 +25,52 @@ mod schema;
 
 #[cfg(feature = "cmark")]
 mod from_markdown;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "to_html")]
+mod to_html;
 #[cfg(feature = "cmark")]
 mod to_markdown;
 
 use crate::model::{
     AttrNode, Block, Fragment, Leaf, Mark, MarkSet, MarkType, Node, Text, TextNode,
 };
+#[cfg(feature = "directives")]
+pub use attrs::ContainerAttrs;
 pub use attrs::{
-    BulletListAttrs, CodeBlockAttrs, HeadingAttrs, ImageAttrs, LinkAttrs, OrderedListAttrs,
+    AttrMap, BulletListAttrs, CodeBlockAttrs, HeadingAttrs, ImageAttrs, LinkAttrs, ListItemAttrs,
+    MarkdownLinkType, OrderedListAttrs,
 };
 pub use content::MarkdownContentMatch;
 pub use schema::{MarkdownNodeType, MD};
 
 #[cfg(feature = "cmark")]
-pub use from_markdown::{from_markdown, FromMarkdownError};
+pub use from_markdown::{
+    from_markdown, from_markdown_reader, from_markdown_with_options, FromMarkdownError,
+    FromMarkdownOptions, MarkdownDeserializer, SoftBreakMode, UnsupportedPolicy,
+};
+#[cfg(feature = "to_html")]
+pub use to_html::to_html;
 #[cfg(feature = "cmark")]
-pub use to_markdown::{to_markdown, ToMarkdownError};
+pub use to_markdown::{
+    to_markdown, to_markdown_events, to_markdown_with_options, CodeFenceChar, ToMarkdownError,
+    ToMarkdownOptions,
+};
 
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 
 /// The node type for the markdown schema
+///
+/// This enum is non-exhaustive, because the `directives` feature adds a variant, and enabling a
+/// feature must not break matches in other crates.
 #[derive(Debug, Derivative, Deserialize, Serialize, PartialEq, Eq)]
 #[derivative(Clone(bound = ""))]
 #[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum MarkdownNode {
     /// The document root
-    Doc(Block<MD>),
+    Doc(AttrNode<MD, AttrMap>),
     /// A heading, e.g. `<h1>`
     Heading(AttrNode<MD, HeadingAttrs>),
     /// A code block
@@ -51,13 +86,16 @@ pub enum MarkdownNode {
     /// An ordered list
     OrderedList(AttrNode<MD, OrderedListAttrs>),
     /// A list item
-    ListItem(Block<MD>),
+    ListItem(AttrNode<MD, ListItemAttrs>),
     /// A horizontal line `<hr>`
     HorizontalRule,
     /// A hard break `<br>`
     HardBreak,
     /// An image `<img>`
     Image(Leaf<ImageAttrs>),
+    /// A directive container, e.g. `:::success`
+    #[cfg(feature = "directives")]
+    Container(AttrNode<MD, ContainerAttrs>),
 }
 
 impl From<TextNode<MD>> for MarkdownNode {
@@ -93,6 +131,8 @@ impl Node<MD> for MarkdownNode {
             Self::Text { .. } => false,
             Self::Image { .. } => false,
             Self::HardBreak => false,
+            #[cfg(feature = "directives")]
+            Self::Container { .. } => true,
         }
     }
 
@@ -110,6 +150,8 @@ impl Node<MD> for MarkdownNode {
             Self::Text { .. } => MarkdownNodeType::Text,
             Self::Image { .. } => MarkdownNodeType::Image,
             Self::HardBreak => MarkdownNodeType::HardBreak,
+            #[cfg(feature = "directives")]
+            Self::Container { .. } => MarkdownNodeType::Container,
         }
     }
 
@@ -122,7 +164,7 @@ impl Node<MD> for MarkdownNode {
 
     fn content(&self) -> Option<&Fragment<MD>> {
         match self {
-            Self::Doc(doc) => Some(&doc.content),
+            Self::Doc(AttrNode { content, .. }) => Some(content),
             Self::Heading(AttrNode { content, .. }) => Some(content),
             Self::CodeBlock(AttrNode { content, .. }) => Some(content),
             Self::Text { .. } => None,
@@ -130,15 +172,17 @@ impl Node<MD> for MarkdownNode {
             Self::Paragraph(Block { content }) => Some(content),
             Self::BulletList(AttrNode { content, .. }) => Some(content),
             Self::OrderedList(AttrNode { content, .. }) => Some(content),
-            Self::ListItem(Block { content }) => Some(content),
+            Self::ListItem(AttrNode { content, .. }) => Some(content),
             Self::HorizontalRule => None,
             Self::HardBreak => None,
             Self::Image { .. } => None,
+            #[cfg(feature = "directives")]
+            Self::Container(AttrNode { content, .. }) => Some(content),
         }
     }
 
     fn marks(&self) -> Option<&MarkSet<MD>> {
-        None
+        self.text_node().map(|node| &node.marks)
     }
 
     fn mark(&self, set: MarkSet<MD>) -> Self {
@@ -158,7 +202,7 @@ impl Node<MD> for MarkdownNode {
         F: FnOnce(&Fragment<MD>) -> Fragment<MD>,
     {
         match self {
-            Self::Doc(block) => Self::Doc(block.copy(map)),
+            Self::Doc(node) => Self::Doc(node.copy(map)),
             Self::Heading(node) => Self::Heading(node.copy(map)),
             Self::CodeBlock(node) => Self::CodeBlock(node.copy(map)),
             Self::Text(node) => Self::Text(node.clone()),
@@ -166,10 +210,12 @@ impl Node<MD> for MarkdownNode {
             Self::Paragraph(block) => Self::Paragraph(block.copy(map)),
             Self::BulletList(node) => Self::BulletList(node.copy(map)),
             Self::OrderedList(node) => Self::OrderedList(node.copy(map)),
-            Self::ListItem(block) => Self::ListItem(block.copy(map)),
+            Self::ListItem(node) => Self::ListItem(node.copy(map)),
             Self::HorizontalRule => Self::HorizontalRule,
             Self::HardBreak => Self::HardBreak,
             Self::Image(img) => Self::Image(img.clone()),
+            #[cfg(feature = "directives")]
+            Self::Container(node) => Self::Container(node.copy(map)),
         }
     }
 }
@@ -181,15 +227,31 @@ impl From<&str> for MarkdownNode {
 }
 
 /// The marks that can be on some span
+///
+/// Strong, em and code used to be unit variants. They carry an [`AttrMap`] now, which is a
+/// breaking change: match them as e.g. `MarkdownMark::Strong { .. }`, and build them without
+/// attributes with [`MarkdownMark::strong`], [`MarkdownMark::em`] and [`MarkdownMark::code`].
 #[derive(Debug, Hash, Eq, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum MarkdownMark {
     /// bold
-    Strong,
+    Strong {
+        /// The attributes
+        #[serde(default, skip_serializing_if = "AttrMap::is_empty")]
+        attrs: AttrMap,
+    },
     /// italics
-    Em,
+    Em {
+        /// The attributes
+        #[serde(default, skip_serializing_if = "AttrMap::is_empty")]
+        attrs: AttrMap,
+    },
     /// monospace
-    Code,
+    Code {
+        /// The attributes
+        #[serde(default, skip_serializing_if = "AttrMap::is_empty")]
+        attrs: AttrMap,
+    },
     /// hyper-linked
     Link {
         /// The attributes
@@ -197,12 +259,35 @@ pub enum MarkdownMark {
     },
 }
 
+impl MarkdownMark {
+    /// A strong mark without attributes
+    pub fn strong() -> Self {
+        Self::Strong {
+            attrs: AttrMap::default(),
+        }
+    }
+
+    /// An em mark without attributes
+    pub fn em() -> Self {
+        Self::Em {
+            attrs: AttrMap::default(),
+        }
+    }
+
+    /// A code mark without attributes
+    pub fn code() -> Self {
+        Self::Code {
+            attrs: AttrMap::default(),
+        }
+    }
+}
+
 impl Mark<MD> for MarkdownMark {
     fn r#type(&self) -> MarkdownMarkType {
         match self {
-            Self::Strong => MarkdownMarkType::Strong,
-            Self::Em => MarkdownMarkType::Em,
-            Self::Code => MarkdownMarkType::Code,
+            Self::Strong { .. } => MarkdownMarkType::Strong,
+            Self::Em { .. } => MarkdownMarkType::Em,
+            Self::Code { .. } => MarkdownMarkType::Code,
             Self::Link { .. } => MarkdownMarkType::Link,
         }
     }
@@ -221,4 +306,101 @@ pub enum MarkdownMarkType {
     Link,
 }
 
-impl MarkType for MarkdownMarkType {}
+impl MarkType for MarkdownMarkType {
+    fn inclusive(self) -> bool {
+        !matches!(self, Self::Link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::helper::*;
+    use super::MarkdownNode;
+
+    fn assert_json(node: MarkdownNode, expected: &str) {
+        assert_eq!(serde_json::to_string(&node).unwrap(), expected);
+        assert_eq!(
+            serde_json::from_str::<MarkdownNode>(expected).unwrap(),
+            node
+        );
+    }
+
+    #[test]
+    fn test_ordered_list_order() {
+        let order = |attrs: &str| {
+            let json = format!(
+                r#"{{"type":"ordered_list","attrs":{},"content":[]}}"#,
+                attrs
+            );
+            match serde_json::from_str::<MarkdownNode>(&json).unwrap() {
+                MarkdownNode::OrderedList(node) => node.attrs.order,
+                other => panic!("expected an ordered list, got {:?}", other),
+            }
+        };
+        assert_eq!(order(r#"{"order":0}"#), 1);
+        assert_eq!(order(r#"{"order":null}"#), 1);
+        assert_eq!(order(r#"{"order":5}"#), 5);
+        assert_eq!(order(r#"{"order":1,"tight":true}"#), 1);
+        assert_eq!(order("{}"), 1);
+    }
+
+    #[test]
+    fn test_json_format() {
+        assert_json(doc(vec![]), r#"{"type":"doc"}"#);
+        assert_json(
+            h2("Title"),
+            r#"{"type":"heading","attrs":{"level":2},"content":[{"type":"text","marks":[],"text":"Title"}]}"#,
+        );
+        assert_json(
+            code_block("rust", node("fn main() {}")),
+            r#"{"type":"code_block","attrs":{"params":"rust"},"content":[{"type":"text","marks":[],"text":"fn main() {}"}]}"#,
+        );
+        assert_json(
+            node("plain"),
+            r#"{"type":"text","marks":[],"text":"plain"}"#,
+        );
+        assert_json(
+            em("em"),
+            r#"{"type":"text","marks":[{"type":"em"}],"text":"em"}"#,
+        );
+        assert_json(
+            link("https://example.com", "Example", "link"),
+            r#"{"type":"text","marks":[{"type":"link","attrs":{"href":"https://example.com","title":"Example"}}],"text":"link"}"#,
+        );
+        assert_json(
+            autolink("https://example.com", "https://example.com"),
+            r#"{"type":"text","marks":[{"type":"link","attrs":{"href":"https://example.com","title":"","linkType":"autolink"}}],"text":"https://example.com"}"#,
+        );
+        assert_json(blockquote(vec![]), r#"{"type":"blockquote"}"#);
+        assert_json(p(vec![]), r#"{"type":"paragraph"}"#);
+        assert_json(
+            ul(vec![]),
+            r#"{"type":"bullet_list","attrs":{"tight":false}}"#,
+        );
+        assert_json(
+            ol(3, vec![]),
+            r#"{"type":"ordered_list","attrs":{"order":3,"tight":false}}"#,
+        );
+        assert_json(li(vec![]), r#"{"type":"list_item"}"#);
+        assert_json(
+            task(true, vec![]),
+            r#"{"type":"list_item","attrs":{"checked":true}}"#,
+        );
+        // Docs and plain list items have no attrs key, like before they had attributes
+        assert_json(
+            doc(ul(vec![li(p("a")), task(false, p("b"))])),
+            r#"{"type":"doc","content":[{"type":"bullet_list","attrs":{"tight":false},"content":[{"type":"list_item","content":[{"type":"paragraph","content":[{"type":"text","marks":[],"text":"a"}]}]},{"type":"list_item","attrs":{"checked":false},"content":[{"type":"paragraph","content":[{"type":"text","marks":[],"text":"b"}]}]}]}]}"#,
+        );
+        assert_json(hr(), r#"{"type":"horizontal_rule"}"#);
+        assert_json(br(), r#"{"type":"hard_break"}"#);
+        assert_json(
+            img("a.png", "alt", "title"),
+            r#"{"type":"image","attrs":{"src":"a.png","alt":"alt","title":"title"}}"#,
+        );
+        #[cfg(feature = "directives")]
+        assert_json(
+            container("success", vec![]),
+            r#"{"type":"container","attrs":{"kind":"success"}}"#,
+        );
+    }
+}
@@ -1,6 +1,9 @@
-use super::MarkdownMarkType;
+use super::{
+    helper::{blockquote, code_block, doc, h, li, ol, p, ul},
+    MarkdownMarkType,
+};
 use crate::markdown::{MarkdownContentMatch, MarkdownMark, MarkdownNode};
-use crate::model::{ContentMatch, Fragment, MarkSet, Node, NodeType, Schema};
+use crate::model::{ContentMatch, Fragment, Node, NodeType, Schema};
 
 /// The markdown schema type
 pub struct MD;
@@ -11,10 +14,47 @@ impl Schema for MD {
     type MarkType = MarkdownMarkType;
     type NodeType = MarkdownNodeType;
     type ContentMatch = MarkdownContentMatch;
+
+    fn node_types() -> &'static [MarkdownNodeType] {
+        &[
+            MarkdownNodeType::Doc,
+            MarkdownNodeType::Heading,
+            MarkdownNodeType::CodeBlock,
+            MarkdownNodeType::Text,
+            MarkdownNodeType::Blockquote,
+            MarkdownNodeType::Paragraph,
+            MarkdownNodeType::BulletList,
+            MarkdownNodeType::OrderedList,
+            MarkdownNodeType::ListItem,
+            MarkdownNodeType::HorizontalRule,
+            MarkdownNodeType::HardBreak,
+            MarkdownNodeType::Image,
+            #[cfg(feature = "directives")]
+            MarkdownNodeType::Container,
+        ]
+    }
+
+    fn empty_doc() -> MarkdownNode {
+        doc(p(vec![]))
+    }
+
+    fn mark_type_from_str(name: &str) -> Option<MarkdownMarkType> {
+        match name {
+            "strong" => Some(MarkdownMarkType::Strong),
+            "em" => Some(MarkdownMarkType::Em),
+            "code" => Some(MarkdownMarkType::Code),
+            "link" => Some(MarkdownMarkType::Link),
+            _ => None,
+        }
+    }
 }
 
 /// The node-spec type for the markdown schema
+///
+/// Like [`MarkdownNode`](super::MarkdownNode), this enum is non-exhaustive because of the
+/// `directives` feature.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum MarkdownNodeType {
     /// The document root
     Doc,
@@ -40,9 +80,22 @@ pub enum MarkdownNodeType {
     HardBreak,
     /// An image `<img>`
     Image,
+    /// A directive container, e.g. `:::success`
+    #[cfg(feature = "directives")]
+    Container,
 }
 
 impl MarkdownNodeType {
+    #[cfg(feature = "directives")]
+    fn is_container(self) -> bool {
+        matches!(self, Self::Container)
+    }
+
+    #[cfg(not(feature = "directives"))]
+    fn is_container(self) -> bool {
+        false
+    }
+
     fn _allow_marks(self) -> bool {
         match self {
             Self::Doc
@@ -51,6 +104,9 @@ impl MarkdownNodeType {
             | Self::OrderedList
             | Self::ListItem => false, // block && !textblock
 
+            #[cfg(feature = "directives")]
+            Self::Container => false, // block && !textblock
+
             Self::CodeBlock => false, // marks = ""
 
             Self::Heading | Self::Paragraph => true, // textblock
@@ -61,10 +117,6 @@ impl MarkdownNodeType {
 }
 
 impl NodeType<MD> for MarkdownNodeType {
-    fn allow_marks(self, _marks: &MarkSet<MD>) -> bool {
-        self._allow_marks()
-    }
-
     fn allows_mark_type(self, _mark_type: MarkdownMarkType) -> bool {
         self._allow_marks()
     }
@@ -83,7 +135,26 @@ impl NodeType<MD> for MarkdownNodeType {
                 | Self::CodeBlock
                 | Self::OrderedList
                 | Self::BulletList
-        )
+        ) || self.is_container()
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Doc => "doc",
+            Self::Heading => "heading",
+            Self::CodeBlock => "code_block",
+            Self::Text => "text",
+            Self::Blockquote => "blockquote",
+            Self::Paragraph => "paragraph",
+            Self::BulletList => "bullet_list",
+            Self::OrderedList => "ordered_list",
+            Self::ListItem => "list_item",
+            Self::HorizontalRule => "horizontal_rule",
+            Self::HardBreak => "hard_break",
+            Self::Image => "image",
+            #[cfg(feature = "directives")]
+            Self::Container => "container",
+        }
     }
 
     fn content_match(self) -> MarkdownContentMatch {
@@ -100,6 +171,26 @@ impl NodeType<MD> for MarkdownNodeType {
             Self::HorizontalRule => MarkdownContentMatch::Empty,
             Self::HardBreak => MarkdownContentMatch::Empty,
             Self::Image => MarkdownContentMatch::Empty,
+            #[cfg(feature = "directives")]
+            Self::Container => MarkdownContentMatch::BlockPlus,
+        }
+    }
+
+    fn create(self, content: Fragment<MD>) -> Option<MarkdownNode> {
+        match self {
+            Self::Doc => Some(doc(content)),
+            Self::Heading => Some(h(1, content)),
+            Self::CodeBlock => Some(code_block("", content)),
+            Self::Blockquote => Some(blockquote(content)),
+            Self::Paragraph => Some(p(content)),
+            Self::BulletList => Some(ul(content)),
+            Self::OrderedList => Some(ol(1, content)),
+            Self::ListItem => Some(li(content)),
+            Self::HorizontalRule => Some(MarkdownNode::HorizontalRule),
+            Self::HardBreak => Some(MarkdownNode::HardBreak),
+            Self::Text | Self::Image => None,
+            #[cfg(feature = "directives")]
+            Self::Container => None,
         }
     }
 
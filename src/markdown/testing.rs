@@ -0,0 +1,309 @@
+//! # Testing
+//!
+//! This module contains `proptest` strategies for arbitrary markdown documents, to be used in
+//! property tests. All generated nodes are valid in the schema, but not every one of them
+//! survives a round-trip through markdown.
+use super::{
+    helper::{blockquote, br, code_block, doc, h, hr, img, li, marked, ol, p, task, ul},
+    LinkAttrs, MarkdownLinkType, MarkdownMark, MarkdownNode, MD,
+};
+use crate::model::{Fragment, Node};
+use crate::transform::ReplaceStep;
+use proptest::{prelude::*, sample::Index};
+
+/// Letters, markdown punctuation, non-ASCII letters and a character outside of the BMP, which
+/// takes two utf-16 code units
+const CHARS: &str = "abcxyzAZ01  .*_`#![]-\\éß\u{1F60A}";
+
+/// Strings with between `min` and `max` characters
+fn arb_string(min: usize, max: usize) -> impl Strategy<Value = String> + Clone {
+    let chars: Vec<char> = CHARS.chars().collect();
+    prop::collection::vec(prop::sample::select(chars), min..=max)
+        .prop_map(|chars| chars.into_iter().collect())
+}
+
+/// Lowercase words with between `min` and `max` letters
+fn arb_word(min: usize, max: usize) -> impl Strategy<Value = String> + Clone {
+    prop::collection::vec(proptest::char::range('a', 'z'), min..=max)
+        .prop_map(|chars| chars.into_iter().collect())
+}
+
+fn arb_marks() -> impl Strategy<Value = Vec<MarkdownMark>> {
+    let link = prop::option::of(arb_word(1, 8)).prop_map(|href| {
+        href.map(|href| MarkdownMark::Link {
+            attrs: LinkAttrs {
+                href: format!("https://{}.example", href),
+                title: String::new(),
+                link_type: MarkdownLinkType::Inline,
+            },
+        })
+    });
+    (any::<[bool; 3]>(), link).prop_map(|([strong, em, code], link)| {
+        let marks = [
+            (strong, MarkdownMark::strong()),
+            (em, MarkdownMark::em()),
+            (code, MarkdownMark::code()),
+        ];
+        let marks = marks.iter().filter(|(on, _)| *on).map(|(_, mark)| mark);
+        marks.cloned().chain(link).collect()
+    })
+}
+
+/// A non-empty text node with any combination of marks
+pub fn arb_text_node() -> BoxedStrategy<MarkdownNode> {
+    (arb_string(1, 12), arb_marks())
+        .prop_map(|(text, marks)| marked(&marks, &text))
+        .boxed()
+}
+
+fn arb_inline(breaks: bool) -> BoxedStrategy<Fragment<MD>> {
+    let image = (arb_word(1, 8), arb_string(0, 8), arb_string(0, 8))
+        .prop_map(|(src, alt, title)| img(&format!("{}.png", src), &alt, &title));
+    let node = if breaks {
+        prop_oneof![8 => arb_text_node(), 1 => image, 1 => Just(br())].boxed()
+    } else {
+        prop_oneof![8 => arb_text_node(), 1 => image].boxed()
+    };
+    // Appending joins adjacent text nodes with the same marks
+    prop::collection::vec(node, 0..8)
+        .prop_map(|nodes| {
+            nodes.into_iter().fold(Fragment::new(), |fragment, node| {
+                fragment.append(Fragment::from((node,)))
+            })
+        })
+        .boxed()
+}
+
+/// The content of a paragraph: text nodes, images and hard breaks
+pub fn arb_inline_fragment() -> BoxedStrategy<Fragment<MD>> {
+    arb_inline(true)
+}
+
+/// Words with at most one mark each, separated by spaces. Spaces between words with different
+/// marks are left unmarked, so that the markdown delimiters are always flanking.
+fn arb_simple_inline() -> BoxedStrategy<Fragment<MD>> {
+    let link = MarkdownMark::Link {
+        attrs: LinkAttrs {
+            href: "https://example.com".to_owned(),
+            title: String::new(),
+            link_type: MarkdownLinkType::Inline,
+        },
+    };
+    let mark = prop_oneof![
+        3 => Just(None),
+        1 => Just(Some(MarkdownMark::strong())),
+        1 => Just(Some(MarkdownMark::em())),
+        1 => Just(Some(MarkdownMark::code())),
+        1 => Just(Some(link)),
+    ];
+    prop::collection::vec((arb_word(1, 8), mark), 1..8)
+        .prop_map(|words| {
+            let text = |mark: &Option<MarkdownMark>, text: &str| {
+                let marks: Vec<_> = mark.iter().cloned().collect();
+                Fragment::from((marked(&marks, text),))
+            };
+            let mut fragment = Fragment::new();
+            for (i, (word, mark)) in words.iter().enumerate() {
+                if i > 0 {
+                    let space_mark = if &words[i - 1].1 == mark { mark } else { &None };
+                    fragment = fragment.append(text(space_mark, " "));
+                }
+                fragment = fragment.append(text(mark, word));
+            }
+            fragment
+        })
+        .boxed()
+}
+
+fn arb_textblock(roundtrip: bool) -> BoxedStrategy<MarkdownNode> {
+    let (inline, heading, line) = if roundtrip {
+        let line = prop::collection::vec(arb_word(1, 8), 1..=4).prop_map(|words| words.join(" "));
+        (arb_simple_inline(), arb_simple_inline(), line.boxed())
+    } else {
+        (
+            arb_inline_fragment(),
+            arb_inline(false),
+            arb_string(0, 12).boxed(),
+        )
+    };
+    let lines = prop::collection::vec(line, 0..4).prop_map(|lines| lines.join("\n"));
+    prop_oneof![
+        3 => inline.prop_map(p),
+        1 => (1u8..=6, heading).prop_map(|(level, content)| h(level, content)),
+        1 => (arb_word(0, 6), lines).prop_map(|(params, code)| {
+            if code.is_empty() {
+                code_block(&params, Vec::<MarkdownNode>::new())
+            } else {
+                code_block(&params, code.as_str())
+            }
+        }),
+    ]
+    .boxed()
+}
+
+/// Two lists of the same kind next to each other are parsed as one
+fn no_adjacent_lists(blocks: &[MarkdownNode]) -> bool {
+    blocks.windows(2).all(|w| {
+        !matches!(
+            (&w[0], &w[1]),
+            (MarkdownNode::BulletList(_), MarkdownNode::BulletList(_))
+                | (MarkdownNode::OrderedList(_), MarkdownNode::OrderedList(_))
+        )
+    })
+}
+
+/// Up to `max` blocks, and at least one if `min_one` is set. For a round trip, lists of the same
+/// kind are never next to each other.
+fn arb_blocks(
+    block: BoxedStrategy<MarkdownNode>,
+    min_one: bool,
+    max: usize,
+    roundtrip: bool,
+) -> BoxedStrategy<Vec<MarkdownNode>> {
+    let blocks = prop::collection::vec(block, usize::from(min_one)..max);
+    if roundtrip {
+        blocks
+            .prop_filter("adjacent lists", |blocks| no_adjacent_lists(blocks))
+            .boxed()
+    } else {
+        blocks.boxed()
+    }
+}
+
+fn arb_block_with(roundtrip: bool) -> BoxedStrategy<MarkdownNode> {
+    let leaf = prop_oneof![4 => arb_textblock(roundtrip), 1 => Just(hr())];
+    leaf.prop_recursive(3, 32, 4, move |block| {
+        let first = if roundtrip {
+            arb_simple_inline()
+        } else {
+            arb_inline_fragment()
+        };
+        let item = (
+            prop::option::of(any::<bool>()),
+            first,
+            arb_blocks(block.clone(), false, 3, roundtrip),
+        )
+            .prop_map(|(checked, first, rest)| {
+                let content: Vec<_> = std::iter::once(p(first)).chain(rest).collect();
+                match checked {
+                    Some(checked) => task(checked, content),
+                    None => li(content),
+                }
+            });
+        // A list with a single paragraph in a single item is tight, which isn't kept
+        let min_items = if roundtrip { 2 } else { 1 };
+        let items = prop::collection::vec(item, min_items..4).boxed();
+        prop_oneof![
+            arb_blocks(block, true, 4, roundtrip).prop_map(blockquote),
+            items.clone().prop_map(ul),
+            (1usize..10, items).prop_map(|(order, items)| ol(order, items)),
+        ]
+    })
+    .boxed()
+}
+
+/// A block node, i.e. a textblock, a horizontal rule, or a blockquote or list nested up to
+/// three levels deep
+pub fn arb_block() -> BoxedStrategy<MarkdownNode> {
+    arb_block_with(false)
+}
+
+/// A document with up to five blocks
+pub fn arb_doc() -> BoxedStrategy<MarkdownNode> {
+    prop::collection::vec(arb_block(), 1..6)
+        .prop_map(doc)
+        .boxed()
+}
+
+/// A document that survives a round trip through markdown unchanged. Its text consists of
+/// lowercase words with at most one mark each, and it has no adjacent lists of the same kind or
+/// lists with a single item.
+pub fn arb_roundtrip_doc() -> BoxedStrategy<MarkdownNode> {
+    arb_blocks(arb_block_with(true), true, 6, true)
+        .prop_map(doc)
+        .boxed()
+}
+
+/// A document and a valid step that replaces a range in it with a slice of another document.
+///
+/// The positions are picked with an [`Index`], so that they shrink independently of the
+/// documents. The end of the replaced range is picked among the positions at the depth that
+/// the open end of the slice needs.
+pub fn arb_replace() -> BoxedStrategy<(MarkdownNode, ReplaceStep<MD>)> {
+    fn depth(node: &MarkdownNode, pos: usize) -> Option<usize> {
+        node.resolve(pos).ok().map(|rp| rp.depth)
+    }
+
+    (arb_doc(), arb_doc(), any::<[Index; 4]>())
+        .prop_filter_map("the slice doesn't fit", |(doc, source, [a, b, c, d])| {
+            let source_size = source.content_size() + 1;
+            let (a, b) = (a.index(source_size), b.index(source_size));
+            let (start, end) = (a.min(b), a.max(b));
+            depth(&source, start)?;
+            depth(&source, end)?;
+            let slice = source.slice(start..end, false).ok()?;
+
+            let size = doc.content_size() + 1;
+            let from = c.index(size);
+            let to_depth = (depth(&doc, from)? + slice.open_end).checked_sub(slice.open_start)?;
+            let ends: Vec<usize> = (from..size)
+                .filter(|&pos| depth(&doc, pos) == Some(to_depth))
+                .collect();
+            let to = *ends.get(d.index(ends.len().max(1)))?;
+            let step = ReplaceStep::between(&doc, from, to, slice).ok()?;
+            Some((doc, step))
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{arb_block, arb_doc};
+    use crate::markdown::MarkdownNode;
+    use crate::model::{Node, NodeType};
+    use proptest::prelude::*;
+
+    fn assert_valid(node: &MarkdownNode) {
+        for (_, _, child) in node.walk() {
+            if let Some(content) = child.content() {
+                assert!(child.r#type().valid_content(content), "{:?}", child);
+            }
+        }
+    }
+
+    fn doc_and_range() -> impl Strategy<Value = (MarkdownNode, usize, usize)> {
+        arb_doc().prop_flat_map(|doc| {
+            let size = doc.content_size();
+            (Just(doc), 0..=size, 0..=size)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_valid(block in arb_block()) {
+            assert_valid(&block);
+        }
+
+        #[test]
+        fn test_slice_replace((doc, a, b) in doc_and_range()) {
+            let (from, to) = (a.min(b), a.max(b));
+            prop_assume!(doc.resolve(from).is_ok() && doc.resolve(to).is_ok(), "split character");
+            let slice = doc.slice(from..to, false).unwrap();
+            let replaced = doc.replace(from..to, &slice).unwrap();
+            prop_assert_eq!(replaced.text_content(), doc.text_content());
+            prop_assert_eq!(replaced, doc);
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    proptest! {
+        #[test]
+        fn test_replace_invert((doc, step) in super::arb_replace()) {
+            use crate::transform::StepKind;
+
+            let applied = step.apply(&doc).unwrap();
+            let inverted = step.invert(&doc).unwrap();
+            prop_assert_eq!(inverted.apply(&applied).unwrap(), doc);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use super::{MarkdownMark, MarkdownNode, MD};
+use crate::model::{AttrNode, Block, Fragment, Leaf, Node};
+use crate::util;
+
+/// Turn a markdown document into HTML
+///
+/// The output follows the HTML that `pulldown_cmark` writes for the same markdown: paragraphs in
+/// tight lists have no `<p>` tags, and the first word of a code block's params becomes its
+/// `language-*` class. Text and attribute values are escaped.
+pub fn to_html(doc: &MarkdownNode) -> String {
+    let mut buf = String::with_capacity(doc.node_size() * 2);
+    write_node(&mut buf, doc, false);
+    buf
+}
+
+fn write_blocks(buf: &mut String, content: &Fragment<MD>, tight: bool) {
+    for child in content.children() {
+        write_node(buf, child, tight);
+    }
+}
+
+/// Start a block on a new line
+fn start_block(buf: &mut String, tag: &str) {
+    if !buf.is_empty() && !buf.ends_with('\n') {
+        buf.push('\n');
+    }
+    buf.push_str(tag);
+}
+
+fn write_node(buf: &mut String, node: &MarkdownNode, tight: bool) {
+    match node {
+        MarkdownNode::Doc(AttrNode { content, .. }) => write_blocks(buf, content, false),
+        MarkdownNode::Heading(AttrNode { attrs, content }) => {
+            start_block(buf, &format!("<h{}>", attrs.level));
+            write_inline(buf, content.children());
+            buf.push_str(&format!("</h{}>\n", attrs.level));
+        }
+        MarkdownNode::CodeBlock(AttrNode { attrs, content }) => {
+            match attrs.params.split(' ').next().unwrap_or("") {
+                "" => start_block(buf, "<pre><code>"),
+                lang => {
+                    start_block(buf, "<pre><code class=\"language-");
+                    escape_html(buf, lang);
+                    buf.push_str("\">");
+                }
+            }
+            for child in content.children() {
+                if let Some(text) = child.text_node() {
+                    escape_html(buf, text.text.as_str());
+                }
+            }
+            // The code doesn't include the newline before the closing fence
+            if content.size() > 0 {
+                buf.push('\n');
+            }
+            buf.push_str("</code></pre>\n");
+        }
+        MarkdownNode::Text(_) | MarkdownNode::HardBreak | MarkdownNode::Image(_) => {
+            write_inline(buf, std::slice::from_ref(node));
+        }
+        MarkdownNode::Blockquote(Block { content }) => {
+            start_block(buf, "<blockquote>\n");
+            write_blocks(buf, content, false);
+            buf.push_str("</blockquote>\n");
+        }
+        MarkdownNode::Paragraph(Block { content }) => {
+            if tight {
+                write_inline(buf, content.children());
+            } else {
+                start_block(buf, "<p>");
+                write_inline(buf, content.children());
+                buf.push_str("</p>\n");
+            }
+        }
+        MarkdownNode::BulletList(AttrNode { attrs, content }) => {
+            start_block(buf, "<ul>\n");
+            write_blocks(buf, content, attrs.tight);
+            buf.push_str("</ul>\n");
+        }
+        MarkdownNode::OrderedList(AttrNode { attrs, content }) => {
+            if attrs.order == 1 {
+                start_block(buf, "<ol>\n");
+            } else {
+                start_block(buf, &format!("<ol start=\"{}\">\n", attrs.order));
+            }
+            write_blocks(buf, content, attrs.tight);
+            buf.push_str("</ol>\n");
+        }
+        MarkdownNode::ListItem(AttrNode { attrs, content }) => {
+            start_block(buf, "<li>");
+            match attrs.checked {
+                Some(true) => {
+                    buf.push_str("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n")
+                }
+                Some(false) => buf.push_str("<input disabled=\"\" type=\"checkbox\"/>\n"),
+                None => {}
+            }
+            write_blocks(buf, content, tight);
+            buf.push_str("</li>\n");
+        }
+        MarkdownNode::HorizontalRule => start_block(buf, "<hr />\n"),
+        #[cfg(feature = "directives")]
+        MarkdownNode::Container(AttrNode { attrs, content }) => {
+            start_block(buf, "<div class=\"");
+            escape_html(buf, &attrs.kind);
+            buf.push_str("\">\n");
+            write_blocks(buf, content, false);
+            buf.push_str("</div>\n");
+        }
+    }
+}
+
+/// Write inline content, opening and closing the tags for marks as they change between nodes
+fn write_inline(buf: &mut String, children: &[MarkdownNode]) {
+    let mut open: Vec<&MarkdownMark> = Vec::new();
+    for child in children {
+        let marks = child.marks().map_or(&[][..], |marks| marks.as_slice());
+        let keep = open
+            .iter()
+            .zip(marks)
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        for mark in open.drain(keep..).rev() {
+            close_mark(buf, mark);
+        }
+        for mark in &marks[keep..] {
+            open_mark(buf, mark);
+            open.push(mark);
+        }
+
+        match child {
+            MarkdownNode::Text(text_node) => escape_html(buf, text_node.text.as_str()),
+            MarkdownNode::HardBreak => buf.push_str("<br />\n"),
+            MarkdownNode::Image(Leaf { attrs }) => {
+                buf.push_str("<img src=\"");
+                escape_html(buf, &attrs.src);
+                buf.push_str("\" alt=\"");
+                escape_html(buf, &attrs.alt);
+                if !attrs.title.is_empty() {
+                    buf.push_str("\" title=\"");
+                    escape_html(buf, &attrs.title);
+                }
+                buf.push_str("\" />");
+            }
+            _ => write_node(buf, child, false),
+        }
+    }
+    for mark in open.into_iter().rev() {
+        close_mark(buf, mark);
+    }
+}
+
+fn open_mark(buf: &mut String, mark: &MarkdownMark) {
+    match mark {
+        MarkdownMark::Strong { .. } => buf.push_str("<strong>"),
+        MarkdownMark::Em { .. } => buf.push_str("<em>"),
+        MarkdownMark::Code { .. } => buf.push_str("<code>"),
+        MarkdownMark::Link { attrs } => {
+            buf.push_str("<a href=\"");
+            escape_html(buf, &attrs.href);
+            if !attrs.title.is_empty() {
+                buf.push_str("\" title=\"");
+                escape_html(buf, &attrs.title);
+            }
+            buf.push_str("\">");
+        }
+    }
+}
+
+fn close_mark(buf: &mut String, mark: &MarkdownMark) {
+    buf.push_str(match mark {
+        MarkdownMark::Strong { .. } => "</strong>",
+        MarkdownMark::Em { .. } => "</em>",
+        MarkdownMark::Code { .. } => "</code>",
+        MarkdownMark::Link { .. } => "</a>",
+    });
+}
+
+fn escape_html(buf: &mut String, text: &str) {
+    buf.push_str(&util::escape_html(text));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_html;
+    use crate::markdown::helper::*;
+    use crate::markdown::MarkdownMark;
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(to_html(&doc(p("Hello"))), "<p>Hello</p>\n");
+        assert_eq!(to_html(&doc(h2("Title"))), "<h2>Title</h2>\n");
+        assert_eq!(
+            to_html(&doc(blockquote(p("quote")))),
+            "<blockquote>\n<p>quote</p>\n</blockquote>\n"
+        );
+        assert_eq!(
+            to_html(&doc(code_block("rust main", node("fn main() {}")))),
+            "<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n"
+        );
+        assert_eq!(
+            to_html(&doc(code_block("", vec![]))),
+            "<pre><code></code></pre>\n"
+        );
+        assert_eq!(
+            to_html(&doc(vec![p("a"), hr(), p("b")])),
+            "<p>a</p>\n<hr />\n<p>b</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(
+            to_html(&doc(ul(vec![li(p("a")), li(p("b"))]))),
+            "<ul>\n<li>\n<p>a</p>\n</li>\n<li>\n<p>b</p>\n</li>\n</ul>\n"
+        );
+        assert_eq!(
+            to_html(&doc(ol(
+                3,
+                vec![task(true, p("done")), task(false, p("todo"))]
+            ))),
+            "<ol start=\"3\">\n\
+            <li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n<p>done</p>\n</li>\n\
+            <li><input disabled=\"\" type=\"checkbox\"/>\n<p>todo</p>\n</li>\n</ol>\n"
+        );
+    }
+
+    #[cfg(feature = "cmark")]
+    #[test]
+    fn test_matches_cmark() {
+        use crate::markdown::from_markdown;
+        use pulldown_cmark::html::push_html;
+
+        let md = "# Title\n\nSome *em*, **strong and [linked](https://example.com \"t\")**, \
+                  `code`\\\nand ![alt](a.png).\n\n> quote\n\n- tight\n- list\n  1. nested\n\n\
+                  ---\n\n```rust\nlet x = 1;\n```\n";
+        let doc = from_markdown(md).unwrap();
+        let mut expected = String::new();
+        push_html(&mut expected, pulldown_cmark::Parser::new(md));
+        assert_eq!(to_html(&doc), expected);
+    }
+
+    #[test]
+    fn test_marks() {
+        assert_eq!(
+            to_html(&doc(p(vec![
+                node("a "),
+                marked(&[MarkdownMark::strong(), MarkdownMark::em()], "b"),
+                strong(" c"),
+                code("d"),
+                link("https://example.com/?a=1&b=2", "", "e"),
+            ]))),
+            "<p>a <strong><em>b</em> c</strong><code>d</code>\
+             <a href=\"https://example.com/?a=1&amp;b=2\">e</a></p>\n"
+        );
+        assert_eq!(
+            to_html(&doc(p(vec![node("a"), br(), img("x.png", "an \"x\"", "")]))),
+            "<p>a<br />\n<img src=\"x.png\" alt=\"an &quot;x&quot;\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            to_html(&doc(p("<script>alert(\"&\")</script>"))),
+            "<p>&lt;script&gt;alert(&quot;&amp;&quot;)&lt;/script&gt;</p>\n"
+        );
+        assert_eq!(
+            to_html(&doc(code_block("a<b", node("<b>")))),
+            "<pre><code class=\"language-a&lt;b\">&lt;b&gt;\n</code></pre>\n"
+        );
+    }
+}
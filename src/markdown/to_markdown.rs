@@ -1,8 +1,9 @@
-use super::{MarkdownMark, MarkdownNode, MD};
+use super::{MarkdownLinkType, MarkdownMark, MarkdownNode, MD};
 use crate::model::{AttrNode, Block, Fragment, Leaf, Node};
 use displaydoc::Display;
 use pulldown_cmark::{CodeBlockKind, CowStr, Event, InlineStr, LinkType, Tag};
-use pulldown_cmark_to_cmark::cmark;
+use pulldown_cmark_to_cmark::{cmark, State};
+use std::fmt::Write;
 use thiserror::Error;
 
 /// Possible error when generating markdown
@@ -18,14 +19,213 @@ impl From<std::fmt::Error> for ToMarkdownError {
     }
 }
 
+/// The character used for code fences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeFenceChar {
+    /// `` ` ``, unless the info string contains a backtick
+    Backtick,
+    /// `~`
+    Tilde,
+}
+
+impl CodeFenceChar {
+    fn as_char(self) -> char {
+        match self {
+            Self::Backtick => '`',
+            Self::Tilde => '~',
+        }
+    }
+}
+
+/// Options for [`to_markdown_with_options`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToMarkdownOptions {
+    /// The character used for code fences (default: [`CodeFenceChar::Backtick`])
+    ///
+    /// A code block whose info string contains a backtick always uses `~`, since a backtick
+    /// fence would end at the info string.
+    pub code_fence_char: CodeFenceChar,
+    /// The minimum number of characters in a code fence (default: 4)
+    ///
+    /// Fences are made longer than any run of the fence character in the code.
+    pub min_fence_length: usize,
+}
+
+impl Default for ToMarkdownOptions {
+    fn default() -> Self {
+        Self {
+            code_fence_char: CodeFenceChar::Backtick,
+            min_fence_length: 4,
+        }
+    }
+}
+
+/// Turn a markdown document into the `pulldown_cmark` events that describe it, e.g. to feed
+/// them to a different writer.
+///
+/// The text of a code block ends with a newline, like the events produced by the parser.
+pub fn to_markdown_events(doc: &MarkdownNode) -> Vec<Event<'_>> {
+    MarkdownSerializer::new(doc).collect()
+}
+
 /// Turn a markdown document into a string
 pub fn to_markdown(doc: &MarkdownNode) -> Result<String, ToMarkdownError> {
+    to_markdown_with_options(doc, &ToMarkdownOptions::default())
+}
+
+/// Turn a markdown document into a string, using the given options
+pub fn to_markdown_with_options(
+    doc: &MarkdownNode,
+    options: &ToMarkdownOptions,
+) -> Result<String, ToMarkdownError> {
     let mut buf = String::with_capacity(doc.node_size() + 128);
-    let events = MarkdownSerializer::new(doc);
-    cmark(events, &mut buf, None)?;
+    let mut events = write_autolinks(to_markdown_events(doc)).into_iter();
+    let mut pending = Vec::new();
+    let mut state = None;
+
+    // The cmark writer always uses four backticks for code fences, so we write code blocks
+    // ourselves and pass the writer state around them.
+    while let Some(event) = events.next() {
+        if let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = event {
+            let mut code = String::new();
+            for event in &mut events {
+                match event {
+                    Event::Text(text) => code.push_str(&text),
+                    Event::End(_) => break,
+                    _ => {}
+                }
+            }
+            let mut st = cmark(pending.drain(..), &mut buf, state.take())?;
+            write_code_block(&mut buf, &mut st, &info, &code, options)?;
+            state = Some(st);
+        } else {
+            pending.push(event);
+        }
+    }
+    cmark(pending.into_iter(), &mut buf, state)?;
     Ok(buf)
 }
 
+/// Whether `target` can be written as a CommonMark URI autolink, i.e. it is a scheme followed by
+/// a colon and no whitespace, control characters, `<` or `>`
+fn is_uri_autolink(target: &str) -> bool {
+    let (scheme, rest) = match target.split_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let mut scheme_chars = scheme.chars();
+    matches!(scheme_chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && (2..=32).contains(&scheme.len())
+        && scheme_chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+        && !rest
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>'))
+}
+
+/// Whether `target` can be written as a CommonMark email autolink
+fn is_email_autolink(target: &str) -> bool {
+    let (local, domain) = match target.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let is_label = |label: &str| {
+        (1..=63).contains(&label.len())
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+        && domain.split('.').all(is_label)
+}
+
+/// The cmark writer writes every link as an inline link, so we turn autolinks whose text is
+/// their target into inline HTML, if the target is valid in an autolink. The empty text after it
+/// keeps the writer from adding a newline.
+fn write_autolinks(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut result = Vec::with_capacity(events.len());
+    let mut i = 0;
+    while i < events.len() {
+        if let Some(
+            [Event::Start(Tag::Link(link_type, href, _)), Event::Text(text), Event::End(_)],
+        ) = events.get(i..i + 3)
+        {
+            let target = match link_type {
+                LinkType::Autolink => Some(href.as_ref()).filter(|href| is_uri_autolink(href)),
+                LinkType::Email => href
+                    .strip_prefix("mailto:")
+                    .filter(|email| is_email_autolink(email)),
+                _ => None,
+            };
+            if target == Some(text.as_ref()) {
+                let html = format!("<{}>", text);
+                result.push(Event::Html(CowStr::Boxed(html.into_boxed_str())));
+                result.push(Event::Text(CowStr::Borrowed("")));
+                i += 3;
+                continue;
+            }
+        }
+        result.push(events[i].clone());
+        i += 1;
+    }
+    result
+}
+
+fn write_padding(buf: &mut String, state: &State) {
+    for padding in &state.padding {
+        buf.push_str(padding);
+    }
+}
+
+fn write_code_block(
+    buf: &mut String,
+    state: &mut State,
+    info: &str,
+    code: &str,
+    options: &ToMarkdownOptions,
+) -> Result<(), ToMarkdownError> {
+    let fence_char = match options.code_fence_char {
+        CodeFenceChar::Backtick if info.contains('`') => CodeFenceChar::Tilde,
+        fence_char => fence_char,
+    }
+    .as_char();
+    let longest_run = code
+        .split(|c| c != fence_char)
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence_len = usize::max(usize::max(options.min_fence_length, 3), longest_run + 1);
+    let fence = fence_char.to_string().repeat(fence_len);
+
+    if state.newlines_before_start == 0 && !buf.is_empty() {
+        buf.push('\n');
+        write_padding(buf, state);
+    }
+    while state.newlines_before_start > 0 {
+        state.newlines_before_start -= 1;
+        buf.push('\n');
+        write_padding(buf, state);
+    }
+
+    write!(buf, "{}{}", fence, info)?;
+    // The code ends with the newline before the closing fence, so an empty block has no lines
+    let code = code.strip_suffix('\n').unwrap_or(code);
+    if !code.is_empty() {
+        for line in code.split('\n') {
+            buf.push('\n');
+            write_padding(buf, state);
+            buf.push_str(line);
+        }
+    }
+    buf.push('\n');
+    write_padding(buf, state);
+    buf.push_str(&fence);
+    state.newlines_before_start = 2;
+    Ok(())
+}
+
 struct MarkdownSerializer<'a> {
     inner: Vec<(&'a MarkdownNode, usize)>,
     marks: Vec<&'a MarkdownMark>,
@@ -42,13 +242,17 @@ impl<'a> MarkdownSerializer<'a> {
     }
 }
 
-fn mark_tag(mark: &MarkdownMark) -> Tag {
+fn mark_tag(mark: &MarkdownMark) -> Tag<'_> {
     match mark {
-        MarkdownMark::Strong => Tag::Strong,
-        MarkdownMark::Em => Tag::Emphasis,
-        MarkdownMark::Code => unimplemented!("Should not be pushed on the mark stack"),
+        MarkdownMark::Strong { .. } => Tag::Strong,
+        MarkdownMark::Em { .. } => Tag::Emphasis,
+        MarkdownMark::Code { .. } => unimplemented!("Should not be pushed on the mark stack"),
         MarkdownMark::Link { attrs } => Tag::Link(
-            LinkType::Inline,
+            match attrs.link_type {
+                MarkdownLinkType::Inline => LinkType::Inline,
+                MarkdownLinkType::Autolink => LinkType::Autolink,
+                MarkdownLinkType::Email => LinkType::Email,
+            },
             CowStr::Borrowed(attrs.href.as_str()),
             CowStr::Borrowed(attrs.title.as_str()),
         ),
@@ -56,6 +260,14 @@ fn mark_tag(mark: &MarkdownMark) -> Tag {
 }
 
 impl<'a> MarkdownSerializer<'a> {
+    /// Returns the start of a paragraph that contains just the given fence line
+    #[cfg(feature = "directives")]
+    fn fence_paragraph(&mut self, fence: CowStr<'a>) -> Event<'a> {
+        self.stack.push(Event::End(Tag::Paragraph));
+        self.stack.push(Event::Text(fence));
+        Event::Start(Tag::Paragraph)
+    }
+
     fn process_content(
         &mut self,
         index: usize,
@@ -122,7 +334,7 @@ impl<'a> Iterator for MarkdownSerializer<'a> {
 
         if let Some((node, index)) = self.inner.pop() {
             match node {
-                MarkdownNode::Doc(Block { content }) => {
+                MarkdownNode::Doc(AttrNode { content, .. }) => {
                     self.process_content(index, content, node);
                     self.next()
                 }
@@ -137,7 +349,7 @@ impl<'a> Iterator for MarkdownSerializer<'a> {
                     })
                 }
                 MarkdownNode::Text(text_node) => {
-                    if let Some(last) = self.marks.last().map(|n| *n) {
+                    if let Some(last) = self.marks.last().copied() {
                         if !text_node.marks.contains(last) {
                             self.inner.push((node, index));
                             self.marks.pop();
@@ -146,7 +358,7 @@ impl<'a> Iterator for MarkdownSerializer<'a> {
                     }
                     let mut is_code = false;
                     for mark in &text_node.marks {
-                        if *mark == MarkdownMark::Code {
+                        if let MarkdownMark::Code { .. } = mark {
                             is_code = true;
                         } else if !self.marks.contains(&mark) {
                             self.inner.push((node, index));
@@ -174,8 +386,13 @@ impl<'a> Iterator for MarkdownSerializer<'a> {
                         Tag::List(Some(attrs.order as u64))
                     })
                 }
-                MarkdownNode::ListItem(Block { content }) => {
-                    self.process_attr_node(index, content, &(), node, |()| Tag::Item)
+                MarkdownNode::ListItem(AttrNode { attrs, content }) => {
+                    let event = self.process_attr_node(index, content, attrs, node, |_| Tag::Item);
+                    if let (Some(Event::Start(Tag::Item)), Some(checked)) = (&event, attrs.checked)
+                    {
+                        self.stack.push(Event::TaskListMarker(checked));
+                    }
+                    event
                 }
                 MarkdownNode::HorizontalRule => Some(Event::Rule),
                 MarkdownNode::HardBreak => {
@@ -183,13 +400,36 @@ impl<'a> Iterator for MarkdownSerializer<'a> {
                     Some(Event::HardBreak)
                 }
                 MarkdownNode::Image(Leaf { attrs }) => {
-                    self.process_attr_node(index, Fragment::EMPTY_REF, &(), node, |()| {
-                        Tag::Image(
-                            LinkType::Inline,
-                            CowStr::Borrowed(attrs.src.as_str()),
-                            CowStr::Borrowed(attrs.src.as_str()),
-                        )
-                    })
+                    let event =
+                        self.process_attr_node(index, Fragment::EMPTY_REF, &(), node, |()| {
+                            Tag::Image(
+                                LinkType::Inline,
+                                CowStr::Borrowed(attrs.src.as_str()),
+                                CowStr::Borrowed(attrs.title.as_str()),
+                            )
+                        });
+                    // The alt text is the content of the image tag
+                    if matches!(&event, Some(Event::Start(Tag::Image(..)))) && !attrs.alt.is_empty()
+                    {
+                        self.stack
+                            .push(Event::Text(CowStr::Borrowed(attrs.alt.as_str())));
+                    }
+                    event
+                }
+                #[cfg(feature = "directives")]
+                MarkdownNode::Container(AttrNode { attrs, content }) => {
+                    // Index 0 is the opening fence, the children follow from index 1
+                    if index == 0 {
+                        self.inner.push((node, 1));
+                        let fence = format!(":::{}", attrs.kind);
+                        Some(self.fence_paragraph(CowStr::Boxed(fence.into_boxed_str())))
+                    } else if let Some(child) = content.maybe_child(index - 1) {
+                        self.inner.push((node, index + 1));
+                        self.inner.push((child, 0));
+                        self.next()
+                    } else {
+                        Some(self.fence_paragraph(CowStr::Borrowed(":::")))
+                    }
                 }
             }
         } else {
@@ -201,10 +441,14 @@ impl<'a> Iterator for MarkdownSerializer<'a> {
 #[cfg(test)]
 mod tests {
 
-    use super::to_markdown;
+    use super::{to_markdown, to_markdown_events};
     use crate::markdown::{
-        helper::{blockquote, code_block, doc, h1, h2, node, p, strong},
-        MarkdownNode,
+        from_markdown,
+        helper::{
+            autolink, blockquote, br, code, code_block, doc, h1, h2, hr, img, li, link, marked,
+            node, ol, p, strong, task, ul,
+        },
+        to_markdown_with_options, CodeFenceChar, MarkdownMark, MarkdownNode, ToMarkdownOptions,
     };
 
     fn initial_doc() -> MarkdownNode {
@@ -256,4 +500,193 @@ mod tests {
             line,
         );
     }
+
+    #[test]
+    fn test_nested_ordered_list() {
+        let node = doc(ol(
+            1,
+            vec![
+                li(p("one")),
+                li(vec![p("two"), ol(5, vec![li(p("five")), li(p("six"))])]),
+            ],
+        ));
+        // The writer repeats the start number for every item, which is valid CommonMark
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "1. one\n\n1. two\n   \n   5. five\n   \n   5. six");
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    #[test]
+    fn test_code_fence_length() {
+        let node = doc(vec![code_block("md", "```\nnested\n```"), p("after")]);
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "````md\n```\nnested\n```\n````\n\nafter");
+        assert_eq!(from_markdown(&md), Ok(node.clone()));
+
+        let options = ToMarkdownOptions {
+            code_fence_char: CodeFenceChar::Backtick,
+            min_fence_length: 3,
+        };
+        let node = doc(code_block("rust", "let a = \"````\";"));
+        let md = to_markdown_with_options(&node, &options).unwrap();
+        assert_eq!(md, "`````rust\nlet a = \"````\";\n`````");
+        assert_eq!(from_markdown(&md), Ok(node.clone()));
+
+        let options = ToMarkdownOptions {
+            code_fence_char: CodeFenceChar::Tilde,
+            min_fence_length: 3,
+        };
+        let md = to_markdown_with_options(&node, &options).unwrap();
+        assert_eq!(md, "~~~rust\nlet a = \"````\";\n~~~");
+        assert_eq!(from_markdown(&md), Ok(node));
+
+        let node = doc(code_block("a`b", "code"));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "~~~~a`b\ncode\n~~~~");
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    #[test]
+    fn test_events() {
+        use pulldown_cmark::{CowStr, Event, Tag};
+        let node = doc(p("hi"));
+        assert_eq!(
+            to_markdown_events(&node),
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text(CowStr::Borrowed("hi")),
+                Event::End(Tag::Paragraph),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_leaf_helpers() {
+        let node = doc(vec![
+            p(vec![
+                node("first"),
+                br(),
+                node("second "),
+                img("cat.png", "A cat", "Meow"),
+            ]),
+            hr(),
+            p(vec![node("see "), link("https://example.com", "", "here")]),
+        ]);
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(
+            md,
+            "first  \nsecond ![A cat](cat.png \"Meow\")\n\n---\n\nsee [here](https://example.com)"
+        );
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    #[test]
+    fn test_inline_code() {
+        let node = doc(p(code("let x = 1;")));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "`let x = 1;`");
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    #[test]
+    fn test_marked() {
+        let node = doc(p(vec![
+            node("a "),
+            marked(&[MarkdownMark::strong(), MarkdownMark::em()], "b"),
+        ]));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "a ***b***");
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    #[test]
+    fn test_ordered_list_helper() {
+        let node = doc(ol(3, vec![li(p("three")), li(p("four"))]));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "3. three\n\n3. four");
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    #[test]
+    fn test_empty_code_block() {
+        let node = doc(vec![
+            code_block("rust", Vec::<MarkdownNode>::new()),
+            p("after"),
+        ]);
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "````rust\n````\n\nafter");
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    #[test]
+    fn test_code_block_in_list() {
+        let node = doc(ol(
+            1,
+            li(vec![p("code:"), code_block("", "a\nb"), p("after")]),
+        ));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(
+            md,
+            "1. code:\n   \n   ````\n   a\n   b\n   ````\n   \n   after"
+        );
+    }
+
+    #[test]
+    fn test_autolinks() {
+        let node = doc(p(vec![
+            node("see "),
+            autolink("https://example.com", "https://example.com"),
+            node(" or "),
+            autolink("mailto:me@example.com", "me@example.com"),
+        ]));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "see <https://example.com> or <me@example.com>");
+        assert_eq!(from_markdown(&md), Ok(node));
+
+        // An autolink whose text was changed is written as an inline link
+        let node = doc(p(autolink("https://example.com", "example")));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "[example](https://example.com)");
+        assert_eq!(
+            from_markdown(&md),
+            Ok(doc(p(link("https://example.com", "", "example"))))
+        );
+
+        // Targets that aren't valid in an autolink are written as inline links, too
+        for target in &[
+            "x><img src=x onerror=alert(1)",
+            "https://a b",
+            "mailto:me@exa mple.com",
+            "mailto:me<x>@a",
+        ] {
+            let text = target.trim_start_matches("mailto:");
+            let md = to_markdown(&doc(p(autolink(target, text)))).unwrap();
+            assert_eq!(md, format!("[{}]({})", text, target));
+        }
+    }
+
+    #[test]
+    fn test_task_list() {
+        let node = doc(ul(vec![
+            task(false, p("todo")),
+            task(true, p("done")),
+            li(p("plain")),
+        ]));
+        let md = to_markdown(&node).unwrap();
+        assert_eq!(md, "* [ ] todo\n\n* [x] done\n\n* plain");
+        assert_eq!(from_markdown(&md), Ok(node));
+    }
+
+    mod roundtrip {
+        use crate::markdown::{from_markdown, testing::arb_roundtrip_doc, to_markdown};
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn test_roundtrip(node in arb_roundtrip_doc()) {
+                let md = to_markdown(&node).unwrap();
+                prop_assert_eq!(from_markdown(&md), Ok(node), "{}", md);
+            }
+        }
+    }
 }
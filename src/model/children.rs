@@ -0,0 +1,92 @@
+//! The storage for the children of a fragment.
+//!
+//! With the `rc` feature, the children are shared behind an `Arc`, so that cloning a fragment
+//! (and with it, a node) only bumps a reference count. They are copied when a shared fragment
+//! is modified. Without the feature, they are stored in a plain `Vec`.
+use std::fmt;
+use std::ops::Deref;
+#[cfg(feature = "rc")]
+use std::sync::Arc;
+
+#[cfg(not(feature = "rc"))]
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct Children<N>(Vec<N>);
+
+/// `None` is the empty list, so that it can be created in a `const`.
+#[cfg(feature = "rc")]
+#[derive(PartialEq, Eq)]
+pub(crate) struct Children<N>(Option<Arc<Vec<N>>>);
+
+#[cfg(not(feature = "rc"))]
+impl<N: Clone> Children<N> {
+    pub const EMPTY: Self = Children(Vec::new());
+
+    pub fn make_mut(&mut self) -> &mut Vec<N> {
+        &mut self.0
+    }
+
+    pub fn into_vec(self) -> Vec<N> {
+        self.0
+    }
+}
+
+#[cfg(feature = "rc")]
+impl<N: Clone> Children<N> {
+    pub const EMPTY: Self = Children(None);
+
+    /// Get the children for modification, copying them if they are shared.
+    pub fn make_mut(&mut self) -> &mut Vec<N> {
+        Arc::make_mut(self.0.get_or_insert_with(Default::default))
+    }
+
+    /// Get the children, copying them if they are shared.
+    pub fn into_vec(self) -> Vec<N> {
+        match self.0 {
+            Some(arc) => Arc::try_unwrap(arc).unwrap_or_else(|arc| (*arc).clone()),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "rc")]
+impl<N> Clone for Children<N> {
+    fn clone(&self) -> Self {
+        Children(self.0.clone())
+    }
+}
+
+impl<N> Deref for Children<N> {
+    type Target = [N];
+
+    #[cfg(not(feature = "rc"))]
+    fn deref(&self) -> &[N] {
+        &self.0
+    }
+
+    #[cfg(feature = "rc")]
+    fn deref(&self) -> &[N] {
+        self.0.as_deref().map_or(&[], Vec::as_slice)
+    }
+}
+
+impl<N> From<Vec<N>> for Children<N> {
+    #[cfg(not(feature = "rc"))]
+    fn from(src: Vec<N>) -> Self {
+        Children(src)
+    }
+
+    #[cfg(feature = "rc")]
+    fn from(src: Vec<N>) -> Self {
+        if src.is_empty() {
+            Children(None)
+        } else {
+            Children(Some(Arc::new(src)))
+        }
+    }
+}
+
+impl<N: fmt::Debug> fmt::Debug for Children<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::model::{Fragment, Schema};
+use crate::model::{Fragment, NodeType, Schema};
 use displaydoc::Display;
 use std::ops::RangeBounds;
 use thiserror::Error;
@@ -30,4 +30,23 @@ pub trait ContentMatch<S: Schema>: Copy {
 
     /// Match a node type, returning a match after that node if successful.
     fn match_type(self, r#type: S::NodeType) -> Option<Self>;
+
+    /// True when this match state allows inline content.
+    ///
+    /// By default, this checks whether any inline type of [`Schema::node_types`] matches here.
+    fn inline_content(self) -> bool {
+        S::node_types()
+            .iter()
+            .any(|&r#type| r#type.is_inline() && self.match_type(r#type).is_some())
+    }
+
+    /// The type of node to create when a node has to be inserted here, e.g. to fill a node that
+    /// requires content.
+    ///
+    /// Like in prosemirror, this is the first type that can come next, skipping text, as text
+    /// nodes can't be empty, and nodes with required attributes. Defaults to `None`, i.e. no
+    /// node can be created here.
+    fn default_type(self) -> Option<S::NodeType> {
+        None
+    }
 }
@@ -0,0 +1,100 @@
+use super::{Fragment, Node, Schema};
+
+/// Whether two nodes have the same type, attributes and marks, ignoring their content.
+fn same_markup<S: Schema>(a: &S::Node, b: &S::Node) -> bool {
+    match (a.text_node(), b.text_node()) {
+        (Some(a), Some(b)) => a.marks == b.marks,
+        (None, None) => {
+            a.r#type() == b.r#type() && a.copy(|_| Fragment::new()) == b.copy(|_| Fragment::new())
+        }
+        _ => false,
+    }
+}
+
+/// Whether two nodes are equal, given the size of the first one. Comparing the sizes first is
+/// cheap, since fragments store their size, and avoids comparing whole subtrees that can't be
+/// equal while descending into them.
+fn same_node<S: Schema>(a: &S::Node, b: &S::Node, size_a: usize) -> bool {
+    size_a == b.node_size() && a == b
+}
+
+pub(crate) fn find_diff_start<S: Schema>(
+    a: &Fragment<S>,
+    b: &Fragment<S>,
+    mut pos: usize,
+) -> Option<usize> {
+    for (i, child_a) in a.children().iter().enumerate() {
+        let child_b = match b.maybe_child(i) {
+            Some(child_b) => child_b,
+            None => return Some(pos),
+        };
+        let size = child_a.node_size();
+        if same_node::<S>(child_a, child_b, size) {
+            pos += size;
+            continue;
+        }
+        if !same_markup::<S>(child_a, child_b) {
+            return Some(pos);
+        }
+        if let (Some(text_a), Some(text_b)) = (child_a.text_node(), child_b.text_node()) {
+            let same: usize = (text_a.text.as_str().chars())
+                .zip(text_b.text.as_str().chars())
+                .take_while(|(c_a, c_b)| c_a == c_b)
+                .map(|(c, _)| c.len_utf16())
+                .sum();
+            return Some(pos + same);
+        }
+        if let (Some(content_a), Some(content_b)) = (child_a.content(), child_b.content()) {
+            if let Some(inner) = find_diff_start(content_a, content_b, pos + 1) {
+                return Some(inner);
+            }
+        }
+        pos += size;
+    }
+    if a.child_count() == b.child_count() {
+        None
+    } else {
+        Some(pos)
+    }
+}
+
+pub(crate) fn find_diff_end<S: Schema>(
+    a: &Fragment<S>,
+    b: &Fragment<S>,
+    mut pos_a: usize,
+    mut pos_b: usize,
+) -> Option<(usize, usize)> {
+    let mut children_a = a.children().iter().rev();
+    let mut children_b = b.children().iter().rev();
+    loop {
+        let (child_a, child_b) = match (children_a.next(), children_b.next()) {
+            (Some(child_a), Some(child_b)) => (child_a, child_b),
+            (None, None) => return None,
+            _ => return Some((pos_a, pos_b)),
+        };
+        let size = child_a.node_size();
+        if same_node::<S>(child_a, child_b, size) {
+            pos_a -= size;
+            pos_b -= size;
+            continue;
+        }
+        if !same_markup::<S>(child_a, child_b) {
+            return Some((pos_a, pos_b));
+        }
+        if let (Some(text_a), Some(text_b)) = (child_a.text_node(), child_b.text_node()) {
+            let same: usize = (text_a.text.as_str().chars().rev())
+                .zip(text_b.text.as_str().chars().rev())
+                .take_while(|(c_a, c_b)| c_a == c_b)
+                .map(|(c, _)| c.len_utf16())
+                .sum();
+            return Some((pos_a - same, pos_b - same));
+        }
+        if let (Some(content_a), Some(content_b)) = (child_a.content(), child_b.content()) {
+            if let Some(inner) = find_diff_end(content_a, content_b, pos_a - 1, pos_b - 1) {
+                return Some(inner);
+            }
+        }
+        pos_a -= size;
+        pos_b -= size;
+    }
+}
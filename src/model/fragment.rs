@@ -1,8 +1,12 @@
-use super::{util, Index, Node, Schema, Text};
+use super::{children::Children, diff, util, Index, Node, Schema};
 use derivative::Derivative;
 use displaydoc::Display;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{de::Error as _, Deserialize, Serialize, Serializer};
+use serde_json::Value;
 use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
+use std::iter::FromIterator;
 use std::ops::RangeBounds;
 use thiserror::Error;
 
@@ -12,22 +16,79 @@ pub enum IndexError {
     OutOfBounds(usize),
 }
 
+/// The document is nested deeper than the limit of {0}
+#[derive(Copy, Clone, Debug, Error, Display, PartialEq, Eq)]
+pub struct DepthError(pub usize);
+
+/// The default limit on the nesting of fragments, for [`with_max_depth`] and for the
+/// `max_depth` option of the markdown parser, so that any document the parser accepts can be
+/// read back from its JSON.
+///
+/// In JSON, every level of nesting is an object and a `content` array, so `serde_json`'s
+/// recursion limit of 128 allows about 63 levels. This limit is below that, so it is the one
+/// that rejects documents nested just a bit too deep. Much deeper JSON is still rejected by
+/// `serde_json` first, since nodes are parsed as a whole before their fragments are.
+pub const DEFAULT_MAX_DEPTH: usize = 48;
+
+thread_local! {
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Run `f` with a different limit on the nesting of fragments during deserialization on this
+/// thread, e.g. `with_max_depth(64, || serde_json::from_str::<MarkdownNode>(&json))`.
+///
+/// Many operations on nodes (cloning, comparing, serializing, dropping) recurse into their
+/// children, so a document from an untrusted source that is nested deep enough can overflow the
+/// stack. To prevent that, deserializing a fragment fails with a [`DepthError`] if it is nested
+/// more than [`DEFAULT_MAX_DEPTH`] levels deep, unless a different limit is set here.
+pub fn with_max_depth<R, F: FnOnce() -> R>(max_depth: usize, f: F) -> R {
+    let previous = MAX_DEPTH.with(|max| max.replace(max_depth));
+    let result = f();
+    MAX_DEPTH.with(|max| max.set(previous));
+    result
+}
+
+/// Counts the nesting of fragments that are being deserialized
+struct DepthGuard;
+
+impl DepthGuard {
+    fn enter() -> Result<Self, DepthError> {
+        let max_depth = MAX_DEPTH.with(Cell::get);
+        DEPTH.with(|depth| {
+            if depth.get() >= max_depth {
+                Err(DepthError(max_depth))
+            } else {
+                depth.set(depth.get() + 1);
+                Ok(DepthGuard)
+            }
+        })
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
 /// A fragment represents a node's collection of child nodes.
 ///
 /// Like nodes, fragments are persistent data structures, and you should not mutate them or their
 /// content. Rather, you create new instances whenever needed. The API tries to make this easy.
-#[derive(Derivative, Deserialize, Eq)]
-#[derivative(Debug(bound = ""), Clone(bound = ""), PartialEq(bound = ""))]
-#[serde(from = "Vec<S::Node>")]
+///
+/// Deserializing fails for fragments that are nested too deep, see [`with_max_depth`].
+#[derive(Derivative, Eq)]
+#[derivative(Clone(bound = ""), PartialEq(bound = ""))]
 pub struct Fragment<S: Schema> {
-    inner: Vec<S::Node>,
+    inner: Children<S::Node>,
     size: usize,
 }
 
 impl<S: Schema> Fragment<S> {
     /// An empty fragment
     pub const EMPTY: Self = Fragment {
-        inner: Vec::new(),
+        inner: Children::EMPTY,
         size: 0,
     };
     /// Reference to an empty fragment
@@ -63,28 +124,60 @@ impl<S: Schema> Fragment<S> {
         self.inner.len()
     }
 
+    /// Whether this fragment has no children, and hence a size of 0.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0 && self.inner.is_empty()
+    }
+
+    /// Whether all children of this fragment are text nodes. This is true for an empty fragment.
+    pub fn is_text_only(&self) -> bool {
+        self.inner.iter().all(Node::is_text)
+    }
+
+    /// Check that the size matches the children, when debug assertions are enabled.
+    fn checked(self) -> Self {
+        debug_assert_eq!(
+            self.size,
+            self.inner.iter().map(Node::node_size).sum::<usize>(),
+            "fragment size does not match its children"
+        );
+        self
+    }
+
     /// Create a new fragment containing the combined content of this fragment and the other.
-    pub fn append(mut self, mut other: Self) -> Self {
-        if let Some(first) = other.first_child() {
-            if let Some(last) = self.inner.last_mut() {
-                if let Some(n1) = last.text_node() {
-                    if let Some(n2) = n1.same_markup(first) {
-                        let mid = n1
-                            .with_text(Text::from(n1.text.as_str().to_owned() + n2.text.as_str()));
-                        *last = S::Node::from(mid);
-                        other.inner.remove(0);
-                    }
-                }
+    pub fn append(mut self, other: Self) -> Self {
+        if self.inner.is_empty() {
+            return other;
+        }
+        // The size of a merged text node is the sum of both sizes, so the total stays the same
+        // whether or not the boundary nodes are joined.
+        self.size += other.size;
 
-                self.inner.append(&mut other.inner);
-                self.size += other.size;
-                self
-            } else {
-                other
+        let inner = self.inner.make_mut();
+        let mut rest = other.inner.into_vec().into_iter();
+        if let Some(first) = rest.next() {
+            let last = inner.last_mut().unwrap();
+            match last
+                .text_node()
+                .and_then(|n1| Some((n1, n1.same_markup(&first)?)))
+            {
+                Some((n1, n2)) => *last = S::Node::from(n1.with_text(n1.text.join(&n2.text))),
+                None => inner.push(first),
             }
-        } else {
-            self
         }
+        inner.extend(rest);
+        self.checked()
+    }
+
+    /// Like [`Fragment::append`], but keeps the text nodes at the boundary separate, even if
+    /// they have the same marks.
+    pub fn append_no_merge(mut self, other: Self) -> Self {
+        if self.inner.is_empty() {
+            return other;
+        }
+        self.size += other.size;
+        self.inner.make_mut().extend(other.inner.into_vec());
+        self.checked()
     }
 
     /// Cut out the sub-fragment between the two given positions.
@@ -108,12 +201,12 @@ impl<S: Schema> Fragment<S> {
                     let new_child = if pos < from || end > to {
                         if let Some(node) = child.text_node() {
                             let len = node.text.len_utf16();
-                            let start = if from > pos { from - pos } else { 0 };
+                            let start = from.saturating_sub(pos);
                             let end = usize::min(len, to - pos);
                             child.cut(start..end)
                         } else {
                             let t = pos + 1;
-                            let start = if from > t { from - t } else { 0 };
+                            let start = from.saturating_sub(t);
                             let end = usize::min(child.content_size(), to - t);
                             child.cut(start..end)
                         }
@@ -129,28 +222,53 @@ impl<S: Schema> Fragment<S> {
             }
         }
         Fragment {
-            inner: result,
+            inner: result.into(),
             size,
         }
+        .checked()
+    }
+
+    /// Create a new fragment in which the content between the two given positions is replaced
+    /// by `replacement`. Adjacent text nodes with the same marks are merged at both ends of the
+    /// replacement.
+    pub fn replace_range(&self, from: usize, to: usize, replacement: Fragment<S>) -> Fragment<S> {
+        self.cut(..from).append(replacement).append(self.cut(to..))
+    }
+
+    /// Find the first position at which this fragment and another fragment differ, or `None`
+    /// if they are the same.
+    pub fn find_diff_start(&self, other: &Fragment<S>) -> Option<usize> {
+        diff::find_diff_start(self, other, 0)
+    }
+
+    /// Find the first position, searching from the end, at which this fragment and the given
+    /// fragment differ, or `None` if they are the same. Since this position will not be the same
+    /// in both nodes, a pair of positions is returned, the first for this fragment and the
+    /// second for `other`.
+    pub fn find_diff_end(&self, other: &Fragment<S>) -> Option<(usize, usize)> {
+        diff::find_diff_end(self, other, self.size(), other.size())
     }
 
     /// Invoke a callback for all descendant nodes between the given two positions (relative to
     /// start of this fragment). Doesn't descend into a node when the callback returns `false`.
-    pub fn nodes_between<F: FnMut(&S::Node, usize) -> bool>(
-        &self,
+    pub fn nodes_between<'a, F: FnMut(&'a S::Node, usize) -> bool>(
+        &'a self,
         from: usize,
         to: usize,
         f: &mut F,
         node_start: usize,
     ) {
         let mut pos = 0;
-        for child in &self.inner {
+        for child in self.inner.iter() {
+            if pos >= to {
+                break;
+            }
             let end = pos + child.node_size();
             if end > from && f(child, node_start + pos) {
                 if let Some(content) = child.content() {
                     let start = pos + 1;
                     content.nodes_between(
-                        usize::max(0, from - start),
+                        from.saturating_sub(start),
                         usize::min(content.size(), to - start),
                         f,
                         node_start + start,
@@ -161,18 +279,50 @@ impl<S: Schema> Fragment<S> {
         }
     }
 
+    /// Concatenates all the text nodes found in this fragment and its children.
+    pub fn text_content(&self) -> String {
+        self.text_content_with(|_| Cow::Borrowed(""))
+    }
+
+    /// Like [`Fragment::text_content`], but inserts the result of `leaf_text` for every non-text
+    /// leaf node, e.g. the alt text of each image.
+    pub fn text_content_with<F>(&self, leaf_text: F) -> String
+    where
+        F: Fn(&S::Node) -> Cow<'_, str>,
+    {
+        let mut buf = String::new();
+        let push_leaf = |node: &S::Node, text: &mut String| text.push_str(&leaf_text(node));
+        self.text_between_with(&mut buf, true, 0, self.size, Some(""), push_leaf);
+        buf
+    }
+
     /// Get all text between positions from and to. When `block_separator` is given, it will be
     /// inserted whenever a new block node is started. When `leaf_text` is given, it'll be inserted
     /// for every non-text leaf node encountered.
     pub fn text_between(
         &self,
         text: &mut String,
-        mut separated: bool,
+        separated: bool,
         from: usize,
         to: usize,
         block_separator: Option<&str>,
         leaf_text: Option<&str>,
     ) {
+        let push_leaf = |_: &S::Node, text: &mut String| text.push_str(leaf_text.unwrap_or(""));
+        self.text_between_with(text, separated, from, to, block_separator, push_leaf)
+    }
+
+    fn text_between_with<F>(
+        &self,
+        text: &mut String,
+        mut separated: bool,
+        from: usize,
+        to: usize,
+        block_separator: Option<&str>,
+        push_leaf: F,
+    ) where
+        F: Fn(&S::Node, &mut String),
+    {
         self.nodes_between(
             from,
             to,
@@ -192,9 +342,7 @@ impl<S: Schema> Fragment<S> {
                     text.push_str(slice);
                     separated = block_separator.is_none();
                 } else if node.is_leaf() {
-                    if let Some(leaf_text) = leaf_text {
-                        text.push_str(leaf_text);
-                    }
+                    push_leaf(node, text);
                     separated = block_separator.is_none();
                 } else if !separated && node.is_block() {
                     text.push_str(block_separator.unwrap_or(""));
@@ -207,7 +355,7 @@ impl<S: Schema> Fragment<S> {
     }
 
     /// Create a new fragment in which the node at the given index is replaced by the given node.
-    pub fn replace_child(&self, index: usize, node: S::Node) -> Cow<Self> {
+    pub fn replace_child(&self, index: usize, node: S::Node) -> Cow<'_, Self> {
         let (before, rest) = self.inner.split_at(index);
         let (current, after) = rest.split_first().unwrap();
 
@@ -215,15 +363,26 @@ impl<S: Schema> Fragment<S> {
             Cow::Borrowed(self)
         } else {
             let size = self.size + node.node_size() - current.node_size();
-            let mut copy = Vec::with_capacity(self.inner.capacity());
+            let mut copy = Vec::with_capacity(self.inner.len());
             copy.extend_from_slice(before);
             copy.push(node);
             copy.extend_from_slice(after);
-            Cow::Owned(Fragment { inner: copy, size })
+            Cow::Owned(
+                Fragment {
+                    inner: copy.into(),
+                    size,
+                }
+                .checked(),
+            )
         }
     }
 
-    /// Get the child node at the given index. Panics when the index is out of range.
+    /// Get the child node at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the index is out of range, see [`Fragment::maybe_child`] for a checked
+    /// version.
     pub fn child(&self, index: usize) -> &S::Node {
         &self.inner[index]
     }
@@ -233,6 +392,32 @@ impl<S: Schema> Fragment<S> {
         self.inner.get(index)
     }
 
+    /// Deserialize a fragment from its JSON representation, an array of nodes.
+    ///
+    /// Unlike the `Deserialize` impl, errors name the index of the child that failed.
+    pub fn from_json(value: Value) -> Result<Self, serde_json::Error> {
+        match value {
+            Value::Array(children) => children
+                .into_iter()
+                .enumerate()
+                .map(|(i, child)| {
+                    serde_json::from_value(child).map_err(|e| {
+                        serde_json::Error::custom(format!("invalid node at index {}: {}", i, e))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Self::from),
+            _ => Err(serde_json::Error::custom(
+                "expected a fragment to be an array of nodes",
+            )),
+        }
+    }
+
+    /// Serialize this fragment to its JSON representation, an array of nodes.
+    pub fn to_json(&self) -> Result<Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
     pub(crate) fn find_index(&self, pos: usize, round: bool) -> Result<Index, IndexError> {
         let len = self.inner.len();
         match pos {
@@ -270,10 +455,56 @@ impl<S: Schema> Fragment<S> {
     }
 }
 
+/// The number of children that are shown by the compact `Debug` output.
+const DEBUG_CHILDREN: usize = 3;
+
+/// Shows the number of children, the size, and the first few children. The alternate form
+/// (`{:#?}`) shows all children.
+impl<S: Schema> fmt::Debug for Fragment<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return f
+                .debug_struct("Fragment")
+                .field("size", &self.size)
+                .field("content", &self.inner)
+                .finish();
+        }
+
+        let shown = usize::min(self.inner.len(), DEBUG_CHILDREN);
+        f.debug_struct("Fragment")
+            .field("children", &self.inner.len())
+            .field("size", &self.size)
+            .field(
+                "content",
+                &DebugChildren {
+                    shown: &self.inner[..shown],
+                    elided: self.inner.len() > shown,
+                },
+            )
+            .finish()
+    }
+}
+
+struct DebugChildren<'a, N> {
+    shown: &'a [N],
+    elided: bool,
+}
+
+impl<N: fmt::Debug> fmt::Debug for DebugChildren<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        list.entries(self.shown);
+        if self.elided {
+            list.entry(&format_args!(".."));
+        }
+        list.finish()
+    }
+}
+
 impl<S: Schema> Default for Fragment<S> {
     fn default() -> Self {
         Self {
-            inner: Vec::new(),
+            inner: Children::EMPTY,
             size: 0,
         }
     }
@@ -284,20 +515,60 @@ impl<S: Schema> Serialize for Fragment<S> {
     where
         Sr: Serializer,
     {
-        self.inner.serialize(serializer)
+        self.inner[..].serialize(serializer)
+    }
+}
+
+impl<'de, S: Schema> Deserialize<'de> for Fragment<S> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let _guard = DepthGuard::enter().map_err(D::Error::custom)?;
+        Vec::<S::Node>::deserialize(deserializer).map(Self::from)
     }
 }
 
 impl<S: Schema> From<Vec<S::Node>> for Fragment<S> {
     fn from(src: Vec<S::Node>) -> Fragment<S> {
         let size = src.iter().map(|x| x.node_size()).sum::<usize>();
-        Fragment { inner: src, size }
+        Fragment {
+            inner: src.into(),
+            size,
+        }
+        .checked()
+    }
+}
+
+impl<S: Schema> FromIterator<S::Node> for Fragment<S> {
+    fn from_iter<I: IntoIterator<Item = S::Node>>(iter: I) -> Self {
+        let mut size = 0;
+        let inner: Vec<S::Node> = (iter.into_iter())
+            .inspect(|node| size += node.node_size())
+            .collect();
+        Fragment {
+            inner: inner.into(),
+            size,
+        }
+        .checked()
+    }
+}
+
+/// Adds the nodes to the end of the fragment. Unlike [`Fragment::append`], text nodes with the
+/// same marks are not joined.
+impl<S: Schema> Extend<S::Node> for Fragment<S> {
+    fn extend<I: IntoIterator<Item = S::Node>>(&mut self, iter: I) {
+        let inner = self.inner.make_mut();
+        for node in iter {
+            self.size += node.node_size();
+            inner.push(node);
+        }
     }
 }
 
 impl<S: Schema> From<Fragment<S>> for Vec<S::Node> {
     fn from(src: Fragment<S>) -> Vec<S::Node> {
-        src.inner
+        src.inner.into_vec()
     }
 }
 
@@ -322,3 +593,20 @@ where
         Self::from(vec![a.into()])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Children, Fragment};
+    use crate::markdown::{helper::p, MD};
+
+    #[test]
+    #[cfg_attr(not(debug_assertions), ignore = "needs debug assertions")]
+    #[should_panic(expected = "fragment size does not match its children")]
+    fn test_size_mismatch() {
+        let broken = Fragment::<MD> {
+            inner: Children::from(vec![p("a")]),
+            size: 4,
+        };
+        let _ = broken.append(Fragment::from((p("b"),)));
+    }
+}
@@ -1,29 +1,54 @@
 use super::Schema;
 use derivative::Derivative;
 use displaydoc::Display;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::de::{
+    value::{MapAccessDeserializer, MapDeserializer},
+    Error, MapAccess, Visitor,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
 use std::fmt::{self, Debug};
+use std::marker::PhantomData;
 use std::{borrow::Cow, convert::TryFrom, hash::Hash};
 
 /// A set of marks
-#[derive(Derivative, Deserialize)]
+///
+/// The marks are kept sorted by their [`Mark::r#type`], with at most one mark per type.
+/// Iterating, [`MarkSet::as_slice`] and serializing all yield the marks in that order, no matter
+/// in which order they were added.
+///
+/// In JSON, marks without attributes may also be given by the name of their type, e.g.
+/// `["strong","em"]`, as some older exports do.
+#[derive(Derivative)]
 #[derivative(
     Clone(bound = ""),
     PartialEq(bound = ""),
     Eq(bound = ""),
     Default(bound = "")
 )]
-#[serde(bound = "", try_from = "Vec<S::Mark>")]
 pub struct MarkSet<S: Schema> {
     content: Vec<S::Mark>,
 }
 
 impl<S: Schema> MarkSet<S> {
+    /// The marks in this set, ordered by their type
+    pub fn as_slice(&self) -> &[S::Mark] {
+        &self.content
+    }
+
     /// Check whether the set contains this exact mark
     pub fn contains(&self, mark: &S::Mark) -> bool {
         self.content.contains(mark)
     }
 
+    /// Find the mark of the given type in the set, e.g. to read its attributes
+    pub fn find_by_type(&self, ty: S::MarkType) -> Option<&S::Mark> {
+        match self.content.binary_search_by_key(&ty, Mark::r#type) {
+            Ok(index) => Some(&self.content[index]),
+            Err(_index) => None,
+        }
+    }
+
     /// Add a mark to the set
     pub fn add(&mut self, mark: &S::Mark) {
         match self
@@ -72,17 +97,27 @@ impl<S: Schema> Serialize for MarkSet<S> {
     }
 }
 
+impl<'de, S: Schema> Deserialize<'de> for MarkSet<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let marks = Vec::<MarkRepr<S>>::deserialize(deserializer)?;
+        let marks: Vec<S::Mark> = marks.into_iter().map(|mark| mark.0).collect();
+        Self::try_from(marks).map_err(D::Error::custom)
+    }
+}
+
 #[derive(Display)]
 pub enum MarkSetError {
     /// Duplicate mark types
     Duplicates,
 }
 
+/// Creates a set from marks in any order, sorting them with [`Mark::compare`]. This is also used
+/// when deserializing, so a set is the same no matter in which order its marks are listed.
 impl<S: Schema> TryFrom<Vec<S::Mark>> for MarkSet<S> {
     type Error = MarkSetError;
     fn try_from(mut value: Vec<S::Mark>) -> Result<Self, Self::Error> {
         let len = value.len();
-        value.sort_by_key(|m| m.r#type());
+        value.sort_by(Mark::compare);
         value.dedup_by_key(|m| m.r#type());
         if len > value.len() {
             Err(MarkSetError::Duplicates)
@@ -92,6 +127,34 @@ impl<S: Schema> TryFrom<Vec<S::Mark>> for MarkSet<S> {
     }
 }
 
+/// A mark in JSON, either as an object or as the name of its type
+struct MarkRepr<S: Schema>(S::Mark);
+
+impl<'de, S: Schema> Deserialize<'de> for MarkRepr<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MarkReprVisitor(PhantomData))
+    }
+}
+
+struct MarkReprVisitor<S>(PhantomData<S>);
+
+impl<'de, S: Schema> Visitor<'de> for MarkReprVisitor<S> {
+    type Value = MarkRepr<S>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a mark or the name of a mark type")
+    }
+
+    fn visit_str<E: Error>(self, name: &str) -> Result<Self::Value, E> {
+        let attrs = MapDeserializer::new(std::iter::once(("type", name)));
+        S::Mark::deserialize(attrs).map(MarkRepr)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        S::Mark::deserialize(MapAccessDeserializer::new(map)).map(MarkRepr)
+    }
+}
+
 impl<S: Schema> fmt::Debug for MarkSet<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.content.fmt(f)
@@ -105,6 +168,18 @@ pub trait Mark<S: Schema<Mark = Self>>:
     /// The type of this mark.
     fn r#type(&self) -> S::MarkType;
 
+    /// The canonical order of marks, by their type. This is the order of the marks in a
+    /// [`MarkSet`], so sorting a list of marks with it before turning it into a set is not
+    /// necessary, but gives the list the same order.
+    fn compare(&self, other: &Self) -> Ordering {
+        self.r#type().cmp(&other.r#type())
+    }
+
+    /// Test whether this mark is in the given set of marks, with the same attributes.
+    fn is_in_set(&self, set: &MarkSet<S>) -> bool {
+        set.contains(self)
+    }
+
     /// Given a set of marks, create a new set which contains this one as well, in the right
     /// position. If this mark is already in the set, the set itself is returned. If any marks that
     /// are set to be exclusive with this mark are present, those are replaced by this one.
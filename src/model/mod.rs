@@ -2,7 +2,9 @@
 //!
 //! This module is derived from the `prosemirror-markdown` schema and the
 //! the general JSON serialization of nodes.
+mod children;
 mod content;
+mod diff;
 mod fragment;
 mod marks;
 mod node;
@@ -12,11 +14,11 @@ mod schema;
 pub(crate) mod util;
 
 pub use content::{ContentMatch, ContentMatchError};
-pub use fragment::Fragment;
+pub use fragment::{with_max_depth, DepthError, Fragment, DEFAULT_MAX_DEPTH};
 pub use marks::{Mark, MarkSet};
-pub use node::{Node, NodeType, SliceError, Text};
-pub use replace::{InsertError, ReplaceError, Slice};
-pub use resolved_pos::{ResolveErr, ResolvedNode, ResolvedPos};
+pub use node::{Node, NodeIter, NodeType, SliceError, Text};
+pub use replace::{InsertError, ReplaceError, Slice, SliceBuilder};
+pub use resolved_pos::{NodeRange, ResolveCache, ResolveErr, ResolvedNode, ResolvedPos};
 pub use schema::{AttrNode, Block, Leaf, MarkType, Schema, TextNode};
 
 pub(crate) use replace::replace;
@@ -24,9 +26,20 @@ pub(crate) use resolved_pos::Index;
 
 #[cfg(test)]
 mod tests {
-    use super::{fragment::IndexError, Index, Node, ResolvedNode, ResolvedPos};
-    use crate::markdown::{helper::*, ImageAttrs, MarkdownNode, MD};
+    use super::{
+        fragment::IndexError, with_max_depth, ContentMatch, DepthError, Fragment, Index, Leaf,
+        Mark, MarkSet, Node, NodeType, ReplaceError, ResolveCache, ResolveErr, ResolvedNode,
+        ResolvedPos, Schema, Slice, SliceError, Text, TextNode, DEFAULT_MAX_DEPTH,
+    };
+    use crate::markdown::{
+        helper::*, AttrMap, HeadingAttrs, ImageAttrs, LinkAttrs, ListItemAttrs, MarkdownLinkType,
+        MarkdownMark, MarkdownMarkType, MarkdownNode, MarkdownNodeType, MD,
+    };
+    use std::borrow::Cow;
+    use std::collections::hash_map::DefaultHasher;
+    use std::convert::TryFrom;
     use std::fmt::Debug;
+    use std::hash::{Hash, Hasher};
     use std::ops::Deref;
 
     #[test]
@@ -36,11 +49,519 @@ mod tests {
             ImageAttrs {
                 src: String::new(),
                 title: String::new(),
-                alt: String::new()
+                alt: String::new(),
+                extra: Default::default(),
             }
         );
     }
 
+    #[test]
+    fn test_link_attrs_normalized() {
+        let marks: Vec<MarkdownMark> = [
+            r#"{"type": "link", "attrs": {"href": "x"}}"#,
+            r#"{"type": "link", "attrs": {"href": "x", "title": null}}"#,
+            r#"{"type": "link", "attrs": {"href": "x", "title": ""}}"#,
+        ]
+        .iter()
+        .map(|json| serde_json::from_str(json).unwrap())
+        .collect();
+
+        let expected = MarkdownMark::Link {
+            attrs: LinkAttrs {
+                href: String::from("x"),
+                title: String::new(),
+                link_type: MarkdownLinkType::Inline,
+            },
+        };
+        let hash = |mark: &MarkdownMark| {
+            let mut hasher = DefaultHasher::new();
+            mark.hash(&mut hasher);
+            hasher.finish()
+        };
+        for mark in &marks {
+            assert_eq!(mark, &expected);
+            assert_eq!(hash(mark), hash(&expected));
+        }
+    }
+
+    #[test]
+    fn test_link_attrs_href() {
+        let mark: MarkdownMark =
+            serde_json::from_str(r#"{"type":"link","attrs":{"href":""}}"#).unwrap();
+        assert_eq!(
+            mark,
+            MarkdownMark::Link {
+                attrs: LinkAttrs {
+                    href: String::new(),
+                    title: String::new(),
+                    link_type: MarkdownLinkType::Inline,
+                },
+            }
+        );
+
+        for json in &[
+            r#"{"type":"link","attrs":{}}"#,
+            r#"{"type":"link","attrs":{"href":null,"title":"t"}}"#,
+        ] {
+            let err = serde_json::from_str::<MarkdownMark>(json).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("link is missing the `href` attribute"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_heading_level() {
+        let attrs: HeadingAttrs = serde_json::from_str(r#"{"level":6}"#).unwrap();
+        assert_eq!(Some(attrs), HeadingAttrs::new(6));
+
+        for json in &[r#"{"level":7}"#, r#"{"level":0}"#] {
+            let err = serde_json::from_str::<HeadingAttrs>(json).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("heading level must be between 1 and 6"),
+                "{}",
+                err
+            );
+        }
+        assert_eq!(HeadingAttrs::new(0), None);
+        assert_eq!(HeadingAttrs::new(7), None);
+    }
+
+    #[test]
+    fn test_unknown_attrs() {
+        let json = r#"{"level":1,"align":"center"}"#;
+        let attrs: HeadingAttrs = serde_json::from_str(json).unwrap();
+        assert_eq!(attrs.level, 1);
+
+        let output = serde_json::to_string(&attrs).unwrap();
+        if cfg!(feature = "preserve-unknown-attrs") {
+            assert_eq!(output, json);
+        } else {
+            assert!(attrs.extra.is_empty());
+            assert_eq!(output, r#"{"level":1}"#);
+        }
+
+        // Paragraphs have no attributes, so there is no place to keep unknown ones
+        let json = r#"{"type":"paragraph","attrs":{"align":"center"}}"#;
+        let para: MarkdownNode = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            serde_json::to_string(&para).unwrap(),
+            r#"{"type":"paragraph"}"#
+        );
+
+        // List item attrs can be built and read with their unknown attributes, too
+        let mut extra = serde_json::Map::new();
+        extra.insert("align".to_owned(), serde_json::json!("center"));
+        let attrs = ListItemAttrs {
+            checked: Some(true),
+            extra,
+        };
+        let json = r#"{"checked":true,"align":"center"}"#;
+        let parsed: ListItemAttrs = serde_json::from_str(json).unwrap();
+        if cfg!(feature = "preserve-unknown-attrs") {
+            assert_eq!(serde_json::to_string(&attrs).unwrap(), json);
+            assert_eq!(parsed.extra["align"], "center");
+            assert_eq!(parsed, attrs);
+        } else {
+            assert_eq!(
+                serde_json::to_string(&attrs).unwrap(),
+                r#"{"checked":true}"#
+            );
+            assert!(parsed.extra.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_attrs_json() {
+        use serde_json::json;
+        assert_eq!(h1("Title").attrs_json(), json!({"level": 1}));
+        assert_eq!(
+            code_block("rust", "fn main() {}").attrs_json(),
+            json!({"params": "rust"})
+        );
+        assert_eq!(
+            img("cat.png", "A cat", "").attrs_json(),
+            json!({"src": "cat.png", "alt": "A cat", "title": ""})
+        );
+        assert_eq!(p("text").attrs_json(), json!({}));
+        assert_eq!(node("text").attrs_json(), json!({}));
+    }
+
+    #[test]
+    fn test_mark_set_normalized() {
+        let a: MarkSet<MD> = serde_json::from_str(r#"[{"type":"em"},{"type":"strong"}]"#).unwrap();
+        let b: MarkSet<MD> = serde_json::from_str(r#"[{"type":"strong"},{"type":"em"}]"#).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(
+            serde_json::to_string(&a).unwrap(),
+            serde_json::to_string(&b).unwrap()
+        );
+
+        let mut marks = vec![
+            MarkdownMark::code(),
+            MarkdownMark::strong(),
+            MarkdownMark::em(),
+        ];
+        let set = MarkSet::<MD>::try_from(marks.clone()).ok().unwrap();
+        marks.sort_by(Mark::compare);
+        assert_eq!(set.as_slice(), marks.as_slice());
+        assert!(serde_json::from_str::<MarkSet<MD>>(r#"[{"type":"em"},{"type":"em"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_mark_set_names() {
+        let names: MarkSet<MD> = serde_json::from_str(r#"["strong","em"]"#).unwrap();
+        let objects: MarkSet<MD> =
+            serde_json::from_str(r#"[{"type":"strong"},{"type":"em"}]"#).unwrap();
+        assert_eq!(names, objects);
+        let mixed: MarkSet<MD> = serde_json::from_str(r#"[{"type":"em"},"strong"]"#).unwrap();
+        assert_eq!(mixed, objects);
+
+        let link = r#"{"type":"link","attrs":{"href":"https://example.com"}}"#;
+        let with_link: MarkSet<MD> = serde_json::from_str(&format!("[\"em\",{}]", link)).unwrap();
+        assert_eq!(with_link.as_slice().len(), 2);
+        assert!(serde_json::from_str::<MarkSet<MD>>(r#"["em","link"]"#).is_err());
+        assert!(serde_json::from_str::<MarkSet<MD>>(r#"["em","unknown"]"#).is_err());
+        assert!(serde_json::from_str::<MarkSet<MD>>(r#"["em","em"]"#).is_err());
+        assert!(serde_json::from_str::<MarkSet<MD>>(r#"["em",1]"#).is_err());
+
+        // Errors in a mark point to where they are in the JSON, not to the end of the mark
+        let json = "[\"em\",\n{\"type\":\"unknown\",\n\"attrs\":{}}]";
+        let err = serde_json::from_str::<MarkSet<MD>>(json).unwrap_err();
+        assert_eq!(err.line(), 2);
+    }
+
+    #[test]
+    fn test_mark_set_find_by_type() {
+        let link = MarkdownMark::Link {
+            attrs: LinkAttrs {
+                href: "https://example.com".to_owned(),
+                title: String::new(),
+                link_type: MarkdownLinkType::Inline,
+            },
+        };
+        let set = MarkSet::<MD>::try_from(vec![link.clone(), MarkdownMark::strong()])
+            .ok()
+            .unwrap();
+        match set.find_by_type(MarkdownMarkType::Link) {
+            Some(MarkdownMark::Link { attrs }) => assert_eq!(attrs.href, "https://example.com"),
+            other => panic!("expected a link, got {:?}", other),
+        }
+        assert_eq!(
+            set.find_by_type(MarkdownMarkType::Strong),
+            Some(&MarkdownMark::strong())
+        );
+        assert_eq!(set.find_by_type(MarkdownMarkType::Em), None);
+        assert!(link.is_in_set(&set));
+        assert!(!MarkdownMark::em().is_in_set(&set));
+    }
+
+    #[test]
+    fn test_mark_attrs() {
+        let mark: MarkdownMark = serde_json::from_str(r#"{"type":"code"}"#).unwrap();
+        assert_eq!(mark, MarkdownMark::code());
+        assert_eq!(serde_json::to_string(&mark).unwrap(), r#"{"type":"code"}"#);
+
+        let json = r#"{"type":"strong","attrs":{"color":"red"}}"#;
+        let mark: MarkdownMark = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            mark,
+            MarkdownMark::Strong {
+                attrs: AttrMap {
+                    attrs: vec![("color".to_owned(), "red".into())]
+                        .into_iter()
+                        .collect(),
+                },
+            }
+        );
+        assert_ne!(mark, MarkdownMark::strong());
+        assert_eq!(mark.r#type(), MarkdownMarkType::Strong);
+        assert_eq!(serde_json::to_string(&mark).unwrap(), json);
+
+        // Equal attributes hash the same, and the hash covers the values
+        let hash = |json: &str| {
+            let mark: MarkdownMark = serde_json::from_str(json).unwrap();
+            let mut hasher = DefaultHasher::new();
+            mark.hash(&mut hasher);
+            hasher.finish()
+        };
+        let zero = r#"{"type":"em","attrs":{"size":0.0,"tags":["a",{"b":1}]}}"#;
+        let negative_zero = r#"{"type":"em","attrs":{"size":-0.0,"tags":["a",{"b":1}]}}"#;
+        let one = r#"{"type":"em","attrs":{"size":1.0,"tags":["a",{"b":1}]}}"#;
+        assert_eq!(hash(zero), hash(negative_zero));
+        assert_ne!(hash(zero), hash(one));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let nested = |depth: usize| {
+            let mut json = r#"{"type":"paragraph"}"#.to_owned();
+            for _ in 1..depth {
+                json = format!(r#"{{"type":"blockquote","content":[{}]}}"#, json);
+            }
+            format!(r#"{{"type":"doc","content":[{}]}}"#, json)
+        };
+
+        let parsed: MarkdownNode = serde_json::from_str(&nested(20)).unwrap();
+        assert_eq!(parsed.check_depth(20), Ok(()));
+        assert_eq!(parsed.check_depth(19), Err(DepthError(19)));
+
+        let err =
+            with_max_depth(10, || serde_json::from_str::<MarkdownNode>(&nested(20))).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("The document is nested deeper than the limit of 10"),
+            "{}",
+            err
+        );
+        // The limit is restored afterwards
+        assert!(serde_json::from_str::<MarkdownNode>(&nested(20)).is_ok());
+        assert!(with_max_depth(20, || serde_json::from_str::<MarkdownNode>(&nested(20))).is_ok());
+
+        // The default limit is below serde_json's recursion limit, so it can be reached
+        assert!(serde_json::from_str::<MarkdownNode>(&nested(DEFAULT_MAX_DEPTH)).is_ok());
+        let err = serde_json::from_str::<MarkdownNode>(&nested(DEFAULT_MAX_DEPTH + 1)).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&DepthError(DEFAULT_MAX_DEPTH).to_string()),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_empty_doc() {
+        let empty = MD::empty_doc();
+        assert_eq!(empty, doc(p(vec![])));
+        assert!(empty.r#type().valid_content(empty.content().unwrap()));
+        let para = empty.child(0).unwrap();
+        assert!(para.r#type().valid_content(para.content().unwrap()));
+
+        let json = r#"{"type":"doc","content":[{"type":"paragraph"}]}"#;
+        assert_eq!(serde_json::from_str::<MarkdownNode>(json).unwrap(), empty);
+        assert_eq!(serde_json::to_string(&empty).unwrap(), json);
+        assert_eq!(MarkdownNodeType::Doc.create_and_fill(), Some(empty));
+        assert_eq!(
+            MarkdownNodeType::BulletList.create_and_fill(),
+            Some(ul(li(p(vec![]))))
+        );
+        assert_eq!(MarkdownNodeType::Text.create_and_fill(), None);
+    }
+
+    #[test]
+    fn test_slice_builder() {
+        let empty_para = Fragment::from(p(vec![]));
+        let slice = Slice::<MD>::builder(empty_para.clone())
+            .open_start(1)
+            .open_end(1)
+            .build()
+            .unwrap();
+        assert_eq!(slice, Slice::new(empty_para.clone(), 1, 1));
+        assert_eq!(slice.size(), 0);
+        assert_eq!(
+            Slice::<MD>::builder(empty_para.clone())
+                .open_start(2)
+                .build(),
+            Err(SliceError::OpenStart(2, 1))
+        );
+        assert_eq!(
+            Slice::<MD>::builder(empty_para).open_end(2).build(),
+            Err(SliceError::OpenEnd(2, 1))
+        );
+
+        let quote = Fragment::from(vec![blockquote(p("a")), p("b")]);
+        let slice = Slice::<MD>::builder(quote.clone())
+            .open_start(2)
+            .open_end(1);
+        assert_eq!(slice.build(), Ok(Slice::new(quote.clone(), 2, 1)));
+        assert!(Slice::<MD>::builder(quote).open_end(2).build().is_err());
+        assert_eq!(
+            Slice::<MD>::builder(Fragment::from(node("text")))
+                .open_start(1)
+                .build(),
+            Err(SliceError::OpenStart(1, 0))
+        );
+
+        // Deserializing checks the open depths, too
+        let json = r#"{"content":[],"openStart":1,"openEnd":1}"#;
+        let err = serde_json::from_str::<Slice<MD>>(json).unwrap_err();
+        assert_eq!(err.to_string(), SliceError::OpenStart(1, 0).to_string());
+        let json = r#"{"content":[{"type":"paragraph"}],"openStart":1,"openEnd":1}"#;
+        let slice: Slice<MD> = serde_json::from_str(json).unwrap();
+        assert_eq!(slice, Slice::new(Fragment::from(p(vec![])), 1, 1));
+    }
+
+    #[test]
+    fn test_walk() {
+        let d = doc(vec![
+            blockquote(vec![p("ab"), ul(li(p("c")))]),
+            p(vec![]),
+            p(vec![em("x"), node("y")]),
+        ]);
+        let mut expected = Vec::new();
+        d.descendants(&mut |node, pos| {
+            expected.push((pos, node));
+            true
+        });
+        let walked: Vec<_> = d.walk().map(|(_, pos, node)| (pos, node)).collect();
+        assert_eq!(walked, expected);
+
+        let depths: Vec<_> = d.walk().map(|(depth, pos, _)| (depth, pos)).collect();
+        assert_eq!(
+            depths,
+            [
+                (1, 0),
+                (2, 1),
+                (3, 2),
+                (2, 5),
+                (3, 6),
+                (4, 7),
+                (5, 8),
+                (1, 13),
+                (1, 15),
+                (2, 16),
+                (2, 17)
+            ]
+        );
+        assert_eq!(d.walk().filter(|(_, _, n)| n.is_text()).count(), 4);
+        assert_eq!(node("leaf").walk().count(), 0);
+    }
+
+    #[test]
+    fn test_can_insert_at() {
+        use MarkdownNodeType::*;
+        let d = doc(vec![p("ab"), code_block("", node("cd"))]);
+        // In the paragraph, before, between and after the characters
+        for pos in 1..=3 {
+            assert!(d.can_insert_at(pos, Image));
+            assert!(!d.can_insert_at(pos, Paragraph));
+        }
+        // Between the blocks of the document
+        assert!(!d.can_insert_at(0, Image));
+        assert!(!d.can_insert_at(4, Image));
+        assert!(d.can_insert_at(4, HorizontalRule));
+        // In the code block
+        assert!(!d.can_insert_at(6, Image));
+        assert!(d.can_insert_at(6, Text));
+        assert!(!d.can_insert_at(100, Paragraph));
+    }
+
+    #[test]
+    fn test_default_type() {
+        use MarkdownNodeType::*;
+        assert_eq!(Doc.default_type(), Some(Paragraph));
+        assert_eq!(Blockquote.default_type(), Some(Paragraph));
+        assert_eq!(ListItem.default_type(), Some(Paragraph));
+        assert_eq!(BulletList.default_type(), Some(ListItem));
+        assert_eq!(OrderedList.default_type(), Some(ListItem));
+        assert_eq!(CodeBlock.default_type(), None);
+        assert_eq!(Paragraph.default_type(), Some(HardBreak));
+        assert_eq!(Heading.default_type(), None);
+        assert_eq!(Image.default_type(), None);
+    }
+
+    #[test]
+    fn test_allowed_child_types() {
+        use MarkdownNodeType::*;
+        #[allow(unused_mut)]
+        let mut blocks = vec![
+            Heading,
+            CodeBlock,
+            Blockquote,
+            Paragraph,
+            BulletList,
+            OrderedList,
+            HorizontalRule,
+        ];
+        #[cfg(feature = "directives")]
+        blocks.push(Container);
+        assert_eq!(Doc.allowed_child_types(), blocks);
+        assert_eq!(Blockquote.allowed_child_types(), blocks);
+        assert_eq!(Paragraph.allowed_child_types(), [Text, HardBreak, Image]);
+        assert_eq!(Heading.allowed_child_types(), [Text, Image]);
+        assert_eq!(CodeBlock.allowed_child_types(), [Text]);
+        assert_eq!(ListItem.allowed_child_types(), [Paragraph]);
+        assert_eq!(BulletList.allowed_child_types(), [ListItem]);
+        assert!(HardBreak.allowed_child_types().is_empty());
+    }
+
+    #[test]
+    fn test_valid_content_marks() {
+        let heading = h1(vec![node("see "), link("https://example.com", "", "here")]);
+        assert!(heading.r#type().valid_content(heading.content().unwrap()));
+
+        let plain = code_block("", node("let x;"));
+        assert!(plain.r#type().valid_content(plain.content().unwrap()));
+
+        let linked = Fragment::from(vec![node("see "), link("https://example.com", "", "here")]);
+        assert!(!MarkdownNodeType::CodeBlock.valid_content(&linked));
+        let marks = linked.child(1).marks().unwrap();
+        assert!(MarkdownNodeType::Heading.allow_marks(marks));
+        assert!(!MarkdownNodeType::CodeBlock.allow_marks(marks));
+        assert!(MarkdownNodeType::CodeBlock.allow_marks(&MarkSet::default()));
+    }
+
+    #[test]
+    fn test_doc_attrs() {
+        let plain: MarkdownNode =
+            serde_json::from_str(r#"{"type": "doc", "content": [{"type": "paragraph"}]}"#).unwrap();
+        assert_eq!(plain, doc(p(vec![])));
+        assert_eq!(
+            serde_json::to_value(&plain).unwrap(),
+            serde_json::json!({"type": "doc", "content": [{"type": "paragraph"}]})
+        );
+
+        // Documents without attributes serialize like before the attributes were added
+        let baseline = r#"{"type":"doc","content":[{"type":"paragraph","content":[{"type":"text","marks":[],"text":"a"}]}]}"#;
+        assert_eq!(serde_json::to_string(&doc(p("a"))).unwrap(), baseline);
+        let parsed: MarkdownNode = serde_json::from_str(baseline).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), baseline);
+
+        let null: MarkdownNode =
+            serde_json::from_str(r#"{"type": "doc", "attrs": null, "content": []}"#).unwrap();
+        assert_eq!(null, doc(vec![]));
+
+        let json = serde_json::json!({
+            "type": "doc",
+            "attrs": {"version": 3, "meta": {"author": "me"}},
+            "content": [{"type": "paragraph"}],
+        });
+        let with_attrs: MarkdownNode = serde_json::from_value(json.clone()).unwrap();
+        if let MarkdownNode::Doc(node) = &with_attrs {
+            assert_eq!(node.attrs.attrs["version"], serde_json::json!(3));
+        } else {
+            panic!("expected a doc node");
+        }
+        assert_eq!(serde_json::to_value(&with_attrs).unwrap(), json);
+    }
+
+    #[test]
+    fn test_list_item_attrs() {
+        let plain: MarkdownNode =
+            serde_json::from_str(r#"{"type":"list_item","content":[{"type":"paragraph"}]}"#)
+                .unwrap();
+        assert_eq!(plain, li(p(vec![])));
+        let plain_json = serde_json::to_value(&plain).unwrap();
+        assert_eq!(plain_json.get("attrs"), None);
+        let null: MarkdownNode =
+            serde_json::from_str(r#"{"type":"list_item","attrs":{"checked":null}}"#).unwrap();
+        assert_eq!(null, li(vec![]));
+
+        let checked: MarkdownNode = serde_json::from_str(
+            r#"{"type":"list_item","attrs":{"checked":true},"content":[{"type":"paragraph"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(checked, task(true, p(vec![])));
+        assert_eq!(
+            serde_json::to_value(&checked).unwrap()["attrs"],
+            serde_json::json!({ "checked": true })
+        );
+    }
+
     #[test]
     fn test_deserialize_text() {
         assert_eq!(
@@ -49,6 +570,307 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_text_len() {
+        let ascii = Text::from(String::from("Hello"));
+        assert_eq!(ascii.len_utf16(), 5);
+        assert_eq!(ascii.len_chars(), 5);
+        assert_eq!(ascii.len_bytes(), 5);
+        assert!(!ascii.is_empty());
+
+        let emoji = Text::from(String::from("a\u{1F60A}"));
+        assert_eq!(emoji.len_utf16(), 3);
+        assert_eq!(emoji.len_chars(), 2);
+        assert_eq!(emoji.len_bytes(), 5);
+        assert!(!emoji.is_empty());
+
+        let empty = Text::default();
+        assert_eq!(empty.len_utf16(), 0);
+        assert_eq!(empty.len_chars(), 0);
+        assert_eq!(empty.len_bytes(), 0);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_append_merges_text() {
+        let left = Fragment::<MD>::from(node("ab"));
+        let right = Fragment::from(node("cd"));
+        let joined = left.append(right);
+        assert_eq!(joined.size(), 4);
+        assert_eq!(joined.child_count(), 1);
+        assert_eq!(joined.child(0), &node("abcd"));
+
+        let left = Fragment::<MD>::from(vec![p("x"), node("ab")]);
+        let right = Fragment::from(vec![node("cd"), strong("ef")]);
+        let joined = left.append(right);
+        assert_eq!(joined.size(), 9);
+        assert_eq!(joined.child_count(), 3);
+        assert_eq!(
+            joined.size(),
+            joined.children().iter().map(Node::node_size).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_mark_set_order() {
+        let mut a = MarkSet::<MD>::default();
+        a.add(&MarkdownMark::em());
+        a.add(&MarkdownMark::strong());
+        let mut b = MarkSet::<MD>::default();
+        b.add(&MarkdownMark::strong());
+        b.add(&MarkdownMark::em());
+
+        assert_eq!(a.as_slice(), &[MarkdownMark::strong(), MarkdownMark::em()]);
+        assert_eq!(a.as_slice(), b.as_slice());
+        assert_eq!(
+            serde_json::to_string(&a).unwrap(),
+            r#"[{"type":"strong"},{"type":"em"}]"#
+        );
+        assert_eq!(
+            serde_json::to_string(&a).unwrap(),
+            serde_json::to_string(&b).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_find_diff() {
+        let a = doc(vec![p("Hello World"), p("same")]);
+        let b = doc(vec![p("Hello big World"), p("same")]);
+        let (a, b) = (a.content().unwrap(), b.content().unwrap());
+        assert_eq!(a.find_diff_start(b), Some(7));
+        assert_eq!(a.find_diff_end(b), Some((6, 10)));
+        assert_eq!(a.find_diff_start(a), None);
+        assert_eq!(a.find_diff_end(a), None);
+
+        let a = Fragment::<MD>::from(vec![p("a"), h1("b")]);
+        let b = Fragment::<MD>::from(vec![p("a"), h2("b")]);
+        assert_eq!(a.find_diff_start(&b), Some(3));
+        assert_eq!(a.find_diff_end(&b), Some((6, 6)));
+
+        // Same sizes, different content
+        let a = Fragment::<MD>::from(vec![p("ab"), p("cd")]);
+        let b = Fragment::<MD>::from(vec![p("ab"), p("xd")]);
+        assert_eq!(a.find_diff_start(&b), Some(5));
+        assert_eq!(a.find_diff_end(&b), Some((6, 6)));
+    }
+
+    #[test]
+    fn test_fragment_append_no_merge() {
+        let a = Fragment::<MD>::from((node("Hello "),));
+        let b = Fragment::<MD>::from((node("World"),));
+
+        let separate = a.clone().append_no_merge(b.clone());
+        assert_eq!(separate.child_count(), 2);
+        assert_eq!(separate.size(), 11);
+        assert_eq!(
+            separate,
+            Fragment::from(vec![node("Hello "), node("World")])
+        );
+
+        let merged = a.append(b);
+        assert_eq!(merged.child_count(), 1);
+        assert_eq!(merged.size(), 11);
+        assert_eq!(merged, Fragment::from((node("Hello World"),)));
+
+        assert_eq!(Fragment::new().append_no_merge(separate.clone()), separate);
+    }
+
+    #[test]
+    fn test_fragment_replace_range() {
+        let fragment = Fragment::<MD>::from(vec![node("Hello "), strong("big"), node(" World")]);
+
+        let replaced = fragment.replace_range(6, 9, Fragment::from((node("small"),)));
+        assert_eq!(replaced, Fragment::from((node("Hello small World"),)));
+        assert_eq!(replaced.child_count(), 1);
+        assert_eq!(replaced.size(), 17);
+
+        let replaced = fragment.replace_range(2, 7, Fragment::from((strong("X"),)));
+        assert_eq!(
+            replaced,
+            Fragment::from(vec![node("He"), strong("Xig"), node(" World")])
+        );
+
+        let emptied = fragment.replace_range(0, fragment.size(), Fragment::new());
+        assert_eq!(emptied, Fragment::new());
+        assert_eq!(
+            fragment.replace_range(0, 0, Fragment::from((node(">"),))),
+            Fragment::from(vec![node(">Hello "), strong("big"), node(" World")])
+        );
+    }
+
+    #[test]
+    fn test_can_replace_with() {
+        let test_doc = doc(vec![p("one"), p("two")]);
+        assert!(test_doc.can_replace_with(1, 1, MarkdownNodeType::Blockquote));
+        assert!(test_doc.can_replace_with(0, 2, MarkdownNodeType::Blockquote));
+        assert!(!test_doc.can_replace_with(1, 1, MarkdownNodeType::ListItem));
+        assert!(!test_doc.can_replace_with(1, 1, MarkdownNodeType::Text));
+
+        let code = code_block("", "let a = 1;");
+        assert!(!code.can_replace_with(0, 0, MarkdownNodeType::Image));
+        assert!(code.can_replace_with(0, 1, MarkdownNodeType::Text));
+
+        let list = ul(li(p("a")));
+        assert!(list.can_replace_with(0, 1, MarkdownNodeType::ListItem));
+        assert!(!list.can_replace_with(0, 1, MarkdownNodeType::Paragraph));
+    }
+
+    #[test]
+    fn test_nodes_of_type() {
+        let test_doc = doc(vec![h1("Title"), p("text"), blockquote(h2("Quote"))]);
+        let headings = test_doc.nodes_of_type(MarkdownNodeType::Heading);
+        assert_eq!(headings, vec![(0, &h1("Title")), (14, &h2("Quote"))]);
+        assert!(test_doc.nodes_of_type(MarkdownNodeType::Image).is_empty());
+    }
+
+    #[test]
+    fn test_replace_node_at() {
+        let test_doc = doc(blockquote(vec![p("one"), p("two")]));
+        assert_eq!(
+            test_doc.replace_node_at(6, h1("two")),
+            Ok(doc(blockquote(vec![p("one"), h1("two")])))
+        );
+        assert_eq!(
+            test_doc.replace_node_at(0, ul(li(p("a")))),
+            Ok(doc(ul(li(p("a")))))
+        );
+
+        assert_eq!(
+            test_doc.replace_node_at(1, li(p("one"))),
+            Err(ReplaceError::InvalidContent(MarkdownNodeType::Blockquote))
+        );
+        assert_eq!(
+            test_doc.replace_node_at(3, h1("x")),
+            Err(ReplaceError::NoNodeAt(3))
+        );
+        assert_eq!(
+            test_doc.replace_node_at(11, h1("x")),
+            Err(ReplaceError::NoNodeAt(11))
+        );
+    }
+
+    #[test]
+    fn test_fragment_predicates() {
+        let empty = Fragment::<MD>::new();
+        assert!(empty.is_empty());
+        assert!(empty.is_text_only());
+
+        let text = Fragment::<MD>::from(vec![node("a"), strong("b")]);
+        assert!(!text.is_empty());
+        assert!(text.is_text_only());
+
+        let mixed = Fragment::<MD>::from(vec![node("a"), br()]);
+        assert!(!mixed.is_empty());
+        assert!(!mixed.is_text_only());
+        assert!(!Fragment::<MD>::from(p(vec![])).is_text_only());
+    }
+
+    #[test]
+    fn test_content_or_empty() {
+        let hard_break = br();
+        assert_eq!(hard_break.content(), None);
+        assert!(std::ptr::eq(
+            hard_break.content_or_empty(),
+            Fragment::EMPTY_REF
+        ));
+
+        let empty = p(vec![]);
+        assert_eq!(empty.content(), Some(&Fragment::new()));
+        assert_eq!(empty.content_or_empty().size(), 0);
+        assert_eq!(p("a").content_or_empty(), &Fragment::from(node("a")));
+    }
+
+    #[test]
+    fn test_replace_open_depths() {
+        let test_doc = doc(p("ab"));
+        let slice = Slice::new(Fragment::from((blockquote(p("x")),)), 0, 2);
+        assert_eq!(
+            test_doc.replace(2..2, &slice),
+            Err(ReplaceError::InconsistentOpenDepths {
+                from_depth: 1,
+                open_start: 0,
+                to_depth: 1,
+                open_end: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_fragment_debug() {
+        let fragment = Fragment::<MD>::from(vec![MarkdownNode::HardBreak; 10]);
+        assert_eq!(
+            format!("{:?}", fragment),
+            "Fragment { children: 10, size: 10, content: [HardBreak, HardBreak, HardBreak, ..] }"
+        );
+        assert_eq!(format!("{:#?}", fragment).matches("HardBreak").count(), 10);
+
+        let fragment = Fragment::<MD>::from((p("a"),));
+        assert_eq!(
+            format!("{:?}", fragment),
+            "Fragment { children: 1, size: 3, content: [Paragraph(Block { content: Fragment { \
+             children: 1, size: 1, content: [Text(TextNode { marks: [], text: Text { len_utf16: 1, \
+             content: \"a\" } })] } })] }"
+        );
+    }
+
+    #[test]
+    fn test_fragment_text_content() {
+        let image = |alt: &str| {
+            MarkdownNode::Image(Leaf {
+                attrs: ImageAttrs {
+                    src: "pet.png".to_string(),
+                    alt: alt.to_string(),
+                    title: String::new(),
+                    extra: Default::default(),
+                },
+            })
+        };
+        let fragment = Fragment::<MD>::from(vec![
+            p(vec![node("Hello "), strong("World")]),
+            p(vec![
+                node("Look: "),
+                image("A cat"),
+                node(", "),
+                image("A dog"),
+            ]),
+        ]);
+        assert_eq!(fragment.text_content(), "Hello WorldLook: , ");
+        let text = fragment.text_content_with(|node| match node {
+            MarkdownNode::Image(Leaf { attrs }) => Cow::Borrowed(attrs.alt.as_str()),
+            _ => Cow::Borrowed(""),
+        });
+        assert_eq!(text, "Hello WorldLook: A cat, A dog");
+        assert_eq!(Fragment::<MD>::new().text_content(), "");
+    }
+
+    #[test]
+    fn test_fragment_json() {
+        let fragment = Fragment::<MD>::from(vec![p("a"), p(strong("b"))]);
+        let json = fragment.to_json().unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"type": "paragraph", "content": [{"type": "text", "marks": [], "text": "a"}]},
+                {"type": "paragraph", "content": [
+                    {"type": "text", "marks": [{"type": "strong"}], "text": "b"}
+                ]},
+            ])
+        );
+        assert_eq!(Fragment::<MD>::from_json(json).unwrap(), fragment);
+
+        let bad = serde_json::json!([
+            {"type": "paragraph"},
+            {"type": "horizontal_rule"},
+            {"type": "table", "content": []},
+        ]);
+        let err = Fragment::<MD>::from_json(bad).unwrap_err().to_string();
+        assert!(err.contains("index 2"), "{}", err);
+        assert!(err.contains("table"), "{}", err);
+
+        let err = Fragment::<MD>::from_json(serde_json::json!({})).unwrap_err();
+        assert!(err.to_string().contains("array"));
+    }
+
     #[test]
     fn test_size() {
         assert_eq!(node("Hello").node_size(), 5);
@@ -78,6 +900,371 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_slice_open_depths() {
+        let test_doc = doc(blockquote(p("abcd")));
+
+        // The whole paragraph
+        assert_eq!(
+            test_doc.slice(1..7, false),
+            Ok(Slice::new(Fragment::from((p("abcd"),)), 0, 0))
+        );
+        assert_eq!(
+            test_doc.slice(1..7, true),
+            Ok(Slice::new(Fragment::from((blockquote(p("abcd")),)), 1, 1))
+        );
+
+        // All of the text in the paragraph
+        assert_eq!(
+            test_doc.slice(2..6, false),
+            Ok(Slice::new(Fragment::from((node("abcd"),)), 0, 0))
+        );
+        assert_eq!(
+            test_doc.slice(2..6, true),
+            Ok(Slice::new(Fragment::from((blockquote(p("abcd")),)), 2, 2))
+        );
+
+        // Part of the text
+        assert_eq!(
+            test_doc.slice(3..5, true),
+            Ok(Slice::new(Fragment::from((blockquote(p("bc")),)), 2, 2))
+        );
+
+        // From the start of the paragraph into the text
+        assert_eq!(
+            test_doc.slice(1..4, false),
+            Ok(Slice::new(Fragment::from((p("ab"),)), 0, 1))
+        );
+
+        // The whole content
+        assert_eq!(
+            test_doc.slice(.., false),
+            Ok(Slice::new(Fragment::from((blockquote(p("abcd")),)), 0, 0))
+        );
+        assert_eq!(test_doc.slice(4..4, true), Ok(Slice::default()));
+    }
+
+    #[test]
+    fn test_slice_to_node() {
+        use MarkdownNodeType::*;
+        let test_doc = doc(vec![ul(li(vec![p("ab"), ul(li(p("cd")))])), p("ef")]);
+
+        // From inside the nested list into the last paragraph
+        let slice = test_doc.slice(10..18, false).unwrap();
+        assert_eq!((slice.open_start, slice.open_end), (5, 1));
+        let expected = doc(vec![
+            ul(li(vec![p(Fragment::new()), ul(li(p("d")))])),
+            p("e"),
+        ]);
+        assert_eq!(slice.to_node(Doc), Some(expected.clone()));
+        assert!(Doc.valid_content(expected.content_or_empty()));
+        assert_eq!(
+            slice.to_node(Blockquote),
+            Some(blockquote(expected.content_or_empty().clone()))
+        );
+
+        // Inline content
+        let slice = test_doc.slice(17..18, true).unwrap();
+        assert_eq!(slice.to_node(Doc), Some(doc(p("e"))));
+        let slice = test_doc.slice(17..18, false).unwrap();
+        assert_eq!(slice.to_node(Paragraph), Some(p("e")));
+        assert_eq!(slice.to_node(Doc), None);
+        assert_eq!(slice.to_node(Text), None);
+
+        assert_eq!(
+            Slice::<MD>::default().to_node(Doc),
+            Some(doc(p(Fragment::new())))
+        );
+    }
+
+    #[test]
+    fn test_resolve_split_character() {
+        assert_eq!(
+            super::util::try_split_at_utf16("a\u{1F60A}b", 3),
+            Some(("a\u{1F60A}", "b"))
+        );
+        assert_eq!(super::util::try_split_at_utf16("a\u{1F60A}b", 2), None);
+
+        let test_doc = doc(p("a\u{1F60A}b"));
+        assert!(test_doc.resolve(2).is_ok());
+        assert_eq!(
+            test_doc.resolve(3),
+            Err(ResolveErr::SplitCharacter { pos: 3 })
+        );
+        assert!(test_doc.resolve(4).is_ok());
+        assert_eq!(
+            test_doc.slice(1..3, false),
+            Err(SliceError::Resolve(ResolveErr::SplitCharacter { pos: 3 }))
+        );
+        assert!(test_doc.replace(3..4, &Default::default()).is_err());
+    }
+
+    #[test]
+    fn test_resolve_cache() {
+        let test_doc = doc(vec![
+            blockquote(vec![p("one"), ul(vec![li(p("two")), li(p("three"))])]),
+            p(vec![node("\u{1F60A}"), strong("four")]),
+        ]);
+        let size = test_doc.content_size();
+        let mut cache = ResolveCache::new(&test_doc);
+        // Forwards, backwards and repeated, to hit all cache paths
+        let positions = (0..=size + 1)
+            .chain((0..=size + 1).rev())
+            .chain(0..=size + 1);
+        for pos in positions {
+            assert_eq!(cache.resolve(pos), test_doc.resolve(pos), "pos {}", pos);
+        }
+
+        // A leaf as the root has no content to resolve into, but doesn't panic
+        let leaf = hr();
+        let mut cache = ResolveCache::<MD>::new(&leaf);
+        for pos in 0..=1 {
+            assert_eq!(cache.resolve(pos), leaf.resolve(pos), "pos {}", pos);
+        }
+        assert_eq!(leaf.resolve(1), Err(ResolveErr::RangeError { pos: 1 }));
+    }
+
+    #[test]
+    fn test_common_depth() {
+        let test_doc = doc(vec![p("ab"), blockquote(p("cd"))]);
+        let in_p1 = test_doc.resolve(1).unwrap();
+        let in_p2 = test_doc.resolve(7).unwrap();
+        assert_eq!(in_p2.depth, 2);
+        assert_eq!(in_p1.common_depth(&in_p2), 0);
+        assert_eq!(in_p2.common_depth(&in_p1), 0);
+
+        let in_quote = test_doc.resolve(5).unwrap();
+        assert_eq!(in_quote.common_depth(&in_p2), 1);
+        assert_eq!(in_p2.common_depth(&test_doc.resolve(8).unwrap()), 2);
+        assert_eq!(in_p1.common_depth(&test_doc.resolve(2).unwrap()), 1);
+    }
+
+    #[test]
+    fn test_resolve_clamped() {
+        let test_doc = doc(vec![p("ab"), p("a\u{1F60A}")]);
+        assert_eq!(
+            test_doc.resolve(11),
+            Err(ResolveErr::RangeError { pos: 11 })
+        );
+
+        let end = test_doc.resolve_clamped(11);
+        assert_eq!(end.pos, 9);
+        assert_eq!(end, test_doc.resolve(9).unwrap());
+        assert_eq!(test_doc.resolve_clamped(usize::MAX).pos, 9);
+        assert_eq!(test_doc.resolve_clamped(2), test_doc.resolve(2).unwrap());
+
+        let mid = test_doc.resolve_clamped(7);
+        assert_eq!(mid.pos, 6);
+        assert_eq!(mid.parent_offset, 1);
+    }
+
+    #[test]
+    fn test_pos_at_index() {
+        let test_doc = doc(vec![p("x"), p(vec![node("ab"), strong("cd"), node("e")])]);
+        let pos = test_doc.resolve(6).unwrap();
+        assert_eq!(pos.depth, 1);
+
+        let content = pos.parent().content().unwrap();
+        for index in 0..=pos.parent().child_count() {
+            let at = pos.pos_at_index(index, 1);
+            let offset = at - pos.start(1);
+            assert_eq!(
+                content.find_index(offset, false),
+                Ok(Index::new(index, offset))
+            );
+        }
+        assert_eq!(pos.pos_at_index(0, 1), 4);
+        assert_eq!(pos.pos_at_index(2, 1), 8);
+        assert_eq!(pos.pos_at_index(3, 1), 9);
+
+        assert_eq!(pos.pos_at_index(0, 0), 0);
+        assert_eq!(pos.pos_at_index(1, 0), 3);
+        assert_eq!(pos.pos_at_index(1, 0), pos.before(1).unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_pos_at_index_out_of_range() {
+        let test_doc = doc(vec![p("x"), p("y")]);
+        let pos = test_doc.resolve(1).unwrap();
+        pos.pos_at_index(3, 0);
+    }
+
+    #[test]
+    fn test_last_child() {
+        let para = p(vec![node("foo"), MarkdownNode::HardBreak]);
+        assert_eq!(para.first_child(), Some(&node("foo")));
+        assert_eq!(para.last_child(), Some(&MarkdownNode::HardBreak));
+        assert!(para.ends_with(MarkdownNodeType::HardBreak));
+        assert!(!para.ends_with(MarkdownNodeType::Text));
+
+        let empty = p(vec![]);
+        assert_eq!(empty.last_child(), None);
+        assert!(!empty.ends_with(MarkdownNodeType::HardBreak));
+        assert_eq!(MarkdownNode::HardBreak.last_child(), None);
+    }
+
+    #[test]
+    fn test_text_between() {
+        let test_doc = doc(vec![
+            p("ab"),
+            p(vec![node("c"), MarkdownNode::HardBreak, node("d")]),
+        ]);
+        assert_eq!(test_doc.text_between(2, 6, Some("\n"), None), "b\nc");
+        assert_eq!(test_doc.text_between(2, 6, None, None), "bc");
+        assert_eq!(test_doc.text_between(0, 10, Some("|"), Some("/")), "ab|c/d");
+        assert_eq!(test_doc.text_between(1, 1, Some("\n"), None), "");
+        assert_eq!(node("abc").text_between(1, 2, None, None), "b");
+
+        // Reversed bounds
+        assert_eq!(node("abc").text_between(2, 1, None, None), "");
+        assert_eq!(test_doc.text_between(6, 2, Some("\n"), None), "");
+    }
+
+    #[test]
+    fn test_fragment_from_iter() {
+        let nodes = vec![
+            p("one"),
+            h1("two"),
+            p("three"),
+            MarkdownNode::HorizontalRule,
+        ];
+        let paragraphs: Fragment<MD> = (nodes.into_iter())
+            .filter(|node| node.r#type() == MarkdownNodeType::Paragraph)
+            .collect();
+        assert_eq!(paragraphs, Fragment::from(vec![p("one"), p("three")]));
+        assert_eq!(paragraphs.size(), 12);
+
+        let mut all = paragraphs;
+        all.extend(vec![MarkdownNode::HorizontalRule, h1("four")]);
+        assert_eq!(all.child_count(), 4);
+        assert_eq!(all.size(), 19);
+    }
+
+    #[test]
+    fn test_child_out_of_range() {
+        let para = p(vec![node("foo"), MarkdownNode::HardBreak]);
+        assert_eq!(para.child(1), Some(&MarkdownNode::HardBreak));
+        assert_eq!(para.child(99), None);
+        assert_eq!(MarkdownNode::HardBreak.child(0), None);
+    }
+
+    #[test]
+    fn test_type_from_str() {
+        assert_eq!(
+            MD::node_type_from_str("bullet_list"),
+            Some(MarkdownNodeType::BulletList)
+        );
+        assert_eq!(
+            MD::node_type_from_str("horizontal_rule"),
+            Some(MarkdownNodeType::HorizontalRule)
+        );
+        assert_eq!(MD::node_type_from_str("unknown"), None);
+        assert_eq!(MD::node_type_from_str("BulletList"), None);
+
+        assert_eq!(MD::mark_type_from_str("em"), Some(MarkdownMarkType::Em));
+        assert_eq!(MD::mark_type_from_str("link"), Some(MarkdownMarkType::Link));
+        assert_eq!(MD::mark_type_from_str("unknown"), None);
+
+        for node in &[doc(p("a")), ul(li(p("a"))), MarkdownNode::HardBreak] {
+            let json = serde_json::to_value(node).unwrap();
+            let name = json["type"].as_str().unwrap();
+            assert_eq!(MD::node_type_from_str(name), Some(node.r#type()));
+        }
+    }
+
+    #[test]
+    fn test_node_type_metadata() {
+        use MarkdownNodeType::*;
+        let types = [
+            (Doc, false, false),
+            (Heading, true, false),
+            (CodeBlock, true, false),
+            (Text, false, true),
+            (Blockquote, false, false),
+            (Paragraph, true, false),
+            (BulletList, false, false),
+            (OrderedList, false, false),
+            (ListItem, false, false),
+            (HorizontalRule, false, true),
+            (HardBreak, false, true),
+            (Image, false, true),
+        ];
+        for &(r#type, is_textblock, is_leaf) in &types {
+            assert_eq!(r#type.is_textblock(), is_textblock, "{:?}", r#type);
+            let content_match = r#type.content_match();
+            assert_eq!(content_match.inline_content(), is_textblock, "{:?}", r#type);
+            assert_eq!(r#type.is_leaf(), is_leaf, "{:?}", r#type);
+            assert_eq!(MD::node_type_from_str(r#type.name()), Some(r#type));
+        }
+    }
+
+    #[test]
+    fn test_node_classification() {
+        let image = MarkdownNode::Image(Leaf {
+            attrs: ImageAttrs {
+                src: String::new(),
+                title: String::new(),
+                alt: String::new(),
+                extra: Default::default(),
+            },
+        });
+        let nodes = [
+            (doc(p("a")), true, false),
+            (h1("a"), true, true),
+            (code_block("", "a"), true, true),
+            (node("a"), false, false),
+            (blockquote(p("a")), true, false),
+            (p("a"), true, true),
+            (ul(li(p("a"))), true, false),
+            (ol(1, li(p("a"))), true, false),
+            (li(p("a")), true, false),
+            (MarkdownNode::HorizontalRule, true, false),
+            (MarkdownNode::HardBreak, false, false),
+            (image, false, false),
+        ];
+        for (node, is_block, is_textblock) in &nodes {
+            assert_eq!(node.is_block(), *is_block, "{:?}", node);
+            assert_eq!(node.is_inline(), !*is_block, "{:?}", node);
+            assert_eq!(node.is_textblock(), *is_textblock, "{:?}", node);
+        }
+    }
+
+    #[test]
+    fn test_marks_at() {
+        let test_doc = doc(p(vec![node("ab"), strong("cd"), node("ef")]));
+        let bold = MarkdownMark::strong().into_set();
+        let none = MarkSet::<MD>::default();
+
+        assert_eq!(test_doc.marks_at(0), none);
+        assert_eq!(test_doc.marks_at(1), none);
+        assert_eq!(test_doc.marks_at(3), none);
+        assert_eq!(test_doc.marks_at(4), bold);
+        assert_eq!(test_doc.marks_at(5), bold);
+        assert_eq!(test_doc.marks_at(6), none);
+        assert_eq!(test_doc.marks_at(7), none);
+        assert_eq!(test_doc.marks_at(9), none);
+        assert_eq!(test_doc.marks_at(100), none);
+
+        let starts_bold = doc(p(vec![strong("ab"), node("cd")]));
+        assert_eq!(starts_bold.marks_at(1), bold);
+
+        let link = MarkdownMark::Link {
+            attrs: LinkAttrs {
+                href: String::from("x"),
+                title: String::new(),
+                link_type: MarkdownLinkType::Inline,
+            },
+        };
+        let linked = MarkdownNode::Text(TextNode {
+            text: Text::from(String::from("cd")),
+            marks: link.clone().into_set(),
+        });
+        let link_doc = doc(p(vec![node("ab"), linked, node("ef")]));
+        assert_eq!(link_doc.marks_at(4), link.into_set());
+        assert_eq!(link_doc.marks_at(5), none);
+    }
+
     #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     struct Sol<'a> {
         node: &'a MarkdownNode,
@@ -85,7 +1272,7 @@ mod tests {
         end: usize,
     }
 
-    fn sol(node: &MarkdownNode, start: usize, end: usize) -> Sol {
+    fn sol(node: &MarkdownNode, start: usize, end: usize) -> Sol<'_> {
         Sol { node, start, end }
     }
 
@@ -149,6 +1336,37 @@ mod tests {
             assert_eq!(pos.parent_offset, *parent_offset);
             assert_eq!(pos.node_before(), *before);
             assert_eq!(pos.node_after(), *after);
+            assert_eq!(pos.path().len(), path.len());
+        }
+
+        let deep = ResolvedPos::<MD>::resolve(&test_doc, 9).unwrap();
+        let path: Vec<_> = (deep.path().iter())
+            .map(|rn| (rn.node.r#type(), rn.index, rn.before))
+            .collect();
+        assert_eq!(
+            path,
+            [
+                (MarkdownNodeType::Doc, 1, 4),
+                (MarkdownNodeType::Blockquote, 0, 5),
+                (MarkdownNodeType::Paragraph, 1, 8),
+            ]
+        );
+        for (depth, rn) in deep.path().iter().enumerate() {
+            assert!(std::ptr::eq(rn.node, deep.node(depth)));
+            assert_eq!(rn.index, deep.index(depth));
         }
     }
+
+    #[test]
+    fn test_text_split_at() {
+        let bold = |text: &str| strong(text).text_node().unwrap().clone();
+        let hello = bold("hello");
+        assert_eq!(hello.split_at(2), (bold("he"), bold("llo")));
+        assert_eq!(hello.split_at(0), (bold(""), hello.clone()));
+        assert_eq!(hello.split_at(5), (hello.clone(), bold("")));
+        assert_eq!(hello.split_at(5).1.text.len_utf16(), 0);
+
+        let emoji = bold("a\u{1F60A}b");
+        assert_eq!(emoji.split_at(3), (bold("a\u{1F60A}"), bold("b")));
+    }
 }
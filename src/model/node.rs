@@ -1,7 +1,8 @@
 use super::{
-    replace, util, ContentMatch, ContentMatchError, Fragment, MarkSet, ReplaceError, ResolveErr,
-    ResolvedPos, Schema, Slice, TextNode,
+    replace, util, ContentMatch, ContentMatchError, DepthError, Fragment, Mark, MarkSet,
+    ReplaceError, ResolveErr, ResolvedPos, Schema, Slice, TextNode,
 };
+use derivative::Derivative;
 use displaydoc::Display;
 use serde::{Deserialize, Serialize, Serializer};
 use std::borrow::Cow;
@@ -14,14 +15,24 @@ use thiserror::Error;
 pub enum SliceError {
     /// The given span was invalid
     Resolve(#[from] ResolveErr),
+    /// The open depth at the start ({0}) is deeper than the content ({1})
+    OpenStart(usize, usize),
+    /// The open depth at the end ({0}) is deeper than the content ({1})
+    OpenEnd(usize, usize),
     /// Unknown
     Unknown,
 }
 
 /// This is the type that encodes a kind of node
 pub trait NodeType<S: Schema>: Copy + Clone + Debug + PartialEq + Eq {
-    /// ???
+    /// True if this type and `other` accept the same kinds of content.
     fn compatible_content(self, other: Self) -> bool;
+
+    /// True if a node of type `other` can be joined onto a node of this type, i.e. when the
+    /// boundary between them is deleted.
+    fn can_join(self, other: Self) -> bool {
+        other.compatible_content(self)
+    }
     /// ???
     fn valid_content(self, fragment: &Fragment<S>) -> bool;
 
@@ -31,11 +42,54 @@ pub trait NodeType<S: Schema>: Copy + Clone + Debug + PartialEq + Eq {
     /// ???
     fn content_match(self) -> S::ContentMatch;
 
-    /// Check whether the given marks is allowed in this node.
+    /// The type of node to create as the first child of a node of this type, see
+    /// [`ContentMatch::default_type`].
+    fn default_type(self) -> Option<S::NodeType> {
+        self.content_match().default_type()
+    }
+
+    /// The node types that may be the first child of a node of this type, in the order of
+    /// [`Schema::node_types`], e.g. to show what can be inserted into an empty node.
+    fn allowed_child_types(self) -> Vec<S::NodeType> {
+        let content_match = self.content_match();
+        S::node_types()
+            .iter()
+            .copied()
+            .filter(|&r#type| content_match.match_type(r#type).is_some())
+            .collect()
+    }
+
+    /// Create a node of this type with default attributes and the given content, or `None` if
+    /// there are no sensible defaults, e.g. for text nodes. The content is not checked, see
+    /// [`NodeType::valid_content`].
+    fn create(self, _content: Fragment<S>) -> Option<S::Node> {
+        None
+    }
+
+    /// Create a node of this type with default attributes and the smallest valid content, e.g.
+    /// a document with an empty paragraph. Required children are created with their
+    /// [default type](NodeType::default_type) and filled in turn. Returns `None` if such a node
+    /// can't be created.
+    fn create_and_fill(self) -> Option<S::Node> {
+        let empty = Fragment::new();
+        if self.valid_content(&empty) {
+            return self.create(empty);
+        }
+        let child = self.default_type()?.create_and_fill()?;
+        let content = Fragment::from(vec![child]);
+        if self.valid_content(&content) {
+            self.create(content)
+        } else {
+            None
+        }
+    }
+
+    /// Check whether all of the given marks are allowed in this node.
     ///
-    /// By default, marks are allowed in inline nodes and textblocks, i.e. nodes that
-    /// have inline content.
-    fn allow_marks(self, marks: &MarkSet<S>) -> bool;
+    /// By default, this checks each mark with [`NodeType::allows_mark_type`].
+    fn allow_marks(self, marks: &MarkSet<S>) -> bool {
+        marks.into_iter().all(|m| self.allows_mark_type(m.r#type()))
+    }
 
     /// True if this is an inline type.
     fn is_inline(self) -> bool {
@@ -43,6 +97,30 @@ pub trait NodeType<S: Schema>: Copy + Clone + Debug + PartialEq + Eq {
     }
     /// True if this is a block type
     fn is_block(self) -> bool;
+
+    /// The name of this node type, as used in the JSON representation.
+    ///
+    /// This can't be derived from the other methods, so adding it made it a required method for
+    /// all `NodeType` impls, which is a breaking change.
+    fn name(self) -> &'static str;
+
+    /// True if this is a textblock type, a block that contains inline content.
+    ///
+    /// By default, this is a block type whose content match allows
+    /// [inline content](ContentMatch::inline_content).
+    fn is_textblock(self) -> bool {
+        self.is_block() && self.content_match().inline_content()
+    }
+
+    /// True for node types that allow no content.
+    ///
+    /// By default, this is a type whose content match accepts none of [`Schema::node_types`].
+    fn is_leaf(self) -> bool {
+        let content_match = self.content_match();
+        S::node_types()
+            .iter()
+            .all(|&r#type| content_match.match_type(r#type).is_none())
+    }
 }
 
 /// This class represents a node in the tree that makes up a ProseMirror document. So a document is
@@ -52,7 +130,8 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
 {
     /// The size of this node, as defined by the integer-based indexing scheme. For text nodes,
     /// this is the amount of characters. For other leaf nodes, it is one. For non-leaf nodes, it
-    /// is the size of the content plus two (the start and end token).
+    /// is the size of the content plus two (the start and end token). This doesn't recurse, as
+    /// fragments store their size.
     fn node_size(&self) -> usize {
         match self.content() {
             Some(c) => c.size() + 2,
@@ -66,14 +145,31 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
         }
     }
 
+    /// Check that this node has no more than `max_depth` levels of nested children, without
+    /// recursing. See [`with_max_depth`](super::with_max_depth) for why that matters.
+    fn check_depth(&self, max_depth: usize) -> Result<(), DepthError> {
+        let mut stack = vec![(self, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            if let Some(content) = node.content().filter(|c| c.child_count() > 0) {
+                if depth >= max_depth {
+                    return Err(DepthError(max_depth));
+                }
+                stack.extend(content.children().iter().map(|child| (child, depth + 1)));
+            }
+        }
+        Ok(())
+    }
+
     /// The number of children that the node has.
     fn child_count(&self) -> usize {
         self.content().map_or(0, Fragment::child_count)
     }
 
-    /// Get the child node at the given index. Raises an error when the index is out of range.
+    /// Get the child node at the given index, or `None` if the index is out of range.
+    ///
+    /// Unlike [`Fragment::child`], this never panics.
     fn child(&self, index: usize) -> Option<&Self> {
-        self.content().map(|c| c.child(index))
+        self.maybe_child(index)
     }
 
     /// Get the child node at the given index, if it exists.
@@ -85,7 +181,7 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
     fn mark(&self, marks: MarkSet<S>) -> Self;
 
     /// Create a copy of this node with only the content between the given positions.
-    fn cut<R: RangeBounds<usize>>(&self, range: R) -> Cow<Self> {
+    fn cut<R: RangeBounds<usize>>(&self, range: R) -> Cow<'_, Self> {
         let from = util::from(&range);
 
         if let Some(TextNode { text, marks }) = self.text_node() {
@@ -115,13 +211,20 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
     }
 
     /// Cut out the part of the document between the given positions, and return it as a `Slice` object.
+    ///
+    /// By default, the slice starts at the deepest node that contains both positions. With
+    /// `include_parents`, it starts at this node instead, so all ancestors of the range are part
+    /// of the slice, as open nodes. For example, in `doc(blockquote(p("abcd")))`, the range
+    /// `2..6` covers the text of the paragraph. Without parents, that slice is just the text
+    /// `"abcd"` with open depths of 0. With parents, it is `blockquote(p("abcd"))` with open
+    /// depths of 2 on both sides.
     fn slice<R: RangeBounds<usize> + Debug>(
         &self,
         range: R,
         include_parents: bool,
     ) -> Result<Slice<S>, SliceError> {
         let from = util::from(&range);
-        let to = util::to(&range, self.node_size());
+        let to = util::to(&range, self.content_size());
 
         if from == to {
             return Ok(Slice::default());
@@ -158,6 +261,16 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
         &self,
         range: R,
         slice: &Slice<S>,
+    ) -> Result<Self, ReplaceError<S>> {
+        self.replace_cow(range, Cow::Borrowed(slice))
+    }
+
+    /// Like [`Node::replace`], but takes the slice by value if possible, so that its content is
+    /// not cloned.
+    fn replace_cow<R: RangeBounds<usize> + Debug>(
+        &self,
+        range: R,
+        slice: Cow<'_, Slice<S>>,
     ) -> Result<Self, ReplaceError<S>> {
         let from = util::from(&range);
         let to = util::to(&range, self.node_size());
@@ -172,36 +285,158 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
         Ok(node)
     }
 
+    /// Replace the node that starts at `pos` with `new_node`, and rebuild its ancestors around it.
+    ///
+    /// This fails if no node starts at `pos`, or if the parent node does not allow `new_node` in
+    /// that place.
+    fn replace_node_at(&self, pos: usize, new_node: S::Node) -> Result<Self, ReplaceError<S>> {
+        let rp = self.resolve(pos)?;
+        let parent = rp.parent();
+        let index = rp.index(rp.depth);
+        if rp.text_offset() > 0 || index >= parent.child_count() {
+            return Err(ReplaceError::NoNodeAt(pos));
+        }
+        if !parent.can_replace_with(index, index + 1, new_node.r#type()) {
+            return Err(ReplaceError::InvalidContent(parent.r#type()));
+        }
+
+        let mut node = new_node;
+        for depth in (0..=rp.depth).rev() {
+            let index = rp.index(depth);
+            node = rp
+                .node(depth)
+                .copy(|c| c.replace_child(index, node).into_owned());
+        }
+        Ok(node)
+    }
+
+    /// Call `f` for every descendant node, with its position relative to the start of this
+    /// node's content. Doesn't descend into a node when `f` returns `false`.
+    fn descendants<'a, F: FnMut(&'a Self, usize) -> bool>(&'a self, f: &mut F) {
+        if let Some(content) = self.content() {
+            content.nodes_between(0, content.size(), f, 0);
+        }
+    }
+
+    /// Iterate over all descendant nodes in document order, like [`Node::descendants`].
+    ///
+    /// The items are `(depth, pos, node)`, where the children of this node have depth 1 and `pos`
+    /// is relative to the start of this node's content.
+    fn walk(&self) -> NodeIter<'_, S> {
+        let mut stack = Vec::new();
+        if let Some(content) = self.content() {
+            stack.push((content.children().iter(), 0));
+        }
+        NodeIter { stack }
+    }
+
+    /// All descendant nodes of the given type, in document order and with their positions.
+    fn nodes_of_type(&self, r#type: S::NodeType) -> Vec<(usize, &Self)> {
+        let mut nodes = Vec::new();
+        self.descendants(&mut |node, pos| {
+            if node.r#type() == r#type {
+                nodes.push((pos, node));
+            }
+            true
+        });
+        nodes
+    }
+
     /// Resolve the given position in the document, returning a struct with information about its
     /// context.
-    fn resolve(&self, pos: usize) -> Result<ResolvedPos<S>, ResolveErr> {
+    fn resolve(&self, pos: usize) -> Result<ResolvedPos<'_, S>, ResolveErr> {
         ResolvedPos::resolve(self, pos)
     }
 
+    /// Resolve the given position, after clamping it to the content of this node.
+    ///
+    /// A position that points into the middle of a character is moved to the start of that
+    /// character.
+    fn resolve_clamped(&self, pos: usize) -> ResolvedPos<'_, S> {
+        let pos = usize::min(pos, self.content_size());
+        match self.resolve(pos) {
+            Err(ResolveErr::SplitCharacter { .. }) => self.resolve(pos - 1),
+            res => res,
+        }
+        .expect("a clamped position should always resolve")
+    }
+
+    /// Get the marks at the given position, see [`ResolvedPos::marks`].
+    ///
+    /// Positions past the end of this node are clamped to the end.
+    fn marks_at(&self, pos: usize) -> MarkSet<S> {
+        self.resolve_clamped(pos).marks()
+    }
+
     /// Create a new node with the same markup as this node, containing the given content (or
     /// empty, if no content is given).
     fn copy<F>(&self, map: F) -> Self
     where
         F: FnOnce(&Fragment<S>) -> Fragment<S>;
 
+    /// The attributes of this node as JSON, like `node.attrs` in JS. Nodes without attributes
+    /// return an empty object.
+    fn attrs_json(&self) -> serde_json::Value {
+        let markup = self.copy(|_| Fragment::new());
+        match serde_json::to_value(&markup) {
+            Ok(serde_json::Value::Object(mut map)) => match map.remove("attrs") {
+                Some(attrs @ serde_json::Value::Object(_)) => attrs,
+                _ => serde_json::Value::Object(Default::default()),
+            },
+            _ => serde_json::Value::Object(Default::default()),
+        }
+    }
+
     /// Concatenates all the text nodes found in this fragment and its children.
     fn text_content(&self) -> String {
         if let Some(node) = self.text_node() {
             node.text.content.clone()
+        } else {
+            self.content()
+                .map(Fragment::text_content)
+                .unwrap_or_default()
+        }
+    }
+
+    /// Get all text between positions `from` and `to`, which are relative to the start of this
+    /// node's content, like positions in a document. See [`Fragment::text_between`] for the
+    /// meaning of `block_separator` and `leaf_text`. The result is empty if `from` is after `to`.
+    fn text_between(
+        &self,
+        from: usize,
+        to: usize,
+        block_separator: Option<&str>,
+        leaf_text: Option<&str>,
+    ) -> String {
+        if let Some(node) = self.text_node() {
+            let (_, rest) = util::split_at_utf16(node.text.as_str(), from);
+            util::split_at_utf16(rest, to.saturating_sub(from))
+                .0
+                .to_owned()
         } else {
             let mut buf = String::new();
-            if let Some(c) = self.content() {
-                c.text_between(&mut buf, true, 0, c.size(), Some(""), None);
+            if let Some(content) = self.content() {
+                content.text_between(&mut buf, true, from, to, block_separator, leaf_text);
             }
             buf
         }
     }
 
-    /// Returns this node's first child wrapped in `Some`, or `Node` if there are no children.
+    /// Returns this node's first child wrapped in `Some`, or `None` if there are no children.
     fn first_child(&self) -> Option<&S::Node> {
         self.content().and_then(Fragment::first_child)
     }
 
+    /// Returns this node's last child wrapped in `Some`, or `None` if there are no children.
+    fn last_child(&self) -> Option<&S::Node> {
+        self.content().and_then(Fragment::last_child)
+    }
+
+    /// True when this node has children and the last one is of the given type.
+    fn ends_with(&self, r#type: S::NodeType) -> bool {
+        matches!(self.last_child(), Some(c) if c.r#type() == r#type)
+    }
+
     /// Represents `.content.size` in JS
     fn content_size(&self) -> usize {
         self.content().map(Fragment::size).unwrap_or(0)
@@ -219,6 +454,13 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
     /// A container holding the node's children.
     fn content(&self) -> Option<&Fragment<S>>;
 
+    /// The node's children, or the shared empty fragment for leaf nodes.
+    ///
+    /// Use [`Node::content`] to tell leaf nodes from nodes without children.
+    fn content_or_empty(&self) -> &Fragment<S> {
+        self.content().unwrap_or(Fragment::EMPTY_REF)
+    }
+
     /// Get the marks on this node
     fn marks(&self) -> Option<&MarkSet<S>>;
 
@@ -230,7 +472,13 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
 
     /// True when this is an inline node (a text node or a node that can appear among text).
     fn is_inline(&self) -> bool {
-        self.r#type().is_inline()
+        !self.is_block()
+    }
+
+    /// True when this is a textblock node, a block node with inline content, see
+    /// [`NodeType::is_textblock`].
+    fn is_textblock(&self) -> bool {
+        self.r#type().is_textblock()
     }
 
     /// True when this is a text node.
@@ -247,10 +495,34 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
     fn content_match_at(&self, index: usize) -> Result<S::ContentMatch, ContentMatchError> {
         self.r#type()
             .content_match()
-            .match_fragment_range(&self.content().unwrap_or(Fragment::EMPTY_REF), 0..index)
+            .match_fragment_range(self.content_or_empty(), 0..index)
             .ok_or(ContentMatchError::InvalidContent)
     }
 
+    /// Test whether replacing the range between `from` and `to` (by child index) with a node of
+    /// the given type would leave the node's content valid.
+    fn can_replace_with(&self, from: usize, to: usize, r#type: S::NodeType) -> bool {
+        let end = self
+            .content_match_at(from)
+            .ok()
+            .and_then(|m| m.match_type(r#type))
+            .and_then(|m| m.match_fragment_range(self.content_or_empty(), to..));
+        matches!(end, Some(m) if m.valid_end())
+    }
+
+    /// Test whether a node of the given type can be inserted at `pos`, i.e. whether it fits
+    /// between the children of the node that the position points into. Returns `false` if the
+    /// position can't be resolved.
+    fn can_insert_at(&self, pos: usize, r#type: S::NodeType) -> bool {
+        match self.resolve(pos) {
+            Ok(rp) => {
+                let index = rp.index(rp.depth);
+                rp.parent().can_replace_with(index, index, r#type)
+            }
+            Err(_) => false,
+        }
+    }
+
     /// Test whether replacing the range between `from` and `to` (by
     /// child index) with the given replacement fragment (which defaults
     /// to the empty fragment) would leave the node's content valid. You
@@ -269,10 +541,8 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
 
         let one = self
             .content_match_at(from)?
-            .match_fragment_range(&replacement, start..end);
-        let two = one.and_then(|o| {
-            o.match_fragment_range(&self.content().unwrap_or(Fragment::EMPTY_REF), to..)
-        });
+            .match_fragment_range(replacement, start..end);
+        let two = one.and_then(|o| o.match_fragment_range(self.content_or_empty(), to..));
 
         if matches!(two, Some(m) if m.valid_end()) {
             for i in start..end {
@@ -292,6 +562,34 @@ pub trait Node<S: Schema<Node = Self> + 'static>:
     }
 }
 
+/// An iterator over the descendants of a node, see [`Node::walk`]
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""), Clone(bound = ""))]
+pub struct NodeIter<'a, S: Schema> {
+    /// The remaining children and the position of the next one, for each open node
+    stack: Vec<(std::slice::Iter<'a, S::Node>, usize)>,
+}
+
+impl<'a, S: Schema> Iterator for NodeIter<'a, S> {
+    type Item = (usize, usize, &'a S::Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (children, pos) = self.stack.last_mut()?;
+            if let Some(node) = children.next() {
+                let start = *pos;
+                *pos += node.node_size();
+                let depth = self.stack.len();
+                if let Some(content) = node.content().filter(|c| c.size() > 0) {
+                    self.stack.push((content.children().iter(), start + 1));
+                }
+                return Some((depth, start, node));
+            }
+            self.stack.pop();
+        }
+    }
+}
+
 /// A string that stores its length in utf-16
 #[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize)]
 #[serde(from = "String")]
@@ -311,6 +609,21 @@ impl Text {
         self.len_utf16
     }
 
+    /// The number of unicode scalar values (`char`s) in this string
+    pub fn len_chars(&self) -> usize {
+        self.content.chars().count()
+    }
+
+    /// The length of this string in bytes, i.e. if it were encoded in utf-8
+    pub fn len_bytes(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Returns true if this string has a length of zero
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
     /// Join two texts together
     pub fn join(&self, other: &Self) -> Self {
         let left = &self.content;
@@ -1,21 +1,25 @@
 use super::{fragment::IndexError, Index};
-use crate::model::{ContentMatchError, Fragment, Node, NodeType, ResolveErr, ResolvedPos, Schema};
+use crate::model::{
+    ContentMatchError, Fragment, Node, NodeType, ResolveErr, ResolvedPos, Schema, SliceError,
+};
 use crate::util::EitherOrBoth;
 use derivative::Derivative;
 use displaydoc::Display;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::convert::TryFrom;
 use thiserror::Error;
 
 /// A slice of a fragment
 #[derive(Derivative, Deserialize, Serialize)]
 #[derivative(
+    Clone(bound = ""),
     Debug(bound = ""),
     PartialEq(bound = ""),
     Eq(bound = ""),
     Default(bound = "")
 )]
-#[serde(bound = "", rename_all = "camelCase")]
+#[serde(bound = "", rename_all = "camelCase", try_from = "SliceDef<S>")]
 pub struct Slice<S: Schema> {
     /// The slice's content.
     pub content: Fragment<S>,
@@ -27,11 +31,34 @@ pub struct Slice<S: Schema> {
     pub open_end: usize,
 }
 
+/// The serialized form of a [`Slice`], whose open depths aren't checked yet
+#[derive(Deserialize)]
+#[serde(bound = "", rename_all = "camelCase")]
+struct SliceDef<S: Schema> {
+    content: Fragment<S>,
+    #[serde(default)]
+    open_start: usize,
+    #[serde(default)]
+    open_end: usize,
+}
+
+impl<S: Schema> TryFrom<SliceDef<S>> for Slice<S> {
+    type Error = SliceError;
+
+    fn try_from(def: SliceDef<S>) -> Result<Self, Self::Error> {
+        Slice::builder(def.content)
+            .open_start(def.open_start)
+            .open_end(def.open_end)
+            .build()
+    }
+}
+
 impl<S: Schema> Slice<S> {
     /// Create a slice. When specifying a non-zero open depth, you must
     /// make sure that there are nodes of at least that depth at the
     /// appropriate side of the fragment — i.e. if the fragment is an empty
     /// paragraph node, `openStart` and `openEnd` can't be greater than 1.
+    /// Use [`Slice::builder`] to have this checked.
     ///
     /// It is not necessary for the content of open nodes to conform to
     /// the schema's content constraints, though it should be a valid
@@ -45,6 +72,30 @@ impl<S: Schema> Slice<S> {
         }
     }
 
+    /// Start building a slice with the given content, whose open depths are checked by
+    /// [`SliceBuilder::build`].
+    pub fn builder(content: Fragment<S>) -> SliceBuilder<S> {
+        SliceBuilder {
+            content,
+            open_start: 0,
+            open_end: 0,
+        }
+    }
+
+    /// The size this slice would add when inserted into a document.
+    pub fn size(&self) -> usize {
+        self.content.size() - self.open_start - self.open_end
+    }
+
+    /// Wrap the content of this slice into a node of the given type, closing the open nodes at
+    /// both sides. Nodes whose content is no longer valid, like a list item that now starts
+    /// with a nested list, get a node of their [default type](NodeType::default_type) inserted
+    /// at the start. Returns `None` if the content can't be made valid this way.
+    pub fn to_node(&self, wrap_type: S::NodeType) -> Option<S::Node> {
+        let content = close_fragment(&self.content, self.open_start, self.open_end)?;
+        wrap_type.create(fill(wrap_type, content)?)
+    }
+
     pub(crate) fn insert_at(
         &self,
         pos: usize,
@@ -55,6 +106,100 @@ impl<S: Schema> Slice<S> {
     }
 }
 
+fn close_fragment<S: Schema>(
+    fragment: &Fragment<S>,
+    open_start: usize,
+    open_end: usize,
+) -> Option<Fragment<S>> {
+    let count = fragment.child_count();
+    if count == 1 && (open_start > 0 || open_end > 0) {
+        let node = close_node::<S>(
+            fragment.child(0),
+            open_start.saturating_sub(1),
+            open_end.saturating_sub(1),
+        )?;
+        return Some(Fragment::from(vec![node]));
+    }
+    let mut fragment = fragment.clone();
+    if open_start > 0 {
+        let node = close_node::<S>(fragment.first_child()?, open_start - 1, 0)?;
+        fragment = fragment.replace_child(0, node).into_owned();
+    }
+    if open_end > 0 {
+        let node = close_node::<S>(fragment.last_child()?, 0, open_end - 1)?;
+        fragment = fragment.replace_child(count - 1, node).into_owned();
+    }
+    Some(fragment)
+}
+
+fn close_node<S: Schema>(node: &S::Node, open_start: usize, open_end: usize) -> Option<S::Node> {
+    let content = close_fragment(node.content()?, open_start, open_end)?;
+    let content = fill(node.r#type(), content)?;
+    Some(node.copy(|_| content))
+}
+
+/// Make the content valid for the given type by inserting a node of its default type at the
+/// start, if necessary.
+fn fill<S: Schema>(r#type: S::NodeType, content: Fragment<S>) -> Option<Fragment<S>> {
+    if r#type.valid_content(&content) {
+        return Some(content);
+    }
+    let filler = r#type.default_type()?.create_and_fill()?;
+    let content = Fragment::from(vec![filler]).append(content);
+    Some(content).filter(|content| r#type.valid_content(content))
+}
+
+/// A builder for a [`Slice`] with validated open depths
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+pub struct SliceBuilder<S: Schema> {
+    content: Fragment<S>,
+    open_start: usize,
+    open_end: usize,
+}
+
+impl<S: Schema> SliceBuilder<S> {
+    /// Set the open depth at the start.
+    pub fn open_start(mut self, open_start: usize) -> Self {
+        self.open_start = open_start;
+        self
+    }
+
+    /// Set the open depth at the end.
+    pub fn open_end(mut self, open_end: usize) -> Self {
+        self.open_end = open_end;
+        self
+    }
+
+    /// Create the slice, checking that the content has nodes that can be open at least as deep
+    /// as the open depths on the respective side.
+    pub fn build(self) -> Result<Slice<S>, SliceError> {
+        let max_start = max_open_depth(&self.content, Fragment::first_child);
+        if self.open_start > max_start {
+            return Err(SliceError::OpenStart(self.open_start, max_start));
+        }
+        let max_end = max_open_depth(&self.content, Fragment::last_child);
+        if self.open_end > max_end {
+            return Err(SliceError::OpenEnd(self.open_end, max_end));
+        }
+        Ok(Slice::new(self.content, self.open_start, self.open_end))
+    }
+}
+
+/// The number of nodes with content along one side of the fragment
+fn max_open_depth<S: Schema>(
+    content: &Fragment<S>,
+    side: fn(&Fragment<S>) -> Option<&S::Node>,
+) -> usize {
+    let mut depth = 0;
+    let mut next = side(content).and_then(Node::content);
+    while let Some(fragment) = next {
+        depth += 1;
+        next = side(fragment).and_then(Node::content);
+    }
+    depth
+}
+
 /// Error on insertion
 #[derive(Debug, Display, Error)]
 pub enum InsertError {
@@ -87,12 +232,7 @@ fn insert_into<S: Schema>(
         ))
     } else {
         let child = child.unwrap(); // supposed to be safe, because of offset != diff
-        let inner = insert_into(
-            child.content().unwrap_or(Fragment::EMPTY_REF),
-            dist - offset - 1,
-            insert,
-            None,
-        )?;
+        let inner = insert_into(child.content_or_empty(), dist - offset - 1, insert, None)?;
         if let Some(i) = inner {
             Ok(Some(
                 content.replace_child(index, child.copy(|_| i)).into_owned(),
@@ -131,16 +271,26 @@ pub enum ReplaceError<S: Schema> {
     CannotJoin(S::NodeType, S::NodeType),
     /// Invalid content for node {0:?}
     InvalidContent(S::NodeType),
+    /// No node starts at position {0}
+    NoNodeAt(usize),
+}
+
+/// The content of the slice, cloned only if it is borrowed
+fn into_content<S: Schema>(slice: Cow<'_, Slice<S>>) -> Fragment<S> {
+    match slice {
+        Cow::Borrowed(slice) => slice.content.clone(),
+        Cow::Owned(slice) => slice.content,
+    }
 }
 
 pub(crate) fn replace<S: Schema>(
     rp_from: &ResolvedPos<S>,
     rp_to: &ResolvedPos<S>,
-    slice: &Slice<S>, // FIXME: use Cow?
+    slice: Cow<'_, Slice<S>>,
 ) -> Result<S::Node, ReplaceError<S>> {
     if slice.open_start > rp_from.depth {
         Err(ReplaceError::InsertTooDeep)
-    } else if rp_from.depth - slice.open_start != rp_to.depth - slice.open_end {
+    } else if rp_from.depth + slice.open_end != rp_to.depth + slice.open_start {
         Err(ReplaceError::InconsistentOpenDepths {
             from_depth: rp_from.depth,
             open_start: slice.open_start,
@@ -155,7 +305,7 @@ pub(crate) fn replace<S: Schema>(
 pub(crate) fn replace_outer<S: Schema>(
     rp_from: &ResolvedPos<S>,
     rp_to: &ResolvedPos<S>,
-    slice: &Slice<S>,
+    slice: Cow<'_, Slice<S>>,
     depth: usize,
 ) -> Result<S::Node, ReplaceError<S>> {
     let index = rp_from.index(depth);
@@ -164,9 +314,9 @@ pub(crate) fn replace_outer<S: Schema>(
         // When both `from` and `to` are in the same child and the we are not at an open node yet
         let inner = replace_outer(rp_from, rp_to, slice, depth + 1)?;
         Ok(node.copy(|c| c.replace_child(index, inner).into_owned()))
-    } else if slice.content.size() == 0 {
+    } else if slice.content.is_empty() {
         // When we just delete content, i.e. the replacement slice is empty
-        let content = replace_two_way(rp_from, &rp_to, depth)?;
+        let content = replace_two_way(rp_from, rp_to, depth)?;
         close(node, content)
     } else if slice.open_start == 0
         && slice.open_end == 0
@@ -178,18 +328,18 @@ pub(crate) fn replace_outer<S: Schema>(
 
         // Simple, flat case
         let parent = rp_from.parent();
-        let content = parent.content().unwrap_or(Fragment::EMPTY_REF);
+        let content = parent.content_or_empty();
 
         let new_content = content
             .cut(0..rp_from.parent_offset)
-            .append(slice.content.clone())
+            .append(into_content(slice))
             .append(content.cut(rp_to.parent_offset..));
         close(parent, new_content)
     } else {
-        let (n, start, end) = prepare_slice_for_replace(slice, &rp_from);
+        let (n, start, end) = prepare_slice_for_replace(slice, rp_from);
         let rp_start = n.resolve(start)?;
         let rp_end = n.resolve(end)?;
-        let content = replace_three_way(rp_from, &rp_start, &rp_end, &rp_to, depth)?;
+        let content = replace_three_way(rp_from, &rp_start, &rp_end, rp_to, depth)?;
         close(node, content)
     }
 }
@@ -197,7 +347,7 @@ pub(crate) fn replace_outer<S: Schema>(
 fn check_join<S: Schema>(main: &S::Node, sub: &S::Node) -> Result<(), ReplaceError<S>> {
     let sub_type = sub.r#type();
     let main_type = main.r#type();
-    if sub_type.compatible_content(main_type) {
+    if main_type.can_join(sub_type) {
         Ok(())
     } else {
         Err(ReplaceError::CannotJoin(sub_type, main_type))
@@ -229,7 +379,15 @@ fn add_node<S: Schema>(child: Cow<S::Node>, target: &mut Vec<S::Node>) {
 
 type Range<'b, 'a, S> = EitherOrBoth<&'b ResolvedPos<'a, S>, &'b ResolvedPos<'a, S>>;
 
+/// Add the children of the node at `depth` that lie after the left and before the right
+/// position of `range`. A missing side extends the range to that end of the node.
 fn add_range<S: Schema>(range: Range<S>, depth: usize, target: &mut Vec<S::Node>) {
+    if let Some((rp_start, rp_end)) = range.both() {
+        debug_assert!(
+            std::ptr::eq(rp_start.node(depth), rp_end.node(depth)),
+            "both ends of a range must point into the same node"
+        );
+    }
     let node = range.right_or_left().node(depth);
     let mut start_index = 0;
 
@@ -275,18 +433,18 @@ fn replace_three_way<S: Schema>(
     depth: usize,
 ) -> Result<Fragment<S>, ReplaceError<S>> {
     let open_start = if rp_from.depth > depth {
-        Some(joinable(&rp_from, &rp_start, depth + 1)?)
+        Some(joinable(rp_from, rp_start, depth + 1)?)
     } else {
         None
     };
     let open_end = if rp_to.depth > depth {
-        Some(joinable(&rp_end, rp_to, depth + 1)?)
+        Some(joinable(rp_end, rp_to, depth + 1)?)
     } else {
         None
     };
 
     let mut content = Vec::new();
-    add_range(Range::Right(&rp_from), depth, &mut content);
+    add_range(Range::Right(rp_from), depth, &mut content);
     match (open_start, open_end) {
         (Some(os), Some(oe)) if rp_start.index(depth) == rp_end.index(depth) => {
             check_join(os, oe)?;
@@ -296,11 +454,11 @@ fn replace_three_way<S: Schema>(
         }
         _ => {
             if let Some(os) = open_start {
-                let inner = replace_two_way(rp_from, &rp_start, depth + 1)?;
+                let inner = replace_two_way(rp_from, rp_start, depth + 1)?;
                 let closed = close(os, inner)?;
                 add_node::<S>(Cow::Owned(closed), &mut content);
             }
-            add_range(Range::Both(&rp_start, &rp_end), depth, &mut content);
+            add_range(Range::Both(rp_start, rp_end), depth, &mut content);
             if let Some(oe) = open_end {
                 let inner = replace_two_way(rp_end, rp_to, depth + 1)?;
                 let closed = close(oe, inner)?;
@@ -329,19 +487,20 @@ fn replace_two_way<S: Schema>(
     Ok(Fragment::from(content))
 }
 
-fn prepare_slice_for_replace<'a, S: Schema>(
-    slice: &'a Slice<S>,
-    rp_along: &ResolvedPos<'a, S>,
+fn prepare_slice_for_replace<S: Schema>(
+    slice: Cow<'_, Slice<S>>,
+    rp_along: &ResolvedPos<'_, S>,
 ) -> (S::Node, usize, usize) {
-    let extra = rp_along.depth - slice.open_start;
+    let (open_start, open_end) = (slice.open_start, slice.open_end);
+    let extra = rp_along.depth - open_start;
     let parent = rp_along.node(extra);
-    let mut node = parent.copy(|_| slice.content.clone());
+    let mut node = parent.copy(|_| into_content(slice));
     for i in (0..extra).rev() {
         node = rp_along.node(i).copy(|_| Fragment::from((node,)));
     }
 
-    let start = slice.open_start + extra;
-    let end = node.content_size() - slice.open_end - extra;
+    let start = open_start + extra;
+    let end = node.content_size() - open_end - extra;
     (node, start, end)
 }
 
@@ -352,6 +511,7 @@ mod tests {
     use crate::markdown::{MarkdownNode, MarkdownNodeType, MD};
     use crate::model::{Fragment, Node, Slice, SliceError};
     use displaydoc::Display;
+    use std::borrow::Cow;
     use std::fmt::Debug;
     use std::ops::{Range, RangeBounds};
     use thiserror::Error;
@@ -363,7 +523,7 @@ mod tests {
     }
 
     // insert.slice(insert.tag.a, insert.tag.b)
-    fn rpl<DR: RangeBounds<usize> + Debug, SR: RangeBounds<usize> + Debug>(
+    fn rpl<DR: RangeBounds<usize> + Debug + Clone, SR: RangeBounds<usize> + Debug>(
         (doc, range): (MarkdownNode, DR),
         insert: Option<(MarkdownNode, SR)>,
         expected: MarkdownNode,
@@ -372,7 +532,9 @@ mod tests {
             .map(|(n, r)| n.slice(r, false).unwrap())
             .unwrap_or_default();
 
-        assert_eq!(doc.replace(range, &slice), Ok(expected));
+        assert_eq!(doc.replace(range.clone(), &slice), Ok(expected.clone()));
+        // Taking the slice by value gives the same result
+        assert_eq!(doc.replace_cow(range, Cow::Owned(slice)), Ok(expected));
         Ok(())
     }
 
@@ -607,6 +769,18 @@ mod tests {
         bad::<_, Range<usize>>((t, 4..6), None, e);
     }
 
+    #[test]
+    fn can_join() {
+        use crate::model::NodeType;
+        use MarkdownNodeType::*;
+        assert!(!Blockquote.can_join(BulletList));
+        assert!(!BulletList.can_join(Blockquote));
+        assert!(BulletList.can_join(OrderedList));
+        assert!(Paragraph.can_join(Heading));
+        assert!(Blockquote.can_join(Doc));
+        assert!(!Paragraph.can_join(Blockquote));
+    }
+
     #[test]
     fn check_content_validity() {
         let t = doc(blockquote(p("hi"))); // 1..6
@@ -1,4 +1,4 @@
-use super::{fragment::IndexError, Fragment, Node, Schema};
+use super::{fragment::IndexError, util, Fragment, Mark, MarkSet, MarkType, Node, Schema};
 use derivative::Derivative;
 use displaydoc::Display;
 use std::borrow::Cow;
@@ -13,6 +13,11 @@ pub enum ResolveErr {
         /// The position that was out of range
         pos: usize,
     },
+    /// Position {pos} is in the middle of a character
+    SplitCharacter {
+        /// The position that would split the character
+        pos: usize,
+    },
     /// Index error
     Index(#[from] IndexError),
 }
@@ -76,6 +81,14 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
         }
     }
 
+    /// The path from the document to the parent node, one entry per depth.
+    ///
+    /// For each ancestor, this is the node, the index of the child that the position points
+    /// into or before, and the (absolute) position before that child.
+    pub fn path(&self) -> &[ResolvedNode<'a, S>] {
+        &self.path
+    }
+
     /// The parent node that the position points into. Note that even if
     /// a position points into a text node, that node is not considered
     /// the parent—text nodes are ‘flat’ in this model, and have no content.
@@ -123,6 +136,28 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
         self.start(depth) + self.node(depth).content().map(Fragment::size).unwrap_or(0)
     }
 
+    /// The (absolute) position directly before the child at `index` of the ancestor at the given
+    /// level.
+    ///
+    /// This sums the sizes of the children on the side of `index` that has fewer of them, using
+    /// the stored size of the content for the other side.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `depth` is greater than [`ResolvedPos::depth`], or when `index` is greater
+    /// than the number of children of that ancestor. An `index` equal to it gives the end of
+    /// the ancestor's content.
+    pub fn pos_at_index(&self, index: usize, depth: usize) -> usize {
+        let content = self.node(depth).content_or_empty();
+        let children = content.children();
+        if index > children.len() / 2 {
+            let after = children[index..].iter().map(Node::node_size).sum::<usize>();
+            self.start(depth) + content.size() - after
+        } else {
+            self.start(depth) + children[..index].iter().map(Node::node_size).sum::<usize>()
+        }
+    }
+
     /// The (absolute) position directly before the wrapping node at the given level, or, when
     /// depth is `self.depth + 1`, the original position.
     pub fn before(&self, depth: usize) -> Option<usize> {
@@ -156,7 +191,7 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
 
     /// Get the node directly before the position, if any. If the position points into a text node,
     /// only the part of that node before the position is returned.
-    pub fn node_before(&self) -> Option<Cow<S::Node>> {
+    pub fn node_before(&self) -> Option<Cow<'_, S::Node>> {
         let index = self.index(self.depth);
         let d_off = self.pos - self.path.last().unwrap().before;
         if d_off > 0 {
@@ -173,7 +208,7 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
 
     /// Get the node directly after the position, if any. If the position points into a text node,
     /// only the part of that node after the position is returned.
-    pub fn node_after(&self) -> Option<Cow<S::Node>> {
+    pub fn node_after(&self) -> Option<Cow<'_, S::Node>> {
         let parent = self.parent();
         let index = self.index(self.depth);
         if index == parent.child_count() {
@@ -188,6 +223,39 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
         }
     }
 
+    /// Get the marks at this position, factoring in the surrounding marks' `inclusive` property.
+    /// If the position is at the start of a non-empty node, the marks of the node after it are
+    /// returned.
+    pub fn marks(&self) -> MarkSet<S> {
+        let parent = self.parent();
+        let index = self.index(self.depth);
+
+        if self.text_offset() > 0 {
+            return parent
+                .child(index)
+                .and_then(Node::marks)
+                .cloned()
+                .unwrap_or_default();
+        }
+
+        let before = index.checked_sub(1).and_then(|i| parent.maybe_child(i));
+        let after = parent.maybe_child(index);
+        let (main, other) = match (before, after) {
+            (Some(before), after) => (before, after),
+            (None, Some(after)) => (after, None),
+            (None, None) => return MarkSet::default(),
+        };
+
+        let mut marks = main.marks().cloned().unwrap_or_default();
+        for mark in main.marks().into_iter().flatten() {
+            let in_other = matches!(other.and_then(Node::marks), Some(m) if m.contains(mark));
+            if !mark.r#type().inclusive() && !in_other {
+                marks.remove(mark);
+            }
+        }
+        marks
+    }
+
     /// The depth up to which this position and the given (non-resolved)
     /// position share the same parent nodes.
     pub fn shared_depth(&self, pos: usize) -> usize {
@@ -199,14 +267,36 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
         0
     }
 
+    /// The deepest depth at which this position and the given resolved position are in the
+    /// same node, i.e. the depth of their deepest common ancestor.
+    pub fn common_depth(&self, other: &ResolvedPos<'_, S>) -> usize {
+        let max = usize::min(self.depth, other.depth);
+        for depth in 0..max {
+            if self.index(depth) != other.index(depth)
+                || !std::ptr::eq(self.node(depth + 1), other.node(depth + 1))
+            {
+                return depth;
+            }
+        }
+        max
+    }
+
     pub(crate) fn resolve(doc: &'a S::Node, pos: usize) -> Result<Self, ResolveErr> {
-        if pos > doc.content().unwrap().size() {
+        if pos > doc.content_size() {
             return Err(ResolveErr::RangeError { pos });
         }
-        let mut path = vec![];
-        let mut start = 0;
-        let mut parent_offset = pos;
-        let mut node = doc;
+        Self::resolve_below(vec![], doc, 0, pos)
+    }
+
+    /// Resolve `pos`, which must be within the content of `node` (starting at `start`), given
+    /// the path down to `node`.
+    fn resolve_below(
+        mut path: Vec<ResolvedNode<'a, S>>,
+        mut node: &'a S::Node,
+        mut start: usize,
+        pos: usize,
+    ) -> Result<Self, ResolveErr> {
+        let mut parent_offset = pos - start;
 
         loop {
             let Index { index, offset } = node
@@ -223,7 +313,10 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
                 break;
             }
             node = node.child(index).unwrap();
-            if node.is_text() {
+            if let Some(text_node) = node.text_node() {
+                if util::try_split_at_utf16(text_node.text.as_str(), rem).is_none() {
+                    return Err(ResolveErr::SplitCharacter { pos });
+                }
                 break;
             }
             parent_offset = rem - 1;
@@ -233,6 +326,124 @@ impl<'a, S: Schema> ResolvedPos<'a, S> {
     }
 }
 
+/// Represents a flat range of content, i.e. one that starts and ends in the same node.
+#[derive(Derivative)]
+#[derivative(
+    Debug(bound = ""),
+    Clone(bound = ""),
+    PartialEq(bound = ""),
+    Eq(bound = "")
+)]
+pub struct NodeRange<'a, S: Schema> {
+    /// A resolved position along the start of the content. May have a depth greater than this
+    /// object's `depth` property, since these are the positions that were used to compute the
+    /// range, not re-resolved positions directly at its boundaries.
+    pub from: ResolvedPos<'a, S>,
+    /// A position along the end of the content.
+    pub to: ResolvedPos<'a, S>,
+    /// The depth of the node that this range points into.
+    pub depth: usize,
+}
+
+impl<'a, S: Schema> NodeRange<'a, S> {
+    /// Construct a node range. `from` and `to` should point into the same node until at least
+    /// the given `depth`, since a node range denotes an adjacent set of nodes in a single
+    /// parent node.
+    pub fn new(from: ResolvedPos<'a, S>, to: ResolvedPos<'a, S>, depth: usize) -> Self {
+        NodeRange { from, to, depth }
+    }
+
+    /// The position at the start of the range.
+    pub fn start(&self) -> usize {
+        self.from.before(self.depth + 1).unwrap()
+    }
+
+    /// The position at the end of the range.
+    pub fn end(&self) -> usize {
+        self.to.after(self.depth + 1).unwrap()
+    }
+
+    /// The parent node that the range points into.
+    pub fn parent(&self) -> &'a S::Node {
+        self.from.node(self.depth)
+    }
+
+    /// The start index of the range in the parent node.
+    pub fn start_index(&self) -> usize {
+        self.from.index(self.depth)
+    }
+
+    /// The end index of the range in the parent node.
+    pub fn end_index(&self) -> usize {
+        self.to.index_after(self.depth)
+    }
+}
+
+/// The number of positions kept by a [`ResolveCache`].
+const RESOLVE_CACHE_SIZE: usize = 12;
+
+/// A cache for resolving many positions in the same document.
+///
+/// This keeps the last few resolved positions. When a position is resolved, the walk from the
+/// root starts at the deepest node of a cached position that also contains the new position,
+/// so resolving positions near each other only has to look at the innermost nodes.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+pub struct ResolveCache<'a, S: Schema> {
+    doc: &'a S::Node,
+    entries: Vec<ResolvedPos<'a, S>>,
+    next: usize,
+}
+
+impl<'a, S: Schema> ResolveCache<'a, S> {
+    /// Create an empty cache for the given document.
+    pub fn new(doc: &'a S::Node) -> Self {
+        ResolveCache {
+            doc,
+            entries: Vec::with_capacity(RESOLVE_CACHE_SIZE),
+            next: 0,
+        }
+    }
+
+    /// Resolve the given position. The result is the same as that of `Node::resolve`.
+    pub fn resolve(&mut self, pos: usize) -> Result<ResolvedPos<'a, S>, ResolveErr> {
+        if let Some(cached) = self.entries.iter().find(|rp| rp.pos == pos) {
+            return Ok(cached.clone());
+        }
+        if pos > self.doc.content_size() {
+            return Err(ResolveErr::RangeError { pos });
+        }
+
+        let mut best = None;
+        for rp in &self.entries {
+            let depth = (0..=rp.depth)
+                .rev()
+                .find(|&d| rp.start(d) <= pos && pos <= rp.end(d))
+                .unwrap_or(0);
+            if !matches!(best, Some((_, d)) if d >= depth) {
+                best = Some((rp, depth));
+            }
+        }
+        let resolved = match best {
+            Some((rp, depth)) if depth > 0 => ResolvedPos::resolve_below(
+                rp.path[..depth].to_vec(),
+                rp.node(depth),
+                rp.start(depth),
+                pos,
+            ),
+            _ => ResolvedPos::resolve(self.doc, pos),
+        }?;
+
+        if self.entries.len() < RESOLVE_CACHE_SIZE {
+            self.entries.push(resolved.clone());
+        } else {
+            self.entries[self.next] = resolved.clone();
+        }
+        self.next = (self.next + 1) % RESOLVE_CACHE_SIZE;
+        Ok(resolved)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct Index {
     pub index: usize,
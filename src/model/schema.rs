@@ -1,6 +1,8 @@
+use super::util;
 use super::{ContentMatch, Fragment, Mark, MarkSet, Node, NodeType, Text};
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::fmt::Debug;
 
 /// This type represents a schema.
@@ -15,6 +17,37 @@ pub trait Schema: Sized + 'static {
     type NodeType: NodeType<Self>;
     /// This type represents the `ContentMatch` impl
     type ContentMatch: ContentMatch<Self>;
+
+    /// Look up a node type by the name it has in the JSON representation.
+    ///
+    /// Defaults to searching [`Schema::node_types`] for a type with that [name](NodeType::name).
+    fn node_type_from_str(name: &str) -> Option<Self::NodeType> {
+        Self::node_types()
+            .iter()
+            .copied()
+            .find(|t| t.name() == name)
+    }
+
+    /// Look up a mark type by the name it has in the JSON representation.
+    ///
+    /// Defaults to `None`, i.e. no names are known.
+    fn mark_type_from_str(_name: &str) -> Option<Self::MarkType> {
+        None
+    }
+
+    /// All node types of this schema, in the order they are declared in.
+    ///
+    /// This is the one list of node types a schema has to maintain:
+    /// [`Schema::node_type_from_str`] and [`NodeType::allowed_child_types`] are derived from it,
+    /// so it needs to be complete.
+    fn node_types() -> &'static [Self::NodeType];
+
+    /// The smallest valid document in this schema, e.g. to initialize an editor.
+    ///
+    /// For ProseMirror's default top node `doc` with `block+` content, this is a document with a
+    /// single empty paragraph. [`NodeType::create_and_fill`] can build it for schemas that
+    /// implement [`NodeType::create`].
+    fn empty_doc() -> Self::Node;
 }
 
 /// A simple block node
@@ -28,8 +61,8 @@ pub trait Schema: Sized + 'static {
 )]
 #[serde(bound = "")]
 pub struct Block<S: Schema> {
-    /// The content.
-    #[serde(default)]
+    /// The content, left out of the JSON when empty.
+    #[serde(default, skip_serializing_if = "Fragment::is_empty")]
     #[derivative(Debug(bound = ""))]
     pub content: Fragment<S>,
 }
@@ -58,14 +91,26 @@ impl<S: Schema> Block<S> {
 #[serde(bound = "A: for<'d> Deserialize<'d> + Serialize")]
 pub struct AttrNode<S: Schema, A> {
     /// Attributes
+    ///
+    /// These are left out of the JSON when they serialize to an empty map, so the attributes
+    /// type should also deserialize from a missing value if that can happen.
+    #[serde(skip_serializing_if = "attrs_are_empty")]
     pub attrs: A,
 
-    /// The content.
-    #[serde(default)]
+    /// The content, left out of the JSON when empty.
+    #[serde(default, skip_serializing_if = "Fragment::is_empty")]
     #[derivative(Debug(bound = ""))]
     pub content: Fragment<S>,
 }
 
+/// Whether the attributes serialize to an empty map, like a document without attributes
+fn attrs_are_empty<A: Serialize>(attrs: &A) -> bool {
+    match serde_json::to_value(attrs) {
+        Ok(Value::Object(map)) => map.is_empty(),
+        _ => false,
+    }
+}
+
 impl<S: Schema, A: Clone> AttrNode<S, A> {
     /// Copies this block, mapping the content
     pub fn copy<F>(&self, map: F) -> Self
@@ -111,6 +156,20 @@ impl<S: Schema> TextNode<S> {
             text,
         }
     }
+
+    /// Split this node at a utf-16 `offset` into two nodes with the same marks.
+    ///
+    /// An offset of `0` gives an empty first node, an offset at or after the end gives an empty
+    /// second one.
+    ///
+    /// Panics if `offset` points into the middle of a character.
+    pub fn split_at(&self, offset: usize) -> (Self, Self) {
+        let (left, right) = util::split_at_utf16(self.text.as_str(), offset);
+        (
+            self.with_text(Text::from(left.to_owned())),
+            self.with_text(Text::from(right.to_owned())),
+        )
+    }
 }
 
 /// A leaf node (just attributes)
@@ -124,4 +183,11 @@ pub struct Leaf<A> {
 /// things like emphasis or being part of a link) are
 /// [tagged](#model.Mark.type) with type objects, which are
 /// instantiated once per `Schema`.
-pub trait MarkType: Copy + Clone + Debug + PartialEq + Eq + PartialOrd + Ord {}
+pub trait MarkType: Copy + Clone + Debug + PartialEq + Eq + PartialOrd + Ord {
+    /// Whether marks of this type should be active when the cursor is positioned at its end.
+    ///
+    /// Defaults to `true`.
+    fn inclusive(self) -> bool {
+        true
+    }
+}
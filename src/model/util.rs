@@ -16,20 +16,26 @@ pub fn to<R: RangeBounds<usize>>(range: &R, max: usize) -> usize {
     }
 }
 
-pub fn split_at_utf16(text: &str, mut index: usize) -> (&str, &str) {
+pub fn split_at_utf16(text: &str, index: usize) -> (&str, &str) {
+    try_split_at_utf16(text, index).expect("Can't split in the middle of a character")
+}
+
+/// Like `split_at_utf16`, but returns `None` instead of panicking if `index` points into the
+/// middle of a character
+pub fn try_split_at_utf16(text: &str, mut index: usize) -> Option<(&str, &str)> {
     let mut iter = text.chars();
     while index > 0 {
         if let Some(c) = iter.next() {
             let l = c.len_utf16();
             if l > index {
-                panic!("Can't split in the middle of a character")
+                return None;
             } else {
                 index -= l;
             }
         } else {
-            return (text, "");
+            return Some((text, ""));
         }
     }
     let mid = text.len() - iter.as_str().len();
-    text.split_at(mid)
+    Some(text.split_at(mid))
 }
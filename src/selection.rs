@@ -0,0 +1,142 @@
+//! # Selections
+//!
+//! A selection is a range in a document that follows the edits made to it, by mapping it through
+//! the [`Mapping`] of the steps.
+use crate::model::{ContentMatch, Node, NodeType, ResolveErr, ResolvedPos, Schema};
+use crate::transform::{Assoc, Mapping};
+use displaydoc::Display;
+use thiserror::Error;
+
+/// An error when resolving a selection in a document
+#[derive(Debug, Clone, PartialEq, Eq, Display, Error)]
+pub enum SelectionError {
+    /// The position could not be resolved
+    Resolve(#[from] ResolveErr),
+    /// Position {0} does not point into inline content
+    NotInline(usize),
+}
+
+/// A selection of text, possibly empty, i.e. a cursor
+///
+/// The `anchor` is the side that stays in place when the selection is extended, the `head` is the
+/// side that moves. The head may come before the anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextSelection {
+    /// The side of the selection that doesn't move
+    pub anchor: usize,
+    /// The side of the selection that moves
+    pub head: usize,
+}
+
+impl TextSelection {
+    /// Create a selection from `anchor` to `head`
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self { anchor, head }
+    }
+
+    /// Create an empty selection at `pos`
+    pub fn cursor(pos: usize) -> Self {
+        Self::new(pos, pos)
+    }
+
+    /// The lower end of the selection
+    pub fn from(&self) -> usize {
+        usize::min(self.anchor, self.head)
+    }
+
+    /// The upper end of the selection
+    pub fn to(&self) -> usize {
+        usize::max(self.anchor, self.head)
+    }
+
+    /// Whether the selection is a cursor
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// Map the selection through the changes of some steps.
+    ///
+    /// Like in prosemirror, both sides move after content that is inserted right at them, and a
+    /// selection whose content was deleted collapses to a cursor.
+    pub fn map(&self, mapping: &Mapping) -> TextSelection {
+        TextSelection {
+            anchor: mapping.map(self.anchor, Assoc::After),
+            head: mapping.map(self.head, Assoc::After),
+        }
+    }
+
+    /// Resolve the anchor and head in `doc`, checking that both point into inline content.
+    pub fn resolve<'a, S: Schema>(
+        &self,
+        doc: &'a S::Node,
+    ) -> Result<(ResolvedPos<'a, S>, ResolvedPos<'a, S>), SelectionError> {
+        let resolve = |pos| {
+            let rp = doc.resolve(pos)?;
+            if rp.parent().r#type().content_match().inline_content() {
+                Ok(rp)
+            } else {
+                Err(SelectionError::NotInline(pos))
+            }
+        };
+        Ok((resolve(self.anchor)?, resolve(self.head)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SelectionError, TextSelection};
+    use crate::markdown::{helper::*, MD};
+    use crate::model::{Fragment, Node, ResolveErr, Slice};
+    use crate::transform::{Mapping, ReplaceStep, StepKind};
+
+    fn insert(pos: usize, text: &str) -> Mapping {
+        let d1 = doc(p("Hello World"));
+        let slice = Slice::new(Fragment::from(node(text)), 0, 0);
+        let step = ReplaceStep::<MD>::between(&d1, pos, pos, slice).unwrap();
+        let mut mapping = Mapping::new();
+        mapping.append_map(step.get_map());
+        mapping
+    }
+
+    #[test]
+    fn test_map() {
+        // "World"
+        let sel = TextSelection::new(12, 7);
+        assert_eq!((sel.from(), sel.to()), (7, 12));
+
+        // Before it
+        assert_eq!(sel.map(&insert(1, "Oh, ")), TextSelection::new(16, 11));
+        // Inside of it
+        assert_eq!(sel.map(&insert(9, "X")), TextSelection::new(13, 7));
+        // Right at its start and end
+        assert_eq!(sel.map(&insert(7, "X")), TextSelection::new(13, 8));
+        assert_eq!(sel.map(&insert(12, "X")), TextSelection::new(13, 7));
+
+        let cursor = TextSelection::cursor(7);
+        assert_eq!(cursor.map(&insert(7, "big ")), TextSelection::cursor(11));
+        assert!(cursor.map(&insert(3, "X")).is_empty());
+
+        let d1 = doc(p("Hello World"));
+        let delete = ReplaceStep::<MD>::between(&d1, 6, 12, Slice::default()).unwrap();
+        let mut mapping = Mapping::new();
+        mapping.append_map(delete.get_map());
+        assert_eq!(sel.map(&mapping), TextSelection::cursor(6));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let d1 = doc(vec![p("Hello"), p("World")]);
+        let (anchor, head) = TextSelection::new(2, 9).resolve::<MD>(&d1).unwrap();
+        assert_eq!(anchor.parent(), d1.child(0).unwrap());
+        assert_eq!(head.parent(), d1.child(1).unwrap());
+
+        assert_eq!(
+            TextSelection::new(2, 7).resolve::<MD>(&d1).err(),
+            Some(SelectionError::NotInline(7))
+        );
+        assert_eq!(
+            TextSelection::cursor(20).resolve::<MD>(&d1).err(),
+            Some(SelectionError::Resolve(ResolveErr::RangeError { pos: 20 }))
+        );
+    }
+}
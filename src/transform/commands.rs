@@ -0,0 +1,73 @@
+use super::{AddMarkStep, Assoc, RemoveMarkStep, ReplaceStep, Span, Step, StepError, StepKind};
+use crate::model::{Mark, Node, NodeType, Schema, Slice};
+
+/// Toggle the given mark on the range between `from` and `to`.
+///
+/// If every inline node in the range already has a mark of that type, the mark is removed.
+/// Otherwise, i.e. when the mark is missing from some or all of the range, it is added. Only
+/// inline nodes whose parent allows the mark count, like text in a paragraph but not in a code
+/// block. An empty range, or one where the mark can't be applied anywhere, produces no steps.
+pub fn toggle_mark<S: Schema>(
+    doc: &S::Node,
+    from: usize,
+    to: usize,
+    mark: S::Mark,
+) -> Vec<Step<S>> {
+    if from >= to {
+        return Vec::new();
+    }
+
+    let mark_type = mark.r#type();
+    let mut has_inline = false;
+    let mut all_marked = true;
+    // The children of a node are visited right after it, so the last non-inline node that was
+    // visited is the parent of the inline nodes that follow
+    let mut parent_allows = doc.r#type().allows_mark_type(mark_type);
+    let content = doc.content_or_empty();
+    content.nodes_between(
+        from,
+        to,
+        &mut |node, _pos| {
+            if !node.is_inline() {
+                parent_allows = node.r#type().allows_mark_type(mark_type);
+            } else if parent_allows {
+                has_inline = true;
+                let marked = node
+                    .marks()
+                    .into_iter()
+                    .flatten()
+                    .any(|m| m.r#type() == mark_type);
+                all_marked &= marked;
+            }
+            all_marked
+        },
+        0,
+    );
+
+    let span = Span { from, to };
+    if !has_inline {
+        Vec::new()
+    } else if all_marked {
+        vec![Step::RemoveMark(RemoveMarkStep { span, mark })]
+    } else {
+        vec![Step::AddMark(AddMarkStep { span, mark })]
+    }
+}
+
+/// Replace the selection between `from` and `to` with the slice, and return the new document
+/// together with the position after the inserted content, where the cursor goes.
+///
+/// Like in an editor, the selection collapses to a cursor. For an empty slice, i.e. deleting the
+/// selection, that is `from`. If the slice has open nodes at the end, the cursor is placed inside
+/// the last of them.
+pub fn replace_selection<S: Schema>(
+    doc: &S::Node,
+    from: usize,
+    to: usize,
+    slice: Slice<S>,
+) -> Result<(S::Node, usize), StepError<S>> {
+    let step = ReplaceStep::between(doc, from, to, slice)?;
+    let new_doc = step.apply(doc)?;
+    let cursor = step.get_map().map(to, Assoc::After);
+    Ok((new_doc, cursor))
+}
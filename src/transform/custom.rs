@@ -0,0 +1,110 @@
+use super::{Mapping, Step, StepKind};
+use crate::model::Schema;
+use derivative::Derivative;
+use displaydoc::Display;
+use serde::de::{DeserializeOwned, DeserializeSeed, Deserializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use thiserror::Error;
+
+/// A step type that is not built into this crate.
+///
+/// Custom steps are (de)serialized through [`Step::Other`]. To deserialize them, their
+/// `stepType` needs to be registered in a [`StepRegistry`].
+pub trait CustomStep<S: Schema>: StepKind<S> + Debug + Send + Sync + 'static {
+    /// The `stepType` that identifies this step in JSON
+    fn step_type(&self) -> &'static str;
+
+    /// Serialize the fields of this step, excluding the `stepType` tag.
+    ///
+    /// This is usually implemented as `serde_json::to_value(self)`.
+    fn to_json(&self) -> Result<Value, serde_json::Error>;
+
+    /// Map this step through the changes in `mapping`, returning `None` if it no longer applies.
+    ///
+    /// The default drops the step.
+    fn map(&self, _mapping: &Mapping) -> Option<Box<dyn CustomStep<S>>> {
+        None
+    }
+}
+
+impl<S: Schema> PartialEq for dyn CustomStep<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.step_type() == other.step_type()
+            && matches!((self.to_json(), other.to_json()), (Ok(a), Ok(b)) if a == b)
+    }
+}
+
+impl<S: Schema> Eq for dyn CustomStep<S> {}
+
+/// The `stepType`s of the steps that are built into [`Step`]
+const BUILT_IN_STEP_TYPES: [&str; 4] = ["replace", "replaceAround", "addMark", "removeMark"];
+
+/// Error when registering a custom step type
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum RegisterStepError {
+    /// `{0}` is the type of a built-in step
+    BuiltIn(String),
+    /// The step type `{0}` is already registered
+    Duplicate(String),
+}
+
+type DeserializeFn<S> = fn(Value) -> Result<Box<dyn CustomStep<S>>, serde_json::Error>;
+
+fn deserialize_step<S: Schema, T: CustomStep<S> + DeserializeOwned>(
+    value: Value,
+) -> Result<Box<dyn CustomStep<S>>, serde_json::Error> {
+    let step: T = serde_json::from_value(value)?;
+    Ok(Box::new(step))
+}
+
+/// The custom step types that can be deserialized, in addition to the built-in ones.
+///
+/// Deserializing a [`Step`] on its own only knows the built-in step types. To read custom steps,
+/// register their types here and use the registry as a [`DeserializeSeed`], e.g.
+/// `registry.deserialize(&mut serde_json::Deserializer::from_str(json))`.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+pub struct StepRegistry<S: Schema> {
+    steps: HashMap<String, DeserializeFn<S>>,
+}
+
+impl<S: Schema> StepRegistry<S> {
+    /// Create a registry without any custom step types
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a custom step type, so that steps with the given `stepType` deserialize to a
+    /// [`Step::Other`] containing a `T`.
+    ///
+    /// Fails if `step_type` is the type of a built-in step, or has already been registered.
+    pub fn register<T: CustomStep<S> + DeserializeOwned>(
+        &mut self,
+        step_type: &str,
+    ) -> Result<(), RegisterStepError> {
+        if BUILT_IN_STEP_TYPES.contains(&step_type) {
+            return Err(RegisterStepError::BuiltIn(step_type.to_owned()));
+        }
+        if self.steps.contains_key(step_type) {
+            return Err(RegisterStepError::Duplicate(step_type.to_owned()));
+        }
+        self.steps
+            .insert(step_type.to_owned(), deserialize_step::<S, T>);
+        Ok(())
+    }
+
+    /// Look up the deserializer registered for `step_type`
+    pub(crate) fn get(&self, step_type: &str) -> Option<DeserializeFn<S>> {
+        self.steps.get(step_type).copied()
+    }
+}
+
+impl<'de, S: Schema> DeserializeSeed<'de> for &StepRegistry<S> {
+    type Value = Step<S>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Step<S>, D::Error> {
+        Step::deserialize_with(deserializer, Some(self))
+    }
+}
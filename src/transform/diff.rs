@@ -0,0 +1,145 @@
+use super::{AddMarkStep, RemoveMarkStep, ReplaceStep, Span, Step};
+use crate::model::{MarkSet, Node, Schema};
+
+/// Compute steps that turn `old` into `new`.
+///
+/// The changed region is found with [`Fragment::find_diff_start`] and
+/// [`Fragment::find_diff_end`]. If the documents only differ in the marks on that region, the
+/// result is a list of mark steps. Otherwise, it is a single [`ReplaceStep`] for the whole
+/// region. Identical documents produce no steps.
+pub fn diff<S: Schema>(old: &S::Node, new: &S::Node) -> Vec<Step<S>> {
+    let content_a = old.content_or_empty();
+    let content_b = new.content_or_empty();
+    let start = match content_a.find_diff_start(content_b) {
+        Some(start) => start,
+        None => return Vec::new(),
+    };
+    let (mut end_a, mut end_b) = content_a
+        .find_diff_end(content_b)
+        .expect("fragments that differ at the start also differ at the end");
+    // The common prefix and suffix may overlap when content was repeated
+    let overlap = start.saturating_sub(usize::min(end_a, end_b));
+    end_a += overlap;
+    end_b += overlap;
+
+    if end_a == end_b {
+        if let Some(steps) = mark_steps(old, new, start, end_a) {
+            return steps;
+        }
+    }
+
+    let slice = new
+        .slice(start..end_b, false)
+        .expect("positions from find_diff_end are valid");
+    vec![Step::Replace(ReplaceStep {
+        span: Span {
+            from: start,
+            to: end_a,
+        },
+        slice,
+        structure: false,
+    })]
+}
+
+/// The inline nodes in the range, as `(from, to, marks)` with the positions clipped to the range
+fn inline_spans<S: Schema>(
+    doc: &S::Node,
+    from: usize,
+    to: usize,
+) -> Vec<(usize, usize, MarkSet<S>)> {
+    let mut spans = Vec::new();
+    if let Some(content) = doc.content() {
+        content.nodes_between(
+            from,
+            to,
+            &mut |node, pos| {
+                if node.is_inline() {
+                    let marks = node.marks().cloned().unwrap_or_default();
+                    let start = usize::max(pos, from);
+                    let end = usize::min(pos + node.node_size(), to);
+                    spans.push((start, end, marks));
+                }
+                true
+            },
+            0,
+        );
+    }
+    spans
+}
+
+/// Try to describe the change in the range with mark steps, and check that they produce `new`.
+fn mark_steps<S: Schema>(
+    old: &S::Node,
+    new: &S::Node,
+    from: usize,
+    to: usize,
+) -> Option<Vec<Step<S>>> {
+    let spans_a = inline_spans(old, from, to);
+    let spans_b = inline_spans(new, from, to);
+    if spans_a.is_empty() || spans_b.is_empty() {
+        return None;
+    }
+
+    let mut bounds: Vec<usize> = (spans_a.iter().chain(&spans_b))
+        .flat_map(|&(start, end, _)| vec![start, end])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let marks_at = |spans: &[(usize, usize, MarkSet<S>)], pos: usize| {
+        spans
+            .iter()
+            .find(|(start, end, _)| *start <= pos && pos < *end)
+            .map(|(_, _, marks)| marks.clone())
+    };
+
+    let mut removed: Vec<(Span, S::Mark)> = Vec::new();
+    let mut added: Vec<(Span, S::Mark)> = Vec::new();
+    for pair in bounds.windows(2) {
+        let span = Span {
+            from: pair[0],
+            to: pair[1],
+        };
+        let (marks_a, marks_b) =
+            match (marks_at(&spans_a, span.from), marks_at(&spans_b, span.from)) {
+                (Some(marks_a), Some(marks_b)) => (marks_a, marks_b),
+                (None, None) => continue,
+                _ => return None,
+            };
+        for mark in &marks_a {
+            if !marks_b.contains(mark) {
+                extend_or_push(&mut removed, span, mark);
+            }
+        }
+        for mark in &marks_b {
+            if !marks_a.contains(mark) {
+                extend_or_push(&mut added, span, mark);
+            }
+        }
+    }
+
+    let steps: Vec<Step<S>> = (removed.into_iter())
+        .map(|(span, mark)| Step::RemoveMark(RemoveMarkStep { span, mark }))
+        .chain((added.into_iter()).map(|(span, mark)| Step::AddMark(AddMarkStep { span, mark })))
+        .collect();
+
+    let mut doc = old.clone();
+    for step in &steps {
+        doc = step.apply(&doc).ok()?;
+    }
+    if &doc == new {
+        Some(steps)
+    } else {
+        None
+    }
+}
+
+fn extend_or_push<M: PartialEq + Clone>(list: &mut Vec<(Span, M)>, span: Span, mark: &M) {
+    match list
+        .iter_mut()
+        .find(|(prev, prev_mark)| prev.to == span.from && prev_mark == mark)
+    {
+        Some((prev, _)) => prev.to = span.to,
+        None => list.push((span, mark.clone())),
+    }
+}
@@ -0,0 +1,214 @@
+/// Which side a position sticks to when content is inserted at it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Assoc {
+    /// Stay before content inserted at the position
+    Before,
+    /// Move after content inserted at the position
+    After,
+}
+
+/// The result of mapping a position through a [`StepMap`] or [`Mapping`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MapResult {
+    /// The mapped position
+    pub pos: usize,
+    /// Whether the content around the original position was deleted
+    pub deleted: bool,
+}
+
+/// A map describing the deletions and insertions made by a step, which can be used to find the
+/// correspondence between positions in the document before and after the step.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StepMap {
+    /// `(start, old_size, new_size)` for every changed range, sorted by `start`
+    ranges: Vec<(usize, usize, usize)>,
+}
+
+impl StepMap {
+    /// A map that leaves all positions unchanged
+    pub const EMPTY: Self = StepMap { ranges: Vec::new() };
+
+    /// Create a map from `(start, old_size, new_size)` triples, which must be sorted by `start`
+    /// and must not overlap.
+    pub fn new(ranges: Vec<(usize, usize, usize)>) -> Self {
+        let ranges = ranges
+            .into_iter()
+            .filter(|&(_, old_size, new_size)| old_size > 0 || new_size > 0)
+            .collect();
+        StepMap { ranges }
+    }
+
+    /// The changed ranges as `(start, old_size, new_size)`
+    pub fn ranges(&self) -> &[(usize, usize, usize)] {
+        &self.ranges
+    }
+
+    /// True if this map leaves all positions unchanged
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Create a map that undoes this one, i.e. maps positions in the new document back to the
+    /// old one.
+    pub fn invert(&self) -> StepMap {
+        let mut diff: isize = 0;
+        let ranges = (self.ranges.iter())
+            .map(|&(start, old_size, new_size)| {
+                let new_start = (start as isize + diff) as usize;
+                diff += new_size as isize - old_size as isize;
+                (new_start, new_size, old_size)
+            })
+            .collect();
+        StepMap { ranges }
+    }
+
+    /// The index of the range that `pos` lies strictly within, and its offset in that range
+    fn recover_info(&self, pos: usize) -> Option<(usize, usize)> {
+        (self.ranges.iter())
+            .position(|&(start, old_size, _)| start < pos && pos < start + old_size)
+            .map(|index| (index, pos - self.ranges[index].0))
+    }
+
+    /// The position at `offset` in the new content of the range at `index`
+    fn recover(&self, (index, offset): (usize, usize)) -> Option<usize> {
+        let diff: isize = (self.ranges[..index].iter())
+            .map(|&(_, old_size, new_size)| new_size as isize - old_size as isize)
+            .sum();
+        let &(start, _, new_size) = self.ranges.get(index)?;
+        if offset < new_size {
+            Some((start as isize + diff) as usize + offset)
+        } else {
+            None
+        }
+    }
+
+    /// Map a position through this map.
+    pub fn map(&self, pos: usize, assoc: Assoc) -> usize {
+        self.map_result(pos, assoc).pos
+    }
+
+    /// Map a position through this map, and report whether it was deleted.
+    pub fn map_result(&self, pos: usize, assoc: Assoc) -> MapResult {
+        let mut diff: isize = 0;
+        for &(start, old_size, new_size) in &self.ranges {
+            if start > pos {
+                break;
+            }
+            let end = start + old_size;
+            if pos <= end {
+                let side = if old_size == 0 {
+                    assoc
+                } else if pos == start {
+                    Assoc::Before
+                } else if pos == end {
+                    Assoc::After
+                } else {
+                    assoc
+                };
+                let offset = match side {
+                    Assoc::Before => 0,
+                    Assoc::After => new_size,
+                };
+                let deleted = match assoc {
+                    Assoc::Before => pos != start,
+                    Assoc::After => pos != end,
+                };
+                return MapResult {
+                    pos: (start as isize + diff) as usize + offset,
+                    deleted,
+                };
+            }
+            diff += new_size as isize - old_size as isize;
+        }
+        MapResult {
+            pos: (pos as isize + diff) as usize,
+            deleted: false,
+        }
+    }
+}
+
+/// A pipeline of step maps, to map positions through a series of steps
+///
+/// A map can be marked as the mirror of an earlier one, when it redoes the change that the
+/// earlier one undid. Positions inside content that was deleted by the earlier map are then
+/// recovered in the mirror, instead of being collapsed to the edges of the deletion.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mapping {
+    maps: Vec<StepMap>,
+    /// Pairs of indices of maps that mirror each other
+    mirror: Vec<(usize, usize)>,
+}
+
+impl Mapping {
+    /// Create an empty mapping
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The step maps in this mapping
+    pub fn maps(&self) -> &[StepMap] {
+        &self.maps
+    }
+
+    /// Add a step map to the end of this mapping.
+    pub fn append_map(&mut self, map: StepMap) {
+        self.maps.push(map);
+    }
+
+    /// Mark the maps at index `n` and `m` as mirrors of each other.
+    pub fn set_mirror(&mut self, n: usize, m: usize) {
+        self.mirror.push((n, m));
+    }
+
+    /// The index of the map that mirrors the one at `n`, if any
+    fn get_mirror(&self, n: usize) -> Option<usize> {
+        self.mirror.iter().find_map(|&(a, b)| match n {
+            _ if a == n => Some(b),
+            _ if b == n => Some(a),
+            _ => None,
+        })
+    }
+
+    /// A mapping with the maps from index `from` on, keeping the mirrors between them.
+    pub fn slice(&self, from: usize) -> Mapping {
+        let from = usize::min(from, self.maps.len());
+        Mapping {
+            maps: self.maps[from..].to_vec(),
+            mirror: (self.mirror.iter())
+                .filter(|&&(a, b)| a >= from && b >= from)
+                .map(|&(a, b)| (a - from, b - from))
+                .collect(),
+        }
+    }
+
+    /// Map a position through all the step maps in this mapping.
+    pub fn map(&self, pos: usize, assoc: Assoc) -> usize {
+        self.map_result(pos, assoc).pos
+    }
+
+    /// Map a position through all the step maps in this mapping, and report whether it was
+    /// deleted by any of them.
+    pub fn map_result(&self, pos: usize, assoc: Assoc) -> MapResult {
+        let mut result = MapResult {
+            pos,
+            deleted: false,
+        };
+        let mut i = 0;
+        while i < self.maps.len() {
+            let map = &self.maps[i];
+            let recovered = (self.get_mirror(i))
+                .filter(|&m| m > i)
+                .and_then(|m| Some((m, self.maps[m].recover(map.recover_info(result.pos)?)?)));
+            if let Some((m, pos)) = recovered {
+                result.pos = pos;
+                i = m + 1;
+                continue;
+            }
+            let step = map.map_result(result.pos, assoc);
+            result.pos = step.pos;
+            result.deleted |= step.deleted;
+            i += 1;
+        }
+        result
+    }
+}
@@ -1,4 +1,4 @@
-use super::{util::Span, StepKind};
+use super::{util::Span, Assoc, Mapping, StepKind, StepMap};
 use crate::model::{Fragment, Mark, MarkSet, Node, NodeType, Schema, Slice};
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
@@ -8,32 +8,33 @@ fn map_fragment_parent<S: Schema, F>(fragment: &Fragment<S>, f: &F, parent: &S::
 where
     F: Fn(S::Node, &S::Node, usize) -> S::Node,
 {
-    let mut mapped = vec![];
+    // Appending joins adjacent text nodes that end up with the same marks
+    let mut mapped = Fragment::new();
     for (i, child) in fragment.children().iter().enumerate() {
         let mut child = child.copy(|c| map_fragment_parent(c, f, &*child));
 
         if child.is_inline() {
             child = f(child, parent, i)
         }
-        mapped.push(child)
+        mapped = mapped.append(Fragment::from((child,)))
     }
-    Fragment::from(mapped)
+    mapped
 }
 
 fn map_fragment<S: Schema, F>(fragment: &Fragment<S>, f: &F) -> Fragment<S>
 where
     F: Fn(S::Node) -> S::Node,
 {
-    let mut mapped = vec![];
+    let mut mapped = Fragment::new();
     for child in fragment.children() {
         let mut child = child.copy(|c| map_fragment(c, f));
 
         if child.is_inline() {
             child = f(child)
         }
-        mapped.push(child)
+        mapped = mapped.append(Fragment::from((child,)))
     }
-    Fragment::from(mapped)
+    mapped
 }
 
 /// Adding a mark on some part of the document
@@ -60,6 +61,77 @@ pub struct RemoveMarkStep<S: Schema> {
     pub mark: S::Mark,
 }
 
+/// Map the span of a mark step, or `None` if it was deleted or is now empty
+fn map_span(span: &Span, mapping: &Mapping) -> Option<Span> {
+    let from = mapping.map_result(span.from, Assoc::After);
+    let to = mapping.map_result(span.to, Assoc::Before);
+    if (from.deleted && to.deleted) || from.pos >= to.pos {
+        None
+    } else {
+        Some(Span {
+            from: from.pos,
+            to: to.pos,
+        })
+    }
+}
+
+/// Join two spans of the same mark if they overlap or touch
+fn merge_span(a: &Span, b: &Span) -> Option<Span> {
+    if a.from <= b.to && a.to >= b.from {
+        Some(Span {
+            from: usize::min(a.from, b.from),
+            to: usize::max(a.to, b.to),
+        })
+    } else {
+        None
+    }
+}
+
+impl<S: Schema> AddMarkStep<S> {
+    /// Merge this step with `other` if they add the same mark to overlapping or adjacent spans
+    pub fn merge(&self, other: &AddMarkStep<S>) -> Option<Self> {
+        if self.mark != other.mark {
+            return None;
+        }
+        Some(AddMarkStep {
+            span: merge_span(&self.span, &other.span)?,
+            mark: self.mark.clone(),
+        })
+    }
+
+    /// Map this step through the changes in `mapping`, returning `None` if the marked range was
+    /// deleted.
+    pub fn map(&self, mapping: &Mapping) -> Option<Self> {
+        Some(AddMarkStep {
+            span: map_span(&self.span, mapping)?,
+            mark: self.mark.clone(),
+        })
+    }
+}
+
+impl<S: Schema> RemoveMarkStep<S> {
+    /// Merge this step with `other` if they remove the same mark from overlapping or adjacent
+    /// spans
+    pub fn merge(&self, other: &RemoveMarkStep<S>) -> Option<Self> {
+        if self.mark != other.mark {
+            return None;
+        }
+        Some(RemoveMarkStep {
+            span: merge_span(&self.span, &other.span)?,
+            mark: self.mark.clone(),
+        })
+    }
+
+    /// Map this step through the changes in `mapping`, returning `None` if the unmarked range
+    /// was deleted.
+    pub fn map(&self, mapping: &Mapping) -> Option<Self> {
+        Some(RemoveMarkStep {
+            span: map_span(&self.span, mapping)?,
+            mark: self.mark.clone(),
+        })
+    }
+}
+
 impl<S: Schema> StepKind<S> for AddMarkStep<S> {
     fn apply(&self, doc: &S::Node) -> super::StepResult<S> {
         let old_slice = doc.slice(self.span.from..self.span.to, false)?;
@@ -80,10 +152,14 @@ impl<S: Schema> StepKind<S> for AddMarkStep<S> {
         );
 
         let slice = Slice::new(new_content, old_slice.open_start, old_slice.open_end);
-        // TODO: Cow::Owned?
-        let new_node = doc.replace(self.span.from..self.span.to, &slice)?;
+        let new_node = doc.replace_cow(self.span.from..self.span.to, Cow::Owned(slice))?;
         Ok(new_node)
     }
+
+    fn get_map(&self) -> StepMap {
+        // Marks don't change the size of the content
+        StepMap::EMPTY
+    }
 }
 
 impl<S: Schema> StepKind<S> for RemoveMarkStep<S> {
@@ -96,7 +172,12 @@ impl<S: Schema> StepKind<S> for RemoveMarkStep<S> {
         });
 
         let slice = Slice::new(new_content, old_slice.open_start, old_slice.open_end);
-        let new_node = doc.replace(self.span.from..self.span.to, &slice)?;
+        let new_node = doc.replace_cow(self.span.from..self.span.to, Cow::Owned(slice))?;
         Ok(new_node)
     }
+
+    fn get_map(&self) -> StepMap {
+        // Marks don't change the size of the content
+        StepMap::EMPTY
+    }
 }
@@ -1,27 +1,44 @@
 //! # The document transformations
 //!
+mod commands;
+mod custom;
+mod diff;
+mod map;
 mod mark_step;
+mod rebase;
+mod replace;
 mod replace_step;
 mod step;
 mod util;
 
+pub use commands::{replace_selection, toggle_mark};
+pub use custom::{CustomStep, RegisterStepError, StepRegistry};
+pub use diff::diff;
+pub use map::{Assoc, MapResult, Mapping, StepMap};
 pub use mark_step::{AddMarkStep, RemoveMarkStep};
-pub use replace_step::{ReplaceAroundStep, ReplaceStep};
+pub use rebase::rebase_steps;
+pub use replace::{delete_range, insert_point, replace_range_with};
+pub use replace_step::{first_content_between, ReplaceAroundStep, ReplaceStep};
 pub use step::{StepError, StepKind, StepResult};
 pub use util::Span;
 
 use crate::model::Schema;
 use derivative::Derivative;
-use serde::{Deserialize, Serialize};
+use serde::de::{Deserialize, Deserializer, Error as _};
+use serde::ser::{Error as _, Serialize, Serializer};
+use serde_json::Value;
 
 /// A list of steps
 #[allow(type_alias_bounds)]
 pub type Steps<S: Schema> = Vec<Step<S>>;
 
 /// Steps that can be applied on a document
-#[derive(Derivative, Deserialize, Serialize)]
+///
+/// In JSON, the kind of step is given by the `stepType` field. Steps with a `stepType` other
+/// than the built-in ones deserialize to [`Step::Other`] if they are read through a
+/// [`StepRegistry`] in which that type is registered.
+#[derive(Derivative)]
 #[derivative(Debug(bound = ""), PartialEq(bound = ""), Eq(bound = ""))]
-#[serde(bound = "", tag = "stepType", rename_all = "camelCase")]
 pub enum Step<S: Schema> {
     /// Replace some content
     Replace(ReplaceStep<S>),
@@ -31,6 +48,8 @@ pub enum Step<S: Schema> {
     AddMark(AddMarkStep<S>),
     /// Remove a mark from a span
     RemoveMark(RemoveMarkStep<S>),
+    /// A custom step
+    Other(Box<dyn CustomStep<S>>),
 }
 
 impl<S: Schema> Step<S> {
@@ -41,30 +60,688 @@ impl<S: Schema> Step<S> {
             Self::ReplaceAround(ra_step) => ra_step.apply(doc),
             Self::AddMark(am_step) => am_step.apply(doc),
             Self::RemoveMark(rm_step) => rm_step.apply(doc),
+            Self::Other(step) => step.apply(doc),
+        }
+    }
+
+    /// Map this step through the changes in `mapping`, e.g. to apply it on top of other changes
+    /// to the document it was created for. Returns `None` if the step no longer applies, e.g.
+    /// because the content it changes was deleted.
+    pub fn map(&self, mapping: &Mapping) -> Option<Step<S>> {
+        match self {
+            Self::Replace(r_step) => r_step.map(mapping).map(Self::Replace),
+            Self::ReplaceAround(ra_step) => ra_step.map(mapping).map(Self::ReplaceAround),
+            Self::AddMark(am_step) => am_step.map(mapping).map(Self::AddMark),
+            Self::RemoveMark(rm_step) => rm_step.map(mapping).map(Self::RemoveMark),
+            Self::Other(step) => step.map(mapping).map(Self::Other),
+        }
+    }
+
+    /// Merge this step with `other`, which is applied right after it, into a single step.
+    ///
+    /// This works for adjacent text replacements such as typing or deleting characters one by
+    /// one, and for the same mark being added or removed on touching spans. Returns `None` if
+    /// the steps can't be merged.
+    pub fn merge(&self, other: &Step<S>) -> Option<Step<S>> {
+        match (self, other) {
+            (Self::Replace(a), Self::Replace(b)) => a.merge(b).map(Self::Replace),
+            (Self::AddMark(a), Self::AddMark(b)) => a.merge(b).map(Self::AddMark),
+            (Self::RemoveMark(a), Self::RemoveMark(b)) => a.merge(b).map(Self::RemoveMark),
+            _ => None,
+        }
+    }
+
+    /// Get the step map that represents the changes made by this step
+    pub fn get_map(&self) -> StepMap {
+        match self {
+            Self::Replace(r_step) => r_step.get_map(),
+            Self::ReplaceAround(ra_step) => ra_step.get_map(),
+            Self::AddMark(am_step) => am_step.get_map(),
+            Self::RemoveMark(rm_step) => rm_step.get_map(),
+            Self::Other(step) => step.get_map(),
         }
     }
 }
 
+impl<S: Schema> Serialize for Step<S> {
+    fn serialize<Sr>(&self, serializer: Sr) -> Result<Sr::Ok, Sr::Error>
+    where
+        Sr: Serializer,
+    {
+        let (step_type, value) = match self {
+            Self::Replace(step) => ("replace", serde_json::to_value(step)),
+            Self::ReplaceAround(step) => ("replaceAround", serde_json::to_value(step)),
+            Self::AddMark(step) => ("addMark", serde_json::to_value(step)),
+            Self::RemoveMark(step) => ("removeMark", serde_json::to_value(step)),
+            Self::Other(step) => (step.step_type(), step.to_json()),
+        };
+        match value.map_err(Sr::Error::custom)? {
+            Value::Object(mut map) => {
+                map.insert("stepType".to_owned(), Value::from(step_type));
+                map.serialize(serializer)
+            }
+            _ => Err(Sr::Error::custom("step did not serialize to an object")),
+        }
+    }
+}
+
+impl<S: Schema> Step<S> {
+    fn deserialize_with<'de, D>(
+        deserializer: D,
+        registry: Option<&StepRegistry<S>>,
+    ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        let step_type = match value.get("stepType") {
+            Some(Value::String(step_type)) => step_type.clone(),
+            Some(_) => return Err(D::Error::custom("`stepType` is not a string")),
+            None => return Err(D::Error::missing_field("stepType")),
+        };
+        let step = match step_type.as_str() {
+            "replace" => serde_json::from_value(value).map(Self::Replace),
+            "replaceAround" => serde_json::from_value(value).map(Self::ReplaceAround),
+            "addMark" => serde_json::from_value(value).map(Self::AddMark),
+            "removeMark" => serde_json::from_value(value).map(Self::RemoveMark),
+            other => match registry.and_then(|registry| registry.get(other)) {
+                Some(f) => f(value).map(Self::Other),
+                None => return Err(D::Error::custom(format!("unknown step type `{}`", other))),
+            },
+        };
+        step.map_err(D::Error::custom)
+    }
+}
+
+impl<'de, S: Schema> Deserialize<'de> for Step<S> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::deserialize_with(deserializer, None)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{AddMarkStep, ReplaceStep, Span, Step, StepKind};
+    use super::{
+        delete_range, diff, first_content_between, insert_point, rebase_steps, replace_range_with,
+        replace_selection, toggle_mark, AddMarkStep, Assoc, CustomStep, Mapping, RegisterStepError,
+        ReplaceAroundStep, ReplaceStep, Span, Step, StepError, StepKind, StepMap, StepRegistry,
+        StepResult,
+    };
     use crate::markdown::{
-        helper::{doc, node, p, strong},
-        MarkdownMark, MarkdownNode, MD,
+        helper::{blockquote, code_block, doc, em, li, node, p, strong, ul},
+        MarkdownMark, MarkdownNode, MarkdownNodeType, MD,
     };
-    use crate::model::{Fragment, Node, Slice};
+    use crate::model::{Fragment, Mark, Node, NodeRange, ReplaceError, Slice};
+    use serde::de::DeserializeSeed;
+    use serde::{Deserialize, Serialize};
+    use std::borrow::Cow;
 
     #[test]
     fn test_apply() {
         let d1 = doc(p("Hello World!"));
         let step1 = AddMarkStep::<MD> {
             span: Span { from: 1, to: 9 },
-            mark: MarkdownMark::Strong,
+            mark: MarkdownMark::strong(),
         };
         let d2 = step1.apply(&d1).unwrap();
         assert_eq!(d2, doc(p(vec![strong("Hello Wo"), node("rld!")])));
     }
 
+    #[test]
+    fn test_replace_text() {
+        let d1 = doc(vec![
+            p(vec![node("ab"), strong("c\u{1F60A}d"), node("ef")]),
+            blockquote(p("gh")),
+        ]);
+        let slices = [
+            Fragment::new(),
+            Fragment::from(node("X")),
+            Fragment::from(strong("X")),
+        ];
+        let size = d1.content_size();
+        for from in 0..=size {
+            for to in from..=size {
+                // Don't split the surrogate pair
+                if from == 5 || to == 5 {
+                    continue;
+                }
+                for content in &slices {
+                    let step = ReplaceStep::<MD> {
+                        span: Span { from, to },
+                        slice: Slice::new(content.clone(), 0, 0),
+                        structure: false,
+                    };
+                    assert_eq!(
+                        step.apply(&d1).ok(),
+                        d1.replace(from..to, &step.slice).ok(),
+                        "{}..{} with {:?}",
+                        from,
+                        to,
+                        content
+                    );
+                }
+            }
+        }
+
+        let typed = ReplaceStep::<MD> {
+            span: Span { from: 9, to: 9 },
+            slice: Slice::new(Fragment::from(node("!")), 0, 0),
+            structure: false,
+        };
+        assert_eq!(
+            typed.apply(&d1).ok(),
+            Some(doc(vec![
+                p(vec![node("ab"), strong("c\u{1F60A}d"), node("ef!")]),
+                blockquote(p("gh")),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_replace_step_between() {
+        let d1 = doc(vec![p("one"), p("two")]);
+        let slice = Slice::new(Fragment::from((MarkdownNode::text("X"),)), 0, 0);
+        let step = ReplaceStep::<MD>::between(&d1, 2, 3, slice).unwrap();
+        assert_eq!(step.span, Span { from: 2, to: 3 });
+        assert!(!step.structure);
+        assert_eq!(step.apply(&d1).unwrap(), doc(vec![p("oXe"), p("two")]));
+
+        let join = ReplaceStep::<MD>::between(&d1, 4, 6, Slice::default()).unwrap();
+        assert!(join.structure);
+        assert_eq!(join.apply(&d1).unwrap(), doc(p("onetwo")));
+
+        // A whole empty node is content, even though it contains no leaf nodes
+        let d2 = doc(vec![p("a"), p(Fragment::new()), p("b")]);
+        let join = ReplaceStep::<MD>::between(&d2, 2, 6, Slice::default()).unwrap();
+        assert!(!join.structure);
+        assert_eq!(join.apply(&d2).unwrap(), doc(p("ab")));
+    }
+
+    #[test]
+    fn test_replace_step_between_rejects() {
+        let d1 = doc(blockquote(p("one")));
+        let slice = Slice::new(Fragment::from((MarkdownNode::text("X"),)), 0, 0);
+        let result = ReplaceStep::<MD>::between(&d1, 1, 1, slice);
+        assert!(matches!(
+            result,
+            Err(StepError::Replace(ReplaceError::InvalidContent(
+                MarkdownNodeType::Blockquote
+            )))
+        ));
+    }
+
+    fn apply_all(doc: &MarkdownNode, steps: &[Step<MD>]) -> MarkdownNode {
+        steps
+            .iter()
+            .fold(doc.clone(), |doc, step| step.apply(&doc).unwrap())
+    }
+
+    #[test]
+    fn test_mapping() {
+        let d1 = doc(p("Hello World"));
+        let steps = vec![
+            Step::AddMark(AddMarkStep::<MD> {
+                span: Span { from: 1, to: 6 },
+                mark: MarkdownMark::strong(),
+            }),
+            Step::Replace(ReplaceStep::<MD> {
+                span: Span { from: 1, to: 1 },
+                slice: Slice::new(Fragment::from((node(">> "),)), 0, 0),
+                structure: false,
+            }),
+        ];
+        let d2 = apply_all(&d1, &steps);
+        assert_eq!(
+            d2,
+            doc(p(vec![node(">> "), strong("Hello"), node(" World")]))
+        );
+
+        assert!(steps[0].get_map().is_empty());
+        let mut mapping = Mapping::new();
+        for step in &steps {
+            mapping.append_map(step.get_map());
+        }
+        assert_eq!(mapping.maps().len(), 2);
+        // The position before "W" moves by the size of the inserted text
+        assert_eq!(mapping.map(7, Assoc::Before), 10);
+        assert_eq!(mapping.map(1, Assoc::Before), 1);
+        assert_eq!(mapping.map(1, Assoc::After), 4);
+        assert_eq!(mapping.map(13, Assoc::After), 16);
+
+        let delete = ReplaceStep::<MD> {
+            span: Span { from: 2, to: 5 },
+            slice: Slice::default(),
+            structure: false,
+        };
+        let result = delete.get_map().map_result(3, Assoc::After);
+        assert_eq!((result.pos, result.deleted), (2, true));
+        let result = delete.get_map().map_result(5, Assoc::After);
+        assert_eq!((result.pos, result.deleted), (2, false));
+        assert_eq!(delete.get_map().map(8, Assoc::Before), 5);
+    }
+
+    #[test]
+    fn test_diff() {
+        let check = |old: MarkdownNode, new: MarkdownNode| {
+            let steps = diff::<MD>(&old, &new);
+            assert_eq!(apply_all(&old, &steps), new);
+            steps
+        };
+
+        let same = doc(p("Hello"));
+        assert!(diff::<MD>(&same, &same).is_empty());
+
+        let steps = check(doc(p("Hello World")), doc(p("Hello big World")));
+        assert!(
+            matches!(&steps[..], [Step::Replace(s)] if s.span == Span { from: 7, to: 7 }),
+            "{:?}",
+            steps
+        );
+        check(doc(p("aa")), doc(p("aaa")));
+        check(doc(p("abc")), doc(p("ac")));
+        check(doc(p("a\u{1F600}b")), doc(p("a\u{1F601}b")));
+
+        check(doc(p("one")), doc(vec![p("one"), blockquote(p("two"))]));
+        check(doc(vec![p("a"), p("a")]), doc(p("a")));
+        check(doc(vec![p("one"), p("two")]), doc(ul(li(p("one two")))));
+
+        let steps = check(
+            doc(p("Hello World")),
+            doc(p(vec![node("Hello "), strong("World")])),
+        );
+        assert!(matches!(&steps[..], [Step::AddMark(_)]), "{:?}", steps);
+        let steps = check(
+            doc(p(vec![strong("Hello"), node(" World")])),
+            doc(p(vec![node("Hello "), em("World")])),
+        );
+        assert!(
+            matches!(&steps[..], [Step::RemoveMark(_), Step::AddMark(_)]),
+            "{:?}",
+            steps
+        );
+    }
+
+    #[test]
+    fn test_toggle_mark() {
+        let unmarked = doc(p("Hello World!"));
+        let steps = toggle_mark(&unmarked, 1, 6, MarkdownMark::strong());
+        assert!(matches!(&steps[..], [Step::AddMark(_)]));
+        let bold = apply_all(&unmarked, &steps);
+        assert_eq!(bold, doc(p(vec![strong("Hello"), node(" World!")])));
+
+        let steps = toggle_mark(&bold, 1, 6, MarkdownMark::strong());
+        assert!(matches!(&steps[..], [Step::RemoveMark(_)]));
+        assert_eq!(apply_all(&bold, &steps), unmarked);
+
+        let steps = toggle_mark(&bold, 3, 9, MarkdownMark::strong());
+        assert!(matches!(&steps[..], [Step::AddMark(_)]));
+        assert_eq!(
+            apply_all(&bold, &steps),
+            doc(p(vec![strong("Hello Wo"), node("rld!")]))
+        );
+
+        let steps = toggle_mark(&bold, 2, 4, MarkdownMark::em());
+        assert_eq!(
+            apply_all(&bold, &steps),
+            doc(p(vec![
+                strong("H"),
+                strong_em("el"),
+                strong("lo"),
+                node(" World!")
+            ]))
+        );
+
+        assert!(toggle_mark::<MD>(&bold, 3, 3, MarkdownMark::strong()).is_empty());
+
+        // Text in a code block can't be marked, so it doesn't keep the mark from being removed
+        let mixed = doc(vec![p(strong("Hello")), code_block("", "code")]);
+        let steps = toggle_mark(&mixed, 1, 12, MarkdownMark::strong());
+        assert!(matches!(&steps[..], [Step::RemoveMark(_)]));
+        assert_eq!(
+            apply_all(&mixed, &steps),
+            doc(vec![p("Hello"), code_block("", "code")])
+        );
+        assert!(toggle_mark::<MD>(&mixed, 8, 12, MarkdownMark::strong()).is_empty());
+    }
+
+    fn strong_em(text: &str) -> MarkdownNode {
+        let marks = MarkdownMark::strong().add_to_set(Cow::Owned(MarkdownMark::em().into_set()));
+        em(text).mark(marks.into_owned())
+    }
+
+    fn insert_text(pos: usize, text: &str) -> Step<MD> {
+        Step::Replace(ReplaceStep {
+            span: Span { from: pos, to: pos },
+            slice: Slice::new(Fragment::from(node(text)), 0, 0),
+            structure: false,
+        })
+    }
+
+    #[test]
+    fn test_invert_map() {
+        let step = ReplaceStep::<MD> {
+            span: Span { from: 2, to: 4 },
+            slice: Slice::new(Fragment::from(node("xyz")), 0, 0),
+            structure: false,
+        };
+        let map = step.get_map();
+        let inverted = map.invert();
+        assert_eq!(inverted.ranges(), [(2, 3, 2)]);
+        for pos in [0, 1, 2, 4, 6] {
+            assert_eq!(inverted.map(map.map(pos, Assoc::After), Assoc::After), pos);
+        }
+
+        // Positions in the deleted text are recovered by the mirror
+        let mut mapping = Mapping::new();
+        mapping.append_map(inverted);
+        mapping.append_map(StepMap::new(vec![(0, 0, 5)]));
+        mapping.append_map(StepMap::new(vec![(7, 2, 3)]));
+        assert_eq!(mapping.map(3, Assoc::Before), 7);
+        mapping.set_mirror(0, 2);
+        assert_eq!(mapping.map(3, Assoc::Before), 8);
+        assert_eq!(mapping.slice(0), mapping);
+        assert_eq!(mapping.slice(1).map(3, Assoc::Before), 7);
+    }
+
+    #[test]
+    fn test_rebase_steps() {
+        let d1 = doc(p("Hello"));
+
+        // Concurrent insertions at the same position: the remote one comes first
+        let remote = [insert_text(3, "A")];
+        let local = [insert_text(3, "XY"), insert_text(4, "Z")];
+        let (d2, rebased) = rebase_steps(&d1, &remote, &local).unwrap();
+        assert_eq!(d2, doc(p("HeAXZYllo")));
+        assert_eq!(rebased, [insert_text(4, "XY"), insert_text(5, "Z")]);
+        assert_eq!(apply_all(&apply_all(&d1, &remote), &rebased), d2);
+
+        // Insertions at different positions give the same document in either order
+        let a = [insert_text(2, "A")];
+        let b = [insert_text(4, "B"), insert_text(7, "C")];
+        let (d3, _) = rebase_steps(&d1, &a, &b).unwrap();
+        let (d4, _) = rebase_steps(&d1, &b, &a).unwrap();
+        assert_eq!(d3, doc(p("HAelBloC")));
+        assert_eq!(d3, d4);
+
+        // Steps in deleted content are dropped
+        let delete = [Step::Replace(ReplaceStep {
+            span: Span { from: 2, to: 5 },
+            slice: Slice::default(),
+            structure: false,
+        })];
+        let marked = Step::AddMark(AddMarkStep {
+            span: Span { from: 3, to: 4 },
+            mark: MarkdownMark::em(),
+        });
+        let (d5, rebased) = rebase_steps(
+            &d1,
+            &delete,
+            &[insert_text(3, "X"), marked, insert_text(6, "!")],
+        )
+        .unwrap();
+        assert_eq!(d5, doc(p("H!o")));
+        assert_eq!(rebased, [insert_text(2, "!")]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let d1 = doc(p("Hello"));
+        let delete = |from, to| {
+            Step::Replace(ReplaceStep::<MD> {
+                span: Span { from, to },
+                slice: Slice::default(),
+                structure: false,
+            })
+        };
+
+        // Typing two characters
+        let merged = insert_text(3, "a").merge(&insert_text(4, "b")).unwrap();
+        assert_eq!(merged, insert_text(3, "ab"));
+        let typed = apply_all(&d1, &[insert_text(3, "a"), insert_text(4, "b")]);
+        assert_eq!(merged.apply(&d1).unwrap(), typed);
+        assert_eq!(typed, doc(p("Heabllo")));
+
+        // Deleting backwards and forwards
+        assert_eq!(delete(4, 5).merge(&delete(3, 4)), Some(delete(3, 5)));
+        assert_eq!(delete(3, 4).merge(&delete(3, 4)), Some(delete(3, 5)));
+
+        // Steps that don't touch
+        assert_eq!(insert_text(3, "a").merge(&insert_text(2, "b")), None);
+        assert_eq!(insert_text(3, "a").merge(&insert_text(5, "b")), None);
+
+        let em = |from, to| {
+            Step::AddMark(AddMarkStep::<MD> {
+                span: Span { from, to },
+                mark: MarkdownMark::em(),
+            })
+        };
+        assert_eq!(em(1, 3).merge(&em(3, 5)), Some(em(1, 5)));
+        assert_eq!(em(1, 2).merge(&em(3, 5)), None);
+        assert_eq!(em(1, 3).merge(&insert_text(3, "a")), None);
+    }
+
+    #[test]
+    fn test_merge_replace_step() {
+        let insert = |pos, text| ReplaceStep::<MD> {
+            span: Span { from: pos, to: pos },
+            slice: Slice::new(Fragment::from(node(text)), 0, 0),
+            structure: false,
+        };
+        assert_eq!(insert(3, "a").merge(&insert(4, "b")), Some(insert(3, "ab")));
+
+        // Typing at the end of one paragraph and the start of the next
+        let d1 = doc(vec![p("Hello"), p("World")]);
+        let (first, second) = (insert(6, "a"), insert(9, "b"));
+        let d2 = second.apply(&first.apply(&d1).unwrap()).unwrap();
+        assert_eq!(d2, doc(vec![p("Helloa"), p("bWorld")]));
+        assert_eq!(first.merge(&second), None);
+
+        // Splitting a paragraph and typing at the start of the new one
+        let split = ReplaceStep::<MD> {
+            span: Span { from: 3, to: 3 },
+            slice: Slice::new(Fragment::from(vec![p(vec![]), p(vec![])]), 1, 1),
+            structure: false,
+        };
+        assert_eq!(
+            split.apply(&doc(p("Hello"))).unwrap(),
+            doc(vec![p("He"), p("llo")])
+        );
+        assert_eq!(split.merge(&insert(5, "a")), None);
+    }
+
+    #[test]
+    fn test_replace_selection() {
+        let d1 = doc(p("Hello World!"));
+        let text = |text: &str| Slice::new(Fragment::from(node(text)), 0, 0);
+
+        let (d2, cursor) = replace_selection::<MD>(&d1, 7, 12, text("there")).unwrap();
+        assert_eq!(d2, doc(p("Hello there!")));
+        assert_eq!(cursor, 12);
+
+        let (d3, cursor) = replace_selection::<MD>(&d1, 6, 6, text(",")).unwrap();
+        assert_eq!(d3, doc(p("Hello, World!")));
+        assert_eq!(cursor, 7);
+
+        let (d4, cursor) = replace_selection::<MD>(&d1, 1, 7, Slice::default()).unwrap();
+        assert_eq!(d4, doc(p("World!")));
+        assert_eq!(cursor, 1);
+
+        // The cursor ends up in the second paragraph, after "cd"
+        let open = Slice::new(Fragment::from(vec![p("ab"), p("cd")]), 1, 1);
+        let (d5, cursor) = replace_selection::<MD>(&doc(p("Hello")), 3, 3, open).unwrap();
+        assert_eq!(d5, doc(vec![p("Heab"), p("cdllo")]));
+        assert_eq!(cursor, 9);
+        assert_eq!(d5.resolve(cursor).unwrap().parent_offset, 2);
+
+        assert!(
+            replace_selection::<MD>(&d1, 1, 1, Slice::new(Fragment::from(p("x")), 0, 0)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_replace_range_with() {
+        let d1 = doc(p("Hello World!"));
+        let steps = replace_range_with(&d1, 6, 6, MarkdownNode::HorizontalRule).unwrap();
+        assert_eq!(
+            apply_all(&d1, &steps),
+            doc(vec![p("Hello"), MarkdownNode::HorizontalRule, p(" World!")])
+        );
+
+        let steps = replace_range_with(&d1, 1, 1, MarkdownNode::HorizontalRule).unwrap();
+        assert_eq!(
+            apply_all(&d1, &steps),
+            doc(vec![MarkdownNode::HorizontalRule, p("Hello World!")])
+        );
+        assert_eq!(
+            insert_point::<MD>(&d1, 13, MarkdownNodeType::HorizontalRule),
+            Some(14)
+        );
+
+        let d2 = doc(blockquote(p("one")));
+        let steps = replace_range_with(&d2, 4, 4, MarkdownNode::HorizontalRule).unwrap();
+        assert_eq!(
+            apply_all(&d2, &steps),
+            doc(blockquote(vec![
+                p("on"),
+                MarkdownNode::HorizontalRule,
+                p("e")
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let d1 = doc(ul(vec![li(p("one")), li(p("two"))]));
+        let steps = delete_range(&d1, 2, 7).unwrap();
+        assert_eq!(apply_all(&d1, &steps), doc(ul(li(p("two")))));
+
+        let steps = delete_range(&d1, 3, 5).unwrap();
+        assert_eq!(
+            apply_all(&d1, &steps),
+            doc(ul(vec![li(p("e")), li(p("two"))]))
+        );
+
+        let steps = delete_range(&d1, 3, 6).unwrap();
+        assert_eq!(
+            apply_all(&d1, &steps),
+            doc(ul(vec![li(p(vec![])), li(p("two"))]))
+        );
+
+        // From the start of one paragraph into the next one
+        let d2 = doc(vec![p("ab"), p("cd")]);
+        let steps = delete_range(&d2, 1, 5).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(apply_all(&d2, &steps), doc(p("cd")));
+
+        // Across two whole textblocks
+        let d3 = doc(vec![p("ab"), p("cd"), p("ef")]);
+        let steps = delete_range(&d3, 1, 7).unwrap();
+        assert_eq!(apply_all(&d3, &steps), doc(vec![p(vec![]), p("ef")]));
+
+        assert!(delete_range::<MD>(&d2, 1, 100).is_err());
+    }
+
+    #[test]
+    fn test_replace_around_step_wrap() {
+        let d1 = doc(vec![p("one"), p("two")]);
+        let range = NodeRange::new(d1.resolve(1).unwrap(), d1.resolve(4).unwrap(), 0);
+        let step = ReplaceAroundStep::<MD>::new_wrap(&range, &[blockquote(vec![])]);
+        assert_eq!(
+            step,
+            ReplaceAroundStep {
+                span: Span { from: 0, to: 5 },
+                gap_from: 0,
+                gap_to: 5,
+                slice: Slice::new(Fragment::from((blockquote(vec![]),)), 0, 0),
+                insert: 1,
+                structure: true,
+            }
+        );
+        assert_eq!(
+            step.apply(&d1).unwrap(),
+            doc(vec![blockquote(p("one")), p("two")])
+        );
+        assert_eq!(step.get_map().map(2, Assoc::Before), 3);
+        assert_eq!(step.get_map().map(7, Assoc::Before), 9);
+    }
+
+    #[test]
+    fn test_replace_around_step_lift() {
+        let d1 = doc(blockquote(p("one")));
+        let range = NodeRange::new(d1.resolve(2).unwrap(), d1.resolve(5).unwrap(), 1);
+        let step = ReplaceAroundStep::<MD>::new_lift(&range, 0);
+        assert_eq!(
+            step,
+            ReplaceAroundStep {
+                span: Span { from: 0, to: 7 },
+                gap_from: 1,
+                gap_to: 6,
+                slice: Slice::default(),
+                insert: 0,
+                structure: true,
+            }
+        );
+        assert_eq!(step.apply(&d1).unwrap(), doc(p("one")));
+        assert_eq!(step.get_map().map(2, Assoc::Before), 1);
+
+        let d2 = doc(blockquote(vec![p("one"), p("two")]));
+        let range = NodeRange::new(d2.resolve(7).unwrap(), d2.resolve(10).unwrap(), 1);
+        let step = ReplaceAroundStep::<MD>::new_lift(&range, 0);
+        assert_eq!(step.span, Span { from: 6, to: 12 });
+        assert_eq!((step.gap_from, step.gap_to, step.insert), (6, 11, 1));
+        assert_eq!(
+            step.apply(&d2).unwrap(),
+            doc(vec![blockquote(p("one")), p("two")])
+        );
+    }
+
+    #[test]
+    fn test_first_content_between() {
+        let d1 = doc(vec![p("a"), MarkdownNode::HorizontalRule, p("b")]);
+        assert_eq!(first_content_between::<MD>(&d1, 3, 4), Ok(Some(3)));
+        assert_eq!(first_content_between::<MD>(&d1, 2, 5), Ok(Some(3)));
+        assert_eq!(first_content_between::<MD>(&d1, 4, 5), Ok(None));
+        assert_eq!(first_content_between::<MD>(&d1, 4, 7), Ok(Some(5)));
+
+        let d2 = doc(vec![p("a"), p("b")]);
+        assert_eq!(first_content_between::<MD>(&d2, 2, 4), Ok(None));
+        assert_eq!(first_content_between::<MD>(&d2, 2, 5), Ok(Some(4)));
+
+        let step = ReplaceStep::<MD> {
+            span: Span { from: 2, to: 5 },
+            slice: Slice::default(),
+            structure: true,
+        };
+        assert!(matches!(step.apply(&d1), Err(StepError::WouldOverwrite)));
+    }
+
+    fn invert_roundtrip(d1: MarkdownNode, step: ReplaceStep<MD>) {
+        let d2 = step.apply(&d1).unwrap();
+        let inverted = step.invert(&d1).unwrap();
+        assert_eq!(inverted.apply(&d2).unwrap(), d1);
+    }
+
+    #[test]
+    fn test_replace_step_invert() {
+        let text = Slice::new(Fragment::from((MarkdownNode::text("XY"),)), 0, 0);
+        invert_roundtrip(
+            doc(p("Hello")),
+            ReplaceStep::between(&doc(p("Hello")), 2, 4, text).unwrap(),
+        );
+
+        let d1 = doc(vec![p("one"), p("two")]);
+        let join = ReplaceStep::between(&d1, 3, 7, Slice::default()).unwrap();
+        invert_roundtrip(d1.clone(), join);
+
+        let split = d1.slice(2..7, false).unwrap();
+        assert_eq!((split.open_start, split.open_end), (1, 1));
+        let d2 = doc(blockquote(p("abc")));
+        invert_roundtrip(d2.clone(), ReplaceStep::between(&d2, 3, 3, split).unwrap());
+    }
+
     #[test]
     fn test_deserialize() {
         let s1: Step<MD> = serde_json::from_str(
@@ -76,7 +753,7 @@ mod tests {
             s1,
             Step::AddMark(AddMarkStep {
                 span: Span { from: 61, to: 648 },
-                mark: MarkdownMark::Em,
+                mark: MarkdownMark::em(),
             })
         );
 
@@ -97,4 +774,122 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_deserialize_without_slice() {
+        let d1 = doc(vec![p("Hello"), p("World")]);
+        let s1: Step<MD> =
+            serde_json::from_str(r#"{"stepType":"replace","from":5,"to":9}"#).unwrap();
+        assert_eq!(
+            s1,
+            Step::Replace(ReplaceStep {
+                span: Span { from: 5, to: 9 },
+                slice: Slice::default(),
+                structure: false,
+            })
+        );
+        assert_eq!(s1.apply(&d1).unwrap(), doc(p("Hellorld")));
+
+        let d2 = doc(blockquote(p("abc")));
+        let s2: Step<MD> = serde_json::from_str(
+            r#"{"stepType":"replaceAround","from":0,"to":7,"gapFrom":1,"gapTo":6,"insert":0,"structure":true}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            s2,
+            Step::ReplaceAround(ReplaceAroundStep {
+                span: Span { from: 0, to: 7 },
+                gap_from: 1,
+                gap_to: 6,
+                slice: Slice::default(),
+                insert: 0,
+                structure: true,
+            })
+        );
+        assert_eq!(s2.apply(&d2).unwrap(), doc(p("abc")));
+
+        // A slice that is open deeper than its content is rejected, instead of producing a
+        // step whose map underflows
+        let json = r#"{"stepType":"replace","from":2,"to":2,"slice":{"content":[],"openStart":1,"openEnd":1}}"#;
+        assert!(serde_json::from_str::<Step<MD>>(json).is_err());
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct NoopStep {
+        note: String,
+    }
+
+    impl StepKind<MD> for NoopStep {
+        fn apply(&self, doc: &MarkdownNode) -> StepResult<MD> {
+            Ok(doc.clone())
+        }
+
+        fn get_map(&self) -> StepMap {
+            StepMap::EMPTY
+        }
+    }
+
+    impl CustomStep<MD> for NoopStep {
+        fn step_type(&self) -> &'static str {
+            "noop"
+        }
+
+        fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+            serde_json::to_value(self)
+        }
+    }
+
+    #[test]
+    fn test_custom_step() {
+        let json = r#"{"stepType":"noop","note":"hello"}"#;
+        assert!(serde_json::from_str::<Step<MD>>(json).is_err());
+
+        let mut registry = StepRegistry::<MD>::new();
+        registry.register::<NoopStep>("noop").unwrap();
+        assert_eq!(
+            registry.register::<NoopStep>("noop"),
+            Err(RegisterStepError::Duplicate("noop".to_owned()))
+        );
+        assert_eq!(
+            registry.register::<NoopStep>("replace"),
+            Err(RegisterStepError::BuiltIn("replace".to_owned()))
+        );
+
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let step = registry.deserialize(&mut deserializer).unwrap();
+        assert!(matches!(&step, Step::Other(s) if s.step_type() == "noop"));
+        // Without the registry, the step type is still unknown
+        assert!(serde_json::from_str::<Step<MD>>(json).is_err());
+        // Built-in steps deserialize through the registry, too
+        let replace = r#"{"stepType":"replace","from":1,"to":1}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(replace);
+        assert!(matches!(
+            registry.deserialize(&mut deserializer),
+            Ok(Step::Replace(_))
+        ));
+
+        let d1 = doc(p("Hello"));
+        assert_eq!(step.apply(&d1).unwrap(), d1);
+
+        let value = serde_json::to_value(&step).unwrap();
+        assert_eq!(
+            value,
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+        assert_eq!(registry.deserialize(value).unwrap(), step);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let step = Step::<MD>::AddMark(AddMarkStep {
+            span: Span { from: 1, to: 3 },
+            mark: MarkdownMark::strong(),
+        });
+        let value = serde_json::to_value(&step).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"stepType": "addMark", "from": 1, "to": 3, "mark": {"type": "strong"}})
+        );
+        assert_eq!(serde_json::from_value::<Step<MD>>(value).unwrap(), step);
+    }
 }
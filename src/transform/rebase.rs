@@ -0,0 +1,46 @@
+use super::{Mapping, Step, StepError, Steps};
+use crate::model::Schema;
+
+/// Rebase local steps, which haven't been confirmed yet, over the remote steps that were
+/// confirmed in the meantime.
+///
+/// Both lists of steps apply to `doc`. This applies the remote steps, then maps each local step
+/// through the changes since its original document and applies it. Local steps that no longer
+/// apply, e.g. because the remote steps deleted their content, are dropped.
+///
+/// Returns the new document and the rebased local steps, which apply to the document after
+/// the remote steps. Fails only if a remote step can't be applied.
+pub fn rebase_steps<S: Schema>(
+    doc: &S::Node,
+    remote: &[Step<S>],
+    local: &[Step<S>],
+) -> Result<(S::Node, Steps<S>), StepError<S>> {
+    // The local steps are undone first, in reverse order, so the local step at index `i` is
+    // undone by the map at index `local.len() - 1 - i`.
+    let mut mapping = Mapping::new();
+    for step in local.iter().rev() {
+        mapping.append_map(step.get_map().invert());
+    }
+
+    let mut doc = doc.clone();
+    for step in remote {
+        doc = step.apply(&doc)?;
+        mapping.append_map(step.get_map());
+    }
+
+    let mut rebased = Vec::with_capacity(local.len());
+    for (i, step) in local.iter().enumerate() {
+        let undo = local.len() - 1 - i;
+        let mapped = match step.map(&mapping.slice(undo + 1)) {
+            Some(mapped) => mapped,
+            None => continue,
+        };
+        if let Ok(new_doc) = mapped.apply(&doc) {
+            doc = new_doc;
+            mapping.append_map(mapped.get_map());
+            mapping.set_mirror(undo, mapping.maps().len() - 1);
+            rebased.push(mapped);
+        }
+    }
+    Ok((doc, rebased))
+}
@@ -0,0 +1,168 @@
+use super::{ReplaceStep, Step, StepError};
+use crate::model::{ContentMatch, Fragment, Node, NodeType, ResolvedPos, Schema, Slice};
+
+/// Try to find a point where a node of the given type can be inserted near `pos`, by searching
+/// up the node hierarchy when `pos` itself isn't a valid place but is at the start or end of a
+/// node.
+pub fn insert_point<S: Schema>(doc: &S::Node, pos: usize, r#type: S::NodeType) -> Option<usize> {
+    let rp = doc.resolve(pos).ok()?;
+    let index = rp.index(rp.depth);
+    if rp.parent().can_replace_with(index, index, r#type) {
+        return Some(pos);
+    }
+
+    if rp.parent_offset == 0 {
+        for d in (0..rp.depth).rev() {
+            let index = rp.index(d);
+            if rp.node(d).can_replace_with(index, index, r#type) {
+                return rp.before(d + 1);
+            }
+            if index > 0 {
+                return None;
+            }
+        }
+    }
+    if rp.parent_offset == rp.parent().content_size() {
+        for d in (0..rp.depth).rev() {
+            let index = rp.index_after(d);
+            if rp.node(d).can_replace_with(index, index, r#type) {
+                return rp.after(d + 1);
+            }
+            if index < rp.node(d).child_count() {
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Build a copy of the ancestors of `rp` between `depth` (exclusive) and `shared`
+/// (inclusive), without any content.
+fn empty_ancestors<S: Schema>(rp: &ResolvedPos<S>, depth: usize, shared: usize) -> S::Node {
+    let mut node = rp.node(shared).copy(|_| Fragment::new());
+    for d in (depth + 1..shared).rev() {
+        node = rp.node(d).copy(|_| Fragment::from((node,)));
+    }
+    node
+}
+
+/// Replace the range between `from` and `to` with the given node.
+///
+/// When `from` and `to` are the same and point into a non-empty parent where the node isn't
+/// allowed, this tries to move the insertion point to the start or end of the enclosing nodes
+/// first. If the node still doesn't fit, the ancestors around the range are split until a
+/// level is found where the node can be placed. Returns `None` if there is no such level.
+pub fn replace_range_with<S: Schema>(
+    doc: &S::Node,
+    mut from: usize,
+    mut to: usize,
+    node: S::Node,
+) -> Option<Vec<Step<S>>> {
+    if !node.is_inline() && from == to && doc.resolve(from).ok()?.parent().content_size() > 0 {
+        if let Some(point) = insert_point::<S>(doc, from, node.r#type()) {
+            from = point;
+            to = point;
+        }
+    }
+
+    let rp_from = doc.resolve(from).ok()?;
+    let rp_to = doc.resolve(to).ok()?;
+    let shared = rp_from.shared_depth(to);
+    for depth in (0..=shared).rev() {
+        let open = shared - depth;
+        let content = if open == 0 {
+            Fragment::from((node.clone(),))
+        } else {
+            Fragment::from(vec![
+                empty_ancestors(&rp_from, depth, shared),
+                node.clone(),
+                empty_ancestors(&rp_to, depth, shared),
+            ])
+        };
+        let slice = Slice::new(content, open, open);
+        if let Ok(step) = ReplaceStep::between(doc, from, to, slice) {
+            return Some(vec![Step::Replace(step)]);
+        }
+    }
+    None
+}
+
+/// The depths, from the inside out, of the nodes that are entirely covered by the range between
+/// `rp_from` and `rp_to`, apart from the node boundaries themselves.
+///
+/// A range from the start of one textblock to the end of the next one also covers their depth,
+/// so that both can be emptied together.
+fn covered_depths<S: Schema>(rp_from: &ResolvedPos<S>, rp_to: &ResolvedPos<S>) -> Vec<usize> {
+    let mut result = Vec::new();
+    let min_depth = usize::min(rp_from.depth, rp_to.depth);
+    for d in (0..=min_depth).rev() {
+        let start = rp_from.start(d);
+        if start + (rp_from.depth - d) < rp_from.pos || rp_to.end(d) > rp_to.pos + (rp_to.depth - d)
+        {
+            break;
+        }
+        if start == rp_to.start(d)
+            || (d == rp_from.depth
+                && d == rp_to.depth
+                && rp_from.parent().is_textblock()
+                && rp_to.parent().is_textblock()
+                && d > 0
+                && rp_to.start(d - 1) + 1 == start)
+        {
+            result.push(d);
+        }
+    }
+    result
+}
+
+/// Delete the content between `from` and `to`.
+///
+/// When the range covers the entire content of some nodes, the deletion is widened outward so
+/// that parent nodes which would be left empty (and for which being empty isn't valid) are
+/// removed as well. If the widened range can't be deleted, this falls back to deleting exactly
+/// the range between `from` and `to`.
+pub fn delete_range<S: Schema>(
+    doc: &S::Node,
+    from: usize,
+    to: usize,
+) -> Result<Vec<Step<S>>, StepError<S>> {
+    let delete = |from, to| {
+        ReplaceStep::between(doc, from, to, Slice::default()).map(|step| vec![Step::Replace(step)])
+    };
+    let rp_from = doc.resolve(from)?;
+    let rp_to = doc.resolve(to)?;
+
+    let covered = covered_depths(&rp_from, &rp_to);
+    for (i, &depth) in covered.iter().enumerate() {
+        let last = i == covered.len() - 1;
+        if (last && depth == 0) || rp_from.node(depth).r#type().content_match().valid_end() {
+            return delete(rp_from.start(depth), rp_to.end(depth)).or_else(|_| delete(from, to));
+        }
+        if depth > 0 {
+            let parent = rp_from.node(depth - 1);
+            let (index, index_after) = (rp_from.index(depth - 1), rp_to.index_after(depth - 1));
+            if last || matches!(parent.can_replace(index, index_after, None, ..), Ok(true)) {
+                let (before, after) = (rp_from.before(depth).unwrap(), rp_to.after(depth).unwrap());
+                return delete(before, after).or_else(|_| delete(from, to));
+            }
+        }
+    }
+
+    for d in 1..=usize::min(rp_from.depth, rp_to.depth) {
+        if from - rp_from.start(d) == rp_from.depth - d
+            && to > rp_from.end(d)
+            && rp_to.end(d) - to != rp_to.depth - d
+            && rp_from.start(d - 1) == rp_to.start(d - 1)
+            && matches!(
+                rp_from
+                    .node(d - 1)
+                    .can_replace(rp_from.index(d - 1), rp_to.index(d - 1), None, ..),
+                Ok(true)
+            )
+        {
+            return delete(rp_from.before(d).unwrap(), to).or_else(|_| delete(from, to));
+        }
+    }
+
+    delete(from, to)
+}
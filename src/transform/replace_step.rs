@@ -1,7 +1,10 @@
-use super::{Span, StepError, StepKind, StepResult};
-use crate::model::{Node, ResolveErr, Schema, Slice};
+use super::{Assoc, Mapping, Span, StepError, StepKind, StepMap, StepResult};
+use crate::model::{
+    util, Fragment, Node, NodeRange, ResolveErr, Schema, Slice, SliceError, Text, TextNode,
+};
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
 /// Replace some part of the document
 #[derive(Derivative, Deserialize, Serialize)]
@@ -19,17 +22,219 @@ pub struct ReplaceStep<S: Schema> {
     pub structure: bool,
 }
 
+impl<S: Schema> ReplaceStep<S> {
+    /// Create a step that replaces `from..to` in `doc` with the given slice.
+    ///
+    /// This checks that the slice fits by trying to apply the replacement to `doc`, and returns
+    /// the error if it doesn't. When the replaced range is non-empty but contains only node
+    /// boundaries, i.e. no leaf nodes and no whole empty nodes, the step is marked as a structural
+    /// change.
+    pub fn between(
+        doc: &S::Node,
+        from: usize,
+        to: usize,
+        slice: Slice<S>,
+    ) -> Result<Self, StepError<S>> {
+        doc.replace(from..to, &slice)?;
+        let structure = from < to
+            && !contains_leaf::<S>(&doc.slice(from..to, false)?.content)
+            && !content_between::<S>(doc, from, to)?;
+        Ok(ReplaceStep {
+            span: Span { from, to },
+            slice,
+            structure,
+        })
+    }
+
+    /// Create a step that undoes this one, given the document it was applied to.
+    ///
+    /// The inverted step replaces the inserted content with the content that this step removed
+    /// from `doc`.
+    pub fn invert(&self, doc: &S::Node) -> Result<Self, SliceError> {
+        let from = self.span.from;
+        let slice = doc.slice(from..self.span.to, false)?;
+        Ok(ReplaceStep {
+            span: Span {
+                from,
+                to: from + self.slice.size(),
+            },
+            slice,
+            structure: false,
+        })
+    }
+
+    /// Merge this step with `other`, which is applied after it, into a single step if they
+    /// touch, e.g. when typing or deleting characters one after the other.
+    pub fn merge(&self, other: &ReplaceStep<S>) -> Option<Self> {
+        if self.structure || other.structure {
+            return None;
+        }
+        let (first, second) = if self.span.from + self.slice.size() == other.span.from
+            && self.slice.open_end == 0
+            && other.slice.open_start == 0
+        {
+            (self, other)
+        } else if other.span.to == self.span.from
+            && self.slice.open_start == 0
+            && other.slice.open_end == 0
+        {
+            (other, self)
+        } else {
+            return None;
+        };
+        let slice = if self.slice.size() + other.slice.size() == 0 {
+            Slice::default()
+        } else {
+            Slice::new(
+                (first.slice.content.clone()).append(second.slice.content.clone()),
+                first.slice.open_start,
+                second.slice.open_end,
+            )
+        };
+        let span = if first.span.from == self.span.from {
+            Span {
+                from: self.span.from,
+                to: self.span.to + (other.span.to - other.span.from),
+            }
+        } else {
+            Span {
+                from: other.span.from,
+                to: self.span.to,
+            }
+        };
+        Some(ReplaceStep {
+            span,
+            slice,
+            structure: false,
+        })
+    }
+
+    /// Map this step through the changes in `mapping`, returning `None` if the replaced range
+    /// was deleted entirely.
+    pub fn map(&self, mapping: &Mapping) -> Option<Self> {
+        let from = mapping.map_result(self.span.from, Assoc::After);
+        let to = mapping.map_result(self.span.to, Assoc::Before);
+        if from.deleted && to.deleted {
+            return None;
+        }
+        Some(ReplaceStep {
+            span: Span {
+                from: from.pos,
+                to: usize::max(from.pos, to.pos),
+            },
+            slice: self.slice.clone(),
+            structure: self.structure,
+        })
+    }
+}
+
 impl<S: Schema> StepKind<S> for ReplaceStep<S> {
     fn apply(&self, doc: &S::Node) -> StepResult<S> {
         let from = self.span.from;
         let to = self.span.to;
         if self.structure && content_between::<S>(doc, from, to)? {
             Err(StepError::WouldOverwrite)
+        } else if let Some(node) = replace_text(doc, from, to, &self.slice) {
+            Ok(node)
         } else {
             let node = doc.replace(from..to, &self.slice)?;
             Ok(node)
         }
     }
+
+    fn get_map(&self) -> StepMap {
+        StepMap::new(vec![(
+            self.span.from,
+            self.span.to - self.span.from,
+            self.slice.size(),
+        )])
+    }
+}
+
+/// Replace `from..to` when it lies within a single text node and the slice is closed, plain text
+/// with the same marks, by rebuilding just that text node and its ancestors. This is the common
+/// case while typing.
+///
+/// Returns `None` if that's not the case, or if the result could differ from the general
+/// replace, e.g. because the text would have to be merged with a neighbour or removed.
+fn replace_text<S: Schema>(
+    doc: &S::Node,
+    from: usize,
+    to: usize,
+    slice: &Slice<S>,
+) -> Option<S::Node> {
+    if from > to || slice.open_start > 0 || slice.open_end > 0 || slice.content.child_count() > 1 {
+        return None;
+    }
+    let inserted = match slice.content.maybe_child(0) {
+        Some(node) => Some(node.text_node()?),
+        None => None,
+    };
+    let rp = doc.resolve(from).ok()?;
+    let parent = rp.parent();
+    let content = parent.content()?;
+
+    // The text node and the offset of `from` in it
+    let fits = |index: usize, offset: usize| {
+        let node = content.maybe_child(index)?.text_node()?;
+        let len = node.text.len_utf16();
+        if offset + (to - from) > len {
+            return None;
+        }
+        let same_marks = !matches!(inserted, Some(ins) if ins.marks != node.marks);
+        let remaining = len - (to - from) + inserted.map_or(0, |ins| ins.text.len_utf16());
+        if same_marks && remaining > 0 {
+            Some((index, offset, node))
+        } else {
+            None
+        }
+    };
+    let index = rp.index(rp.depth);
+    let offset = rp.text_offset();
+    let (index, offset, node) = if offset > 0 {
+        fits(index, offset)?
+    } else {
+        let before = index.checked_sub(1).and_then(|i| {
+            let len = content.child(i).text_node()?.text.len_utf16();
+            fits(i, len)
+        });
+        before.or_else(|| fits(index, 0))?
+    };
+
+    // Unmerged neighbours with the same marks would be joined by the general replace
+    let mergeable = |i: usize| {
+        matches!(
+            content.maybe_child(i).and_then(Node::text_node),
+            Some(other) if other.marks == node.marks
+        )
+    };
+    if (index > 0 && mergeable(index - 1)) || mergeable(index + 1) {
+        return None;
+    }
+
+    let (left, rest) = util::try_split_at_utf16(node.text.as_str(), offset)?;
+    let (_, right) = util::try_split_at_utf16(rest, to - from)?;
+    let middle = inserted.map_or("", |ins| ins.text.as_str());
+    let mut text = String::with_capacity(left.len() + middle.len() + right.len());
+    text.push_str(left);
+    text.push_str(middle);
+    text.push_str(right);
+
+    let mut result = S::Node::new_text_node(TextNode {
+        marks: node.marks.clone(),
+        text: Text::from(text),
+    });
+    for depth in (0..=rp.depth).rev() {
+        let i = if depth == rp.depth {
+            index
+        } else {
+            rp.index(depth)
+        };
+        result = rp
+            .node(depth)
+            .copy(|c| c.replace_child(i, result).into_owned());
+    }
+    Some(result)
 }
 
 /// Replace the document structure while keeping some content
@@ -47,13 +252,122 @@ pub struct ReplaceAroundStep<S: Schema> {
     /// The inner slice
     #[serde(default)]
     pub slice: Slice<S>,
-    /// ???
+    /// The position in the slice where the content of the gap is inserted
     pub insert: usize,
     /// Whether this is a structural change
     #[serde(default)]
     pub structure: bool,
 }
 
+impl<S: Schema> ReplaceAroundStep<S> {
+    /// Create a step that wraps the content of `range` in the given nodes.
+    ///
+    /// The `wrappers` are given from the outside in, and their content is ignored: the first
+    /// node wraps the second, and so on, while the innermost one wraps the range.
+    pub fn new_wrap(range: &NodeRange<S>, wrappers: &[S::Node]) -> Self {
+        let mut content = Fragment::new();
+        for wrapper in wrappers.iter().rev() {
+            content = Fragment::from((wrapper.copy(|_| content),));
+        }
+        let (start, end) = (range.start(), range.end());
+        ReplaceAroundStep {
+            span: Span {
+                from: start,
+                to: end,
+            },
+            gap_from: start,
+            gap_to: end,
+            slice: Slice::new(content, 0, 0),
+            insert: wrappers.len(),
+            structure: true,
+        }
+    }
+
+    /// Create a step that lifts the content of `range` out of its parents, up to the node at
+    /// depth `target`.
+    ///
+    /// Parent nodes that have content before or after the range are split instead of removed.
+    pub fn new_lift(range: &NodeRange<S>, target: usize) -> Self {
+        let (rp_from, rp_to, depth) = (&range.from, &range.to, range.depth);
+        let gap_from = range.start();
+        let gap_to = range.end();
+
+        let mut start = gap_from;
+        let mut before = Fragment::new();
+        let mut open_start = 0;
+        let mut splitting = false;
+        for d in (target + 1..=depth).rev() {
+            if splitting || rp_from.index(d) > 0 {
+                splitting = true;
+                before = Fragment::from((rp_from.node(d).copy(|_| before),));
+                open_start += 1;
+            } else {
+                start -= 1;
+            }
+        }
+
+        let mut end = gap_to;
+        let mut after = Fragment::new();
+        let mut open_end = 0;
+        let mut splitting = false;
+        for d in (target + 1..=depth).rev() {
+            if splitting || rp_to.after(d + 1).unwrap() < rp_to.end(d) {
+                splitting = true;
+                after = Fragment::from((rp_to.node(d).copy(|_| after),));
+                open_end += 1;
+            } else {
+                end += 1;
+            }
+        }
+
+        let insert = before.size() - open_start;
+        ReplaceAroundStep {
+            span: Span {
+                from: start,
+                to: end,
+            },
+            gap_from,
+            gap_to,
+            slice: Slice::new(before.append(after), open_start, open_end),
+            insert,
+            structure: true,
+        }
+    }
+}
+
+impl<S: Schema> ReplaceAroundStep<S> {
+    /// Map this step through the changes in `mapping`, returning `None` if the replaced range
+    /// was deleted entirely, or the gap is no longer inside of it.
+    pub fn map(&self, mapping: &Mapping) -> Option<Self> {
+        let from = mapping.map_result(self.span.from, Assoc::After);
+        let to = mapping.map_result(self.span.to, Assoc::Before);
+        let gap_from = if self.span.from == self.gap_from {
+            from.pos
+        } else {
+            mapping.map(self.gap_from, Assoc::Before)
+        };
+        let gap_to = if self.span.to == self.gap_to {
+            to.pos
+        } else {
+            mapping.map(self.gap_to, Assoc::After)
+        };
+        if (from.deleted && to.deleted) || gap_from < from.pos || gap_to > to.pos {
+            return None;
+        }
+        Some(ReplaceAroundStep {
+            span: Span {
+                from: from.pos,
+                to: to.pos,
+            },
+            gap_from,
+            gap_to,
+            slice: self.slice.clone(),
+            insert: self.insert,
+            structure: self.structure,
+        })
+    }
+}
+
 impl<S: Schema> StepKind<S> for ReplaceAroundStep<S> {
     fn apply(&self, doc: &S::Node) -> StepResult<S> {
         if self.structure
@@ -71,12 +385,44 @@ impl<S: Schema> StepKind<S> for ReplaceAroundStep<S> {
         let inserted = self.slice.insert_at(self.insert, gap.content)?;
         let inserted = inserted.ok_or(StepError::GapNotFit)?;
 
-        let result = doc.replace(self.span.from..self.span.to, &inserted)?;
+        let result = doc.replace_cow(self.span.from..self.span.to, Cow::Owned(inserted))?;
         Ok(result)
     }
+
+    fn get_map(&self) -> StepMap {
+        StepMap::new(vec![
+            (self.span.from, self.gap_from - self.span.from, self.insert),
+            (
+                self.gap_to,
+                self.span.to - self.gap_to,
+                self.slice.size() - self.insert,
+            ),
+        ])
+    }
+}
+
+fn contains_leaf<S: Schema>(fragment: &Fragment<S>) -> bool {
+    fragment.children().iter().any(|c| match c.content() {
+        Some(content) => contains_leaf::<S>(content),
+        None => true,
+    })
 }
 
 fn content_between<S: Schema>(doc: &S::Node, from: usize, to: usize) -> Result<bool, ResolveErr> {
+    Ok(first_content_between::<S>(doc, from, to)?.is_some())
+}
+
+/// Find the position of the first content between `from` and `to`, if any.
+///
+/// This is the check that makes structural steps fail with
+/// [`StepError::WouldOverwrite`](super::StepError::WouldOverwrite): the returned position is where
+/// the first leaf node (or the end of an ancestor) inside the range starts, i.e. where the step
+/// would overwrite content.
+pub fn first_content_between<S: Schema>(
+    doc: &S::Node,
+    from: usize,
+    to: usize,
+) -> Result<Option<usize>, ResolveErr> {
     let rp_from = doc.resolve(from)?;
     let mut dist = to - from;
     let mut depth = rp_from.depth;
@@ -85,22 +431,21 @@ fn content_between<S: Schema>(doc: &S::Node, from: usize, to: usize) -> Result<b
         dist -= 1;
     }
     if dist > 0 {
-        let mut next = rp_from.node(depth).maybe_child(rp_from.index_after(depth));
+        let index = rp_from.index_after(depth);
+        let mut next = rp_from.node(depth).maybe_child(index);
+        let mut pos = rp_from.pos_at_index(index, depth);
         while dist > 0 {
             match next {
-                Some(c) => {
-                    if c.is_leaf() {
-                        return Ok(true);
-                    } else {
-                        next = c.first_child();
-                        dist -= 1;
-                    }
+                Some(c) if !c.is_leaf() => {
+                    next = c.first_child();
+                    dist -= 1;
+                    pos += 1;
                 }
-                None => {
-                    return Ok(true);
+                _ => {
+                    return Ok(Some(pos));
                 }
             }
         }
     }
-    Ok(false)
+    Ok(None)
 }
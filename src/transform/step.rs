@@ -1,3 +1,4 @@
+use super::StepMap;
 use crate::model::{InsertError, ReplaceError, ResolveErr, Schema, SliceError};
 use derivative::Derivative;
 use displaydoc::Display;
@@ -40,4 +41,10 @@ pub trait StepKind<S: Schema> {
     /// applied to this document, or indicates success by containing a
     /// transformed document.
     fn apply(&self, doc: &S::Node) -> StepResult<S>;
+
+    /// Get the step map that represents the changes made by this step, which can be used to
+    /// map positions in the old document to positions in the new one.
+    ///
+    /// Steps that don't change the positions in the document return [`StepMap::EMPTY`].
+    fn get_map(&self) -> StepMap;
 }
@@ -1,6 +1,9 @@
 //! # Generic utilities
 
+use std::borrow::Cow;
+
 /// A type the holds a value of A or B or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EitherOrBoth<A, B> {
     /// Both values
     Both(A, B),
@@ -28,6 +31,32 @@ impl<A, B> EitherOrBoth<A, B> {
             Self::Right(b) => Some(b),
         }
     }
+
+    /// Get both values if both are present
+    pub fn both(&self) -> Option<(&A, &B)> {
+        match self {
+            Self::Both(a, b) => Some((a, b)),
+            Self::Left(_) | Self::Right(_) => None,
+        }
+    }
+
+    /// Apply a function to the left value, if present
+    pub fn map_left<C, F: FnOnce(A) -> C>(self, f: F) -> EitherOrBoth<C, B> {
+        match self {
+            Self::Both(a, b) => EitherOrBoth::Both(f(a), b),
+            Self::Left(a) => EitherOrBoth::Left(f(a)),
+            Self::Right(b) => EitherOrBoth::Right(b),
+        }
+    }
+
+    /// Apply a function to the right value, if present
+    pub fn map_right<C, F: FnOnce(B) -> C>(self, f: F) -> EitherOrBoth<A, C> {
+        match self {
+            Self::Both(a, b) => EitherOrBoth::Both(a, f(b)),
+            Self::Left(a) => EitherOrBoth::Left(a),
+            Self::Right(b) => EitherOrBoth::Right(f(b)),
+        }
+    }
 }
 
 impl<T> EitherOrBoth<T, T> {
@@ -48,3 +77,75 @@ pub(crate) fn then_some<T>(b: bool, v: T) -> Option<T> {
         None
     }
 }
+
+/// Escape `&`, `<`, `>` and quotes for use in HTML text and attribute values.
+///
+/// The input is taken as plain text, so already escaped entities are escaped again.
+#[cfg_attr(not(feature = "to_html"), allow(dead_code))]
+pub(crate) fn escape_html(text: &str) -> Cow<'_, str> {
+    let needs_escape = |c| matches!(c, '&' | '<' | '>' | '"' | '\'');
+    let first = match text.find(needs_escape) {
+        Some(index) => index,
+        None => return Cow::Borrowed(text),
+    };
+    let mut buf = String::with_capacity(text.len() + 16);
+    buf.push_str(&text[..first]);
+    for c in text[first..].chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '"' => buf.push_str("&quot;"),
+            '\'' => buf.push_str("&#39;"),
+            c => buf.push(c),
+        }
+    }
+    Cow::Owned(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_html, EitherOrBoth};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_either_or_both() {
+        let both: EitherOrBoth<u8, &str> = EitherOrBoth::Both(1, "b");
+        let left: EitherOrBoth<u8, &str> = EitherOrBoth::Left(1);
+        let right: EitherOrBoth<u8, &str> = EitherOrBoth::Right("b");
+
+        assert_eq!(both.left(), Some(&1));
+        assert_eq!(right.left(), None);
+        assert_eq!(both.right(), Some(&"b"));
+        assert_eq!(left.right(), None);
+
+        assert_eq!(both.both(), Some((&1, &"b")));
+        assert_eq!(left.both(), None);
+        assert_eq!(right.both(), None);
+
+        assert_eq!(both.map_left(|a| a + 1), EitherOrBoth::Both(2, "b"));
+        assert_eq!(left.map_left(|a| a + 1), EitherOrBoth::Left(2));
+        assert_eq!(right.map_left(|a| a + 1), EitherOrBoth::Right("b"));
+
+        assert_eq!(both.map_right(str::len), EitherOrBoth::Both(1, 1));
+        assert_eq!(left.map_right(str::len), EitherOrBoth::Left(1));
+        assert_eq!(right.map_right(str::len), EitherOrBoth::Right(1));
+
+        assert_eq!(EitherOrBoth::Both(1, 2).right_or_left(), &2);
+        assert_eq!(EitherOrBoth::<_, u8>::Left(1).right_or_left(), &1);
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("\"<script>&'\""),
+            "&quot;&lt;script&gt;&amp;&#39;&quot;"
+        );
+        assert!(matches!(
+            escape_html("plain text"),
+            Cow::Borrowed("plain text")
+        ));
+        assert_eq!(escape_html("a &amp; b"), "a &amp;amp; b");
+        assert_eq!(escape_html(&escape_html("<")), "&amp;lt;");
+    }
+}